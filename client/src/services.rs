@@ -5,7 +5,7 @@ use yew::platform::time::sleep;
 use yew::Callback;
 use common::network::{Connection, Packet, Request, Response};
 use common::network::Request::Login;
-use common::User;
+use common::{User, WagerFilter};
 
 
 pub fn query_wager_info(data_callback: Callback<Vec<common::Wager>>) {
@@ -14,8 +14,8 @@ pub fn query_wager_info(data_callback: Callback<Vec<common::Wager>>) {
         let mut connection = common::network::Connection::connect("127.0.0.1:6379").await.unwrap();
         let _user = login(&mut connection).await.unwrap();
         loop {
-            connection.send(Packet::RequestPacket(Request::WagerData)).await.unwrap();
-            let response = connection.read().await.unwrap();
+            connection.send(Packet::RequestPacket(Request::WagerData(WagerFilter::default()))).await.unwrap();
+            let response = read_response(&mut connection).await.unwrap();
             if let Packet::ResponsePacket(Response::WagerData(wager_data)) = response {
                 data_callback.emit(wager_data)
             }
@@ -26,7 +26,7 @@ pub fn query_wager_info(data_callback: Callback<Vec<common::Wager>>) {
 
 async fn login(connection: &mut Connection) -> anyhow::Result<User> {
     connection.send(Packet::RequestPacket(Login { user: "aidan".into() })).await.unwrap();
-    let response = connection.read().await?;
+    let response = read_response(connection).await?;
     if let Packet::ResponsePacket(Response::SuccessfulLogin {username, balance}) = response {
         Ok(User {
             name: username,
@@ -37,6 +37,16 @@ async fn login(connection: &mut Connection) -> anyhow::Result<User> {
     }
 }
 
+// notifications can arrive at any time, so skip past them to the response we are waiting for
+async fn read_response(connection: &mut Connection) -> anyhow::Result<Packet> {
+    loop {
+        let packet = connection.read().await?;
+        if !matches!(packet, Packet::NotificationPacket(_)) {
+            return Ok(packet);
+        }
+    }
+}
+
 pub fn generate_new_num(num_callback: Callback<i32>) {
     spawn_local(async move {
         let mut val = 1;
@@ -12,16 +12,398 @@ pub struct Wager {
     pub id: String,
     pub name: String,
     pub description: String,
+    // a broad grouping, like "match winner" or "player props"
+    pub category: Option<String>,
+    // the game or title the wager is about
+    pub game: Option<String>,
+    // always lowercase
+    pub tags: Vec<String>,
     pub pot: u64,
+    pub status: WagerStatus,
+    // unix timestamp (seconds) at which betting automatically closes
+    pub closes_at: Option<u64>,
+    pub resolution: Option<Resolution>,
+    pub rake: Rake,
+    pub kind: WagerKind,
+    pub no_winner_policy: NoWinnerPolicy,
+    // limits for this wager only. Anything left unset falls back to the server wide limits
+    pub stake_limits: StakeLimits,
+    pub electorate: Electorate,
+    // everyone who can vote has, without enough of them agreeing. An admin has to resolve it
+    pub vote_deadlocked: bool,
     pub options: Vec<WagerOption>,
 }
 
+// Narrows down the wagers sent back for a `WagerData` request. Anything left unset matches every wager,
+// and a wager has to have every one of the tags to match
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct WagerFilter {
+    pub category: Option<String>,
+    pub game: Option<String>,
+    pub tags: Vec<String>,
+}
+
+// Tags are matched without caring about case or surrounding whitespace, so they are kept lowercase and
+// trimmed, without blanks or duplicates
+pub fn normalise_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalised: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalised.contains(&tag) {
+            normalised.push(tag);
+        }
+    }
+    normalised
+}
+
+// Categories and games are matched the same way as tags, so they are kept lowercase and trimmed too. One
+// that is left blank is treated as not set
+pub fn normalise_label(label: Option<String>) -> Option<String> {
+    label
+        .map(|label| label.trim().to_lowercase())
+        .filter(|label| !label.is_empty())
+}
+
+// Limits on how much can be staked. A limit that is not set does not apply
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct StakeLimits {
+    pub min_stake: Option<u64>,
+    pub max_stake: Option<u64>,
+    // the most one user may have staked on a single wager, across all of its options
+    pub max_exposure: Option<u64>,
+}
+
+impl StakeLimits {
+    // These limits, with anything unset taken from `defaults`
+    pub fn or(self, defaults: StakeLimits) -> StakeLimits {
+        StakeLimits {
+            min_stake: self.min_stake.or(defaults.min_stake),
+            max_stake: self.max_stake.or(defaults.max_stake),
+            max_exposure: self.max_exposure.or(defaults.max_exposure),
+        }
+    }
+
+    // Checks a bet of `stake` by a user who already has `exposure` staked on the same wager
+    pub fn check(&self, stake: u64, exposure: u64) -> Result<(), StakeLimit> {
+        if let Some(min_stake) = self.min_stake.filter(|&min_stake| stake < min_stake) {
+            return Err(StakeLimit::MinStake(min_stake));
+        }
+        if let Some(max_stake) = self.max_stake.filter(|&max_stake| stake > max_stake) {
+            return Err(StakeLimit::MaxStake(max_stake));
+        }
+        if let Some(max_exposure) = self.max_exposure.filter(|&max_exposure| exposure.saturating_add(stake) > max_exposure) {
+            return Err(StakeLimit::MaxExposure(max_exposure));
+        }
+        Ok(())
+    }
+}
+
+// A stake limit that a bet broke, along with the limit's value
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StakeLimit {
+    MinStake(u64),
+    MaxStake(u64),
+    MaxExposure(u64),
+}
+
+// What happens to a pari-mutuel wager's money when nobody backed any of the winning options
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum NoWinnerPolicy {
+    // every bet gets its stake back. The pot is not paid out
+    #[default]
+    Refund,
+    // the stakes and the pot are added to the pot of another, still unresolved, wager
+    Rollover { wager_id: String },
+    // the stakes and the pot go to the house
+    House,
+}
+
+// Who may vote on how a wager turned out, so that it can be resolved without an admin watching
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum Electorate {
+    // only an admin resolves the wager
+    #[default]
+    Nobody,
+    // everyone with a bet on the wager
+    Bettors,
+    // the users playing in the match
+    Participants { user_ids: Vec<String> },
+}
+
+// How the winners of a wager are paid
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum WagerKind {
+    // winners split everything staked on the wager, in proportion to their stakes
+    #[default]
+    PariMutuel,
+    // every option has decimal odds, and each bet is paid what those odds were when it was placed.
+    // The house takes the other side of every bet
+    FixedOdds,
+}
+
+// The house's cut of a wager's losing pool, taken before winners are paid
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Rake {
+    // in basis points, so 250 is a 2.5% rake
+    pub basis_points: u32,
+    pub destination: RakeDestination,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum RakeDestination {
+    #[default]
+    House,
+    Jackpot,
+}
+
+// The recorded outcome of a resolved wager
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Resolution {
+    // more than one option wins on a draw or a dead heat
+    pub winners: Vec<Winner>,
+    // set when nobody backed a winner, to the policy that decided where the money went instead
+    pub no_winner_policy: Option<NoWinnerPolicy>,
+    // the number a numeric wager was resolved with
+    pub outcome: Option<i64>,
+    // unix timestamp (seconds)
+    pub resolved_at: u64,
+    // set until the resolution is final, to when its dispute window closes. Nothing is paid out before
+    pub pending_until: Option<u64>,
+    // a disputed resolution waits for an admin to confirm it, however long ago the window closed
+    pub disputed: bool,
+}
+
+// One of the winning options of a wager. The weight is its share of the winnings relative to the other
+// winners, and counts as 1 when left out, so tied winners split evenly by default
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Winner {
+    pub option_id: String,
+    pub weight: Option<u32>,
+}
+
+impl Winner {
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(1)
+    }
+}
+
+// A single bet slip combining selections from several wagers, which only pays if every leg wins. The
+// payout is the stake multiplied by the multiplier of each winning leg
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Parlay {
+    pub id: String,
+    pub user_id: String,
+    pub stake: u64,
+    pub legs: Vec<ParlayLeg>,
+    pub status: ParlayStatus,
+    // what the parlay paid out, once it has won or been voided
+    pub payout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ParlayLeg {
+    pub wager_id: String,
+    pub wager_option_id: String,
+    // the odds of a fixed-odds leg, fixed when the parlay was placed
+    pub odds: Option<u32>,
+    pub status: LegStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ParlayStatus {
+    #[default]
+    Active,
+    Won,
+    Lost,
+    // every leg was voided, so the stake was refunded
+    Void,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum LegStatus {
+    // the leg's wager has not been settled yet
+    #[default]
+    Pending,
+    // the stake is multiplied by numerator / denominator
+    Won { numerator: u64, denominator: u64 },
+    Lost,
+    // the leg's wager was cancelled, or its winner has no price, so it drops out of the parlay
+    Void,
+}
+
+// A one-on-one bet proposed by one user to another. Once accepted, both stakes are held in escrow as
+// bets on a two-option wager of their own, until the arbiter or an admin settles it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Challenge {
+    pub id: String,
+    pub challenger_id: String,
+    pub opponent_id: String,
+    // what each side puts up
+    pub stake: u64,
+    pub terms: String,
+    // the user both sides agreed may settle the challenge. Admins can always settle it
+    pub arbiter_id: Option<String>,
+    pub status: ChallengeStatus,
+    // the wager holding the stakes, once the challenge has been accepted
+    pub wager_id: Option<String>,
+    pub winner_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ChallengeStatus {
+    #[default]
+    Proposed,
+    Declined,
+    // both stakes are in escrow
+    Accepted,
+    Settled,
+    // settled without a winner, so both stakes were refunded
+    Void,
+}
+
+// A game that can post its state to the server through game state integration
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GsiGame {
+    CounterStrike2,
+    Dota2,
+}
+
+// Ties the game state posted by a game client to a wager, so the wager resolves itself when the game
+// reports a winner
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct GsiLink {
+    pub game: GsiGame,
+    // only results on this map count, if set. Counter-Strike 2 only
+    pub map_name: Option<String>,
+    // only the result of this match counts, if set. Dota 2 only
+    pub match_id: Option<String>,
+    pub teams: Vec<GsiTeam>,
+}
+
+// The option that wins if `team` does. A team is either the name the game reports for it, or the side it
+// finishes on: ct or t in Counter-Strike 2, radiant or dire in Dota 2. Names are matched first, so a
+// team that swaps sides at half time should be linked by name
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct GsiTeam {
+    pub team: String,
+    pub wager_option_id: String,
+}
+
+// A tournament to lay out as a bracket, with a wager for every match
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BracketDefinition {
+    pub name: String,
+    pub format: BracketFormat,
+    // in seed order, best first. Single elimination gives byes to the top seeds when the number of
+    // teams is not a power of two
+    pub teams: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum BracketFormat {
+    #[default]
+    SingleElimination,
+    // needs a power of two teams, at least four. The grand final is a single match
+    DoubleElimination,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Bracket {
+    pub id: String,
+    pub name: String,
+    pub format: BracketFormat,
+    pub matches: Vec<BracketMatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BracketMatch {
+    pub id: String,
+    pub name: String,
+    pub wager_id: String,
+    // the two sides, left out until the match that decides them has been resolved
+    pub teams: Vec<Option<String>>,
+}
+
+// Where a wager is in its lifecycle. Bets may only be placed on open wagers, and a wager can only be
+// resolved or cancelled once. A waiting wager is still missing some of its options, like a bracket match
+// whose teams are not known yet, and opens once they are filled in rather than by hand
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum WagerStatus {
+    #[default]
+    Open,
+    Locked,
+    Resolved,
+    Cancelled,
+    Waiting,
+}
+
+impl WagerStatus {
+    pub const ALL: [WagerStatus; 5] = [
+        WagerStatus::Open,
+        WagerStatus::Locked,
+        WagerStatus::Resolved,
+        WagerStatus::Cancelled,
+        WagerStatus::Waiting,
+    ];
+
+    pub fn can_transition_to(&self, next: WagerStatus) -> bool {
+        use WagerStatus::*;
+        matches!(
+            (self, next),
+            (Open, Locked) | (Locked, Open) | (Open | Locked, Resolved) | (Open | Locked | Waiting, Cancelled)
+        )
+    }
+}
+
 // A potential, unrealised wager. To be used the creation of a new, fully realised wager
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct PotentialWager {
     pub name: String,
     pub description: String,
     pub pot: u64,
+    // locks the wager automatically at this unix timestamp
+    #[serde(default)]
+    pub closes_at: Option<u64>,
+    // the server's default rake if unset
+    #[serde(default)]
+    pub rake: Option<Rake>,
+    #[serde(default)]
+    pub kind: WagerKind,
+    #[serde(default)]
+    pub no_winner_policy: NoWinnerPolicy,
+    #[serde(default)]
+    pub stake_limits: StakeLimits,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub game: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub electorate: Electorate,
+}
+
+// One option of a potential wager. Fixed-odds wagers need odds on every option, and numeric wagers
+// need a range on every option
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct PotentialWagerOption {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub odds: Option<u32>,
+    #[serde(default)]
+    pub range: Option<OutcomeRange>,
+}
+
+impl From<&WagerOption> for PotentialWagerOption {
+    fn from(option: &WagerOption) -> Self {
+        Self {
+            name: option.name.clone(),
+            description: option.description.clone(),
+            odds: option.odds,
+            range: option.range,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -29,12 +411,87 @@ pub struct WagerOption {
     pub id: String,
     pub name: String,
     pub description: String,
+    // decimal odds in hundredths, so 250 pays 2.5 times the stake. Only set on fixed-odds wagers
+    pub odds: Option<u32>,
+    // the outcomes this option covers. Only set on numeric wagers, which are resolved with a number
+    pub range: Option<OutcomeRange>,
+    pub stats: OptionStats,
     pub bets: Vec<Bet>,
 }
 
+// Live figures for an option, worked out by the server from the bets on its wager
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct OptionStats {
+    pub total_stake: u64,
+    // how many different users have bet on the option
+    pub bettors: u32,
+    // the chance of the option winning implied by the stakes, or by its odds on a fixed-odds wager. In
+    // basis points, so 2500 is 25%. Unknown while nothing is staked on a pari-mutuel option
+    pub implied_probability: Option<u32>,
+    // what a winning bet would be paid per unit staked, stake included, if the wager were resolved in
+    // favour of this option alone right now. In hundredths, like odds
+    pub multiplier: Option<u64>,
+}
+
+// A range of numeric outcomes, from `min` (inclusive) up to `max` (exclusive). A bound that is left out
+// is unbounded. Outcomes are fixed point numbers in hundredths, so 45.5 is 4550
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OutcomeRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl OutcomeRange {
+    pub fn contains(&self, outcome: i64) -> bool {
+        self.min.is_none_or(|min| outcome >= min) && self.max.is_none_or(|max| outcome < max)
+    }
+
+    // Named under and over options on `line`. An outcome exactly on the line is in neither, so a whole
+    // number line can end in a push
+    pub fn over_under(line: i64) -> Vec<(String, OutcomeRange)> {
+        vec![
+            (format!("Under {}", format_outcome(line)), OutcomeRange { min: None, max: Some(line) }),
+            (format!("Over {}", format_outcome(line)), OutcomeRange { min: Some(line.saturating_add(1)), max: None }),
+        ]
+    }
+
+    // Named options covering every outcome, split at each of `bounds`
+    pub fn split_at(bounds: &[i64]) -> Vec<(String, OutcomeRange)> {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let Some((&first, &last)) = bounds.first().zip(bounds.last()) else {
+            return vec![];
+        };
+
+        let mut ranges = vec![(format!("Under {}", format_outcome(first)), OutcomeRange { min: None, max: Some(first) })];
+        for pair in bounds.windows(2) {
+            ranges.push((
+                format!("{} to {}", format_outcome(pair[0]), format_outcome(pair[1])),
+                OutcomeRange { min: Some(pair[0]), max: Some(pair[1]) },
+            ));
+        }
+        ranges.push((format!("{} or more", format_outcome(last)), OutcomeRange { min: Some(last), max: None }));
+        ranges
+    }
+}
+
+// Formats a numeric outcome in hundredths as a decimal, without trailing zeros
+pub fn format_outcome(outcome: i64) -> String {
+    let sign = if outcome < 0 { "-" } else { "" };
+    let (whole, fraction) = (outcome.unsigned_abs() / 100, outcome.unsigned_abs() % 100);
+    match fraction {
+        0 => format!("{sign}{whole}"),
+        _ if fraction % 10 == 0 => format!("{sign}{whole}.{}", fraction / 10),
+        _ => format!("{sign}{whole}.{fraction:02}"),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Bet {
     pub id: String,
     pub user_id: String,
     pub val: u64,
+    // what a fixed-odds bet pays (stake included) if it wins, fixed when it was placed
+    pub potential_payout: Option<u64>,
 }
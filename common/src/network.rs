@@ -7,8 +7,49 @@ use tokio::net::TcpStream;
 pub enum Request {
     Login { user: String }, // None response
     WhoAmI,
-    WagerData,
-    ResolveWager{ wager_id: String, winning_option_id: String }, //None response
+    WagerData(crate::WagerFilter),
+    ResolveWager{ wager_id: String, winners: Vec<crate::Winner> },
+    // resolves a numeric wager with the number it ended on, in hundredths
+    ResolveNumericWager{ wager_id: String, outcome: i64 },
+    // objects to a resolution before it becomes final
+    DisputeResolution{ wager_id: String, reason: String }, //None response
+    // admins only. Confirming makes a pending resolution final straight away, disputed or not, and
+    // overturning throws it away so the wager can be resolved again
+    ConfirmResolution{ wager_id: String }, //None response
+    OverturnResolution{ wager_id: String }, //None response
+    // votes for the option that won a locked wager. Changing your mind replaces your earlier vote
+    VoteOnOutcome{ wager_id: String, wager_option_id: String },
+    PlaceBet{ wager_option_id: String, val: u64 },
+    LockWager{ wager_id: String }, //None response
+    // reopens a locked wager for betting until `closes_at`, or until it is locked by hand if that is not set
+    ReopenWager{ wager_id: String, closes_at: Option<u64> }, //None response
+    CancelWager{ wager_id: String }, //None response
+    SetOdds{ wager_option_id: String, odds: u32 }, //None response
+    ChangeBet{ bet_id: String, val: u64 },
+    PlaceParlay{ wager_option_ids: Vec<String>, val: u64 },
+    SetStakeLimits{ wager_id: String, limits: crate::StakeLimits }, //None response
+    WithdrawBet{ bet_id: String },
+    // sells `stake` of a bet back before its wager locks, at a price based on how the option's chance
+    // of winning has moved since the bet was placed
+    CashOutBet{ bet_id: String, stake: u64 },
+    ProposeChallenge{ opponent: String, stake: u64, terms: String, arbiter: Option<String> },
+    AcceptChallenge{ challenge_id: String },
+    // declines a challenge, or withdraws one the user proposed
+    DeclineChallenge{ challenge_id: String }, //None response
+    // settles an accepted challenge in favour of one side. No winner voids it and refunds both stakes
+    SettleChallenge{ challenge_id: String, winner: Option<String> },
+    // every challenge the user is part of, or is the arbiter of
+    ChallengeData,
+    CreateBracket(crate::BracketDefinition),
+    CreateWager{ wager: crate::PotentialWager, options: Vec<crate::PotentialWagerOption> },
+    // the rest of these only work while the wager is open. Anything left out is not changed
+    EditWager{ wager_id: String, name: Option<String>, description: Option<String>, pot: Option<u64> }, //None response
+    AddWagerOption{ wager_id: String, option: crate::PotentialWagerOption },
+    EditWagerOption{ wager_option_id: String, name: Option<String>, description: Option<String> }, //None response
+    // refunds every bet on the option
+    RemoveWagerOption{ wager_option_id: String }, //None response
+    // the token that comes back goes in the game's state integration config, so its posts resolve the wager
+    LinkGsi{ wager_id: String, link: crate::GsiLink },
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -17,12 +58,81 @@ pub enum Response {
     SuccessfulLogin{username: String, balance: u64},
     WhoAmI(String),
     WagerData(Vec<crate::Wager>),
+    BetPlaced{balance: u64, bet: crate::Bet},
+    BetChanged{balance: u64, bet: crate::Bet},
+    BetWithdrawn{balance: u64},
+    BetCashedOut{balance: u64, payout: u64},
+    ParlayPlaced{balance: u64, parlay: crate::Parlay},
+    ChallengeProposed(crate::Challenge),
+    ChallengeAccepted{balance: u64, challenge: crate::Challenge},
+    ChallengeSettled(crate::Challenge),
+    ChallengeData(Vec<crate::Challenge>),
+    BracketCreated(crate::Bracket),
+    WagerCreated{ wager_id: String, option_ids: Vec<String> },
+    WagerOptionAdded{ wager_option_id: String },
+    GsiLinked{ token: String },
+    WagerResolved(crate::Resolution),
+    // the resolution is set if this vote settled the outcome
+    VoteCast{ resolution: Option<crate::Resolution> },
+    Rejected(Rejection),
+}
+
+// Why the server refused a request, for failures the client can do something about
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Rejection {
+    // the wager was already resolved with different winners
+    ResolutionConflict{wager_id: String, resolution: crate::Resolution},
+    // the bet was outside the stake limits of its wager
+    StakeLimitHit{limit: crate::StakeLimit},
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::ResolutionConflict { wager_id, resolution } => {
+                if let Some(outcome) = resolution.outcome {
+                    return write!(f, "wager {} was already resolved at {}", wager_id, crate::format_outcome(outcome));
+                }
+                let winners: Vec<&str> = resolution
+                    .winners
+                    .iter()
+                    .map(|winner| winner.option_id.as_str())
+                    .collect();
+                write!(
+                    f,
+                    "wager {} was already resolved in favour of {}",
+                    wager_id,
+                    winners.join(", ")
+                )
+            }
+            Rejection::StakeLimitHit { limit } => match limit {
+                crate::StakeLimit::MinStake(min_stake) => write!(f, "bets must be at least {}", min_stake),
+                crate::StakeLimit::MaxStake(max_stake) => write!(f, "bets can be at most {}", max_stake),
+                crate::StakeLimit::MaxExposure(max_exposure) => write!(
+                    f,
+                    "nobody can have more than {} staked on one wager",
+                    max_exposure
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+// Sent by the server unprompted, whenever something happens that connected clients should know about
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Notification {
+    WagerLocked{wager_id: String},
+    // the voters could not agree, or ran out of time to, so an admin has to resolve the wager
+    VoteDeadlocked{wager_id: String},
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum Packet {
     RequestPacket(Request),
     ResponsePacket(Response),
+    NotificationPacket(Notification),
     Error,
 }
 
@@ -0,0 +1,321 @@
+// Tournament bracket layout. A bracket is a list of matches, where each side of a match is a team that
+// is known from the start or the winner or loser of an earlier match. Byes never become matches: a
+// team with a bye is placed straight into its next match
+
+use anyhow::bail;
+use common::{BracketDefinition, BracketFormat, Rake, WagerStatus};
+use surrealdb::sql::{Id, Thing};
+use crate::database::{
+    BracketLayout, BracketSlot, DbBracket, DbBracketMatch, DbWager, DbWagerOption, TABLE_BRACKET,
+    TABLE_BRACKET_MATCH,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slot {
+    Team(String),
+    WinnerOf(usize),
+    LoserOf(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub name: String,
+    pub slots: [Slot; 2],
+}
+
+pub fn generate(format: BracketFormat, teams: &[String]) -> anyhow::Result<Vec<Match>> {
+    if teams.len() < 2 {
+        bail!("a bracket needs at least two teams");
+    }
+    for (index, team) in teams.iter().enumerate() {
+        if team.is_empty() {
+            bail!("team names cannot be empty");
+        }
+        if teams[..index].contains(team) {
+            bail!("{} is in the bracket more than once", team);
+        }
+    }
+
+    let mut matches = Vec::new();
+    match format {
+        BracketFormat::SingleElimination => {
+            knockout(&mut matches, "", seeded_entrants(teams));
+        }
+        BracketFormat::DoubleElimination => {
+            if teams.len() < 4 || !teams.len().is_power_of_two() {
+                bail!("double elimination needs a power of two teams, and at least four");
+            }
+            let winners_rounds = knockout(&mut matches, "Winners ", seeded_entrants(teams));
+            let winners_final = winners_rounds[winners_rounds.len() - 1][0];
+            let losers_final = losers_bracket(&mut matches, &winners_rounds);
+            matches.push(Match {
+                name: "Grand Final".into(),
+                slots: [Slot::WinnerOf(winners_final), losers_final],
+            });
+        }
+    }
+    Ok(matches)
+}
+
+// The records for a new bracket: a wager per match, with an option for each side. A match that is
+// still waiting on an earlier one can't be bet on or settled yet, with its options named after where its
+// teams come from
+pub fn layout(definition: &BracketDefinition, rake: Rake) -> anyhow::Result<BracketLayout> {
+    let matches = generate(definition.format, &definition.teams)?;
+    let match_ids: Vec<Thing> = matches
+        .iter()
+        .map(|_| Thing {
+            tb: TABLE_BRACKET_MATCH.into(),
+            id: Id::rand(),
+        })
+        .collect();
+    let bracket = DbBracket {
+        id: Thing {
+            tb: TABLE_BRACKET.into(),
+            id: Id::rand(),
+        },
+        name: definition.name.clone(),
+        format: definition.format,
+        matches: match_ids.clone(),
+    };
+
+    let mut winner_to = vec![None; matches.len()];
+    let mut loser_to = vec![None; matches.len()];
+    for (index, bracket_match) in matches.iter().enumerate() {
+        for (slot, side) in bracket_match.slots.iter().enumerate() {
+            let destination = Some(BracketSlot {
+                bracket_match: match_ids[index].clone(),
+                slot,
+            });
+            match side {
+                Slot::WinnerOf(earlier) => winner_to[*earlier] = destination,
+                Slot::LoserOf(earlier) => loser_to[*earlier] = destination,
+                Slot::Team(_) => {}
+            }
+        }
+    }
+
+    let mut layout = BracketLayout {
+        bracket,
+        matches: Vec::with_capacity(matches.len()),
+        wagers: Vec::with_capacity(matches.len()),
+        options: Vec::with_capacity(matches.len() * 2),
+    };
+    for (index, bracket_match) in matches.iter().enumerate() {
+        let mut wager = DbWager::new(
+            format!("{}: {}", definition.name, bracket_match.name),
+            definition.name.clone(),
+            0,
+        );
+        wager.rake = rake;
+        let mut teams = Vec::with_capacity(2);
+        let mut options = Vec::with_capacity(2);
+        for side in &bracket_match.slots {
+            let (team, name) = match side {
+                Slot::Team(team) => (Some(team.clone()), team.clone()),
+                Slot::WinnerOf(earlier) => (None, format!("Winner of {}", matches[*earlier].name)),
+                Slot::LoserOf(earlier) => (None, format!("Loser of {}", matches[*earlier].name)),
+            };
+            let option = DbWagerOption::new(name.clone(), name, wager.id.clone());
+            wager.options.push(option.id.clone());
+            options.push(option.id.clone());
+            layout.options.push(option);
+            teams.push(team);
+        }
+        if teams.iter().any(Option::is_none) {
+            wager.status = WagerStatus::Waiting;
+        }
+        layout.matches.push(DbBracketMatch {
+            id: match_ids[index].clone(),
+            bracket: layout.bracket.id.clone(),
+            name: bracket_match.name.clone(),
+            wager: wager.id.clone(),
+            options,
+            teams,
+            winner_to: winner_to[index].clone(),
+            loser_to: loser_to[index].clone(),
+        });
+        layout.wagers.push(wager);
+    }
+    Ok(layout)
+}
+
+// The first round's entrants in bracket order, so the top seeds only meet late on. A missing entrant
+// is a bye for whoever it was drawn against
+fn seeded_entrants(teams: &[String]) -> Vec<Option<Slot>> {
+    let size = teams.len().next_power_of_two();
+    let mut order = vec![0];
+    while order.len() < size {
+        let round_size = order.len() * 2;
+        order = order
+            .into_iter()
+            .flat_map(|seed| [seed, round_size - 1 - seed])
+            .collect();
+    }
+    order
+        .into_iter()
+        .map(|seed| teams.get(seed).cloned().map(Slot::Team))
+        .collect()
+}
+
+// Plays the entrants off in pairs until one is left, returning the matches of each round
+fn knockout(matches: &mut Vec<Match>, prefix: &str, mut entrants: Vec<Option<Slot>>) -> Vec<Vec<usize>> {
+    let mut rounds = Vec::new();
+    while entrants.len() > 1 {
+        let round_name = if entrants.len() == 2 {
+            format!("{}Final", prefix)
+        } else {
+            format!("{}Round {}", prefix, rounds.len() + 1)
+        };
+        let mut round = Vec::new();
+        let mut next_entrants = Vec::new();
+        for pair in entrants.chunks(2) {
+            match (pair[0].clone(), pair[1].clone()) {
+                (Some(first), Some(second)) => {
+                    round.push(matches.len());
+                    next_entrants.push(Some(Slot::WinnerOf(matches.len())));
+                    matches.push(Match {
+                        name: match_name(&round_name, round.len(), entrants.len() == 2),
+                        slots: [first, second],
+                    });
+                }
+                (bye, None) | (None, bye) => next_entrants.push(bye),
+            }
+        }
+        rounds.push(round);
+        entrants = next_entrants;
+    }
+    rounds
+}
+
+// The losers of the first winners round play each other, then every later round alternates between
+// taking in the losers of the next winners round and halving the field. Returns who comes out of it
+fn losers_bracket(matches: &mut Vec<Match>, winners_rounds: &[Vec<usize>]) -> Slot {
+    let mut round_number = 0;
+    let mut play_round = |matches: &mut Vec<Match>, pairs: Vec<[Slot; 2]>| -> Vec<Slot> {
+        round_number += 1;
+        let only_match = pairs.len() == 1;
+        let round_name = if round_number == 2 * (winners_rounds.len() - 1) {
+            "Losers Final".to_string()
+        } else {
+            format!("Losers Round {}", round_number)
+        };
+        pairs
+            .into_iter()
+            .enumerate()
+            .map(|(index, slots)| {
+                matches.push(Match {
+                    name: match_name(&round_name, index + 1, only_match),
+                    slots,
+                });
+                Slot::WinnerOf(matches.len() - 1)
+            })
+            .collect()
+    };
+
+    let first_round = winners_rounds[0]
+        .chunks(2)
+        .map(|pair| [Slot::LoserOf(pair[0]), Slot::LoserOf(pair[1])])
+        .collect();
+    let mut survivors = play_round(matches, first_round);
+    for winners_round in &winners_rounds[1..] {
+        let drop_ins = survivors
+            .into_iter()
+            .zip(winners_round)
+            .map(|(survivor, &dropped)| [survivor, Slot::LoserOf(dropped)])
+            .collect();
+        survivors = play_round(matches, drop_ins);
+        if survivors.len() > 1 {
+            let pairs = survivors
+                .chunks(2)
+                .map(|pair| [pair[0].clone(), pair[1].clone()])
+                .collect();
+            survivors = play_round(matches, pairs);
+        }
+    }
+    survivors.remove(0)
+}
+
+fn match_name(round_name: &str, number: usize, only_match: bool) -> String {
+    if only_match {
+        round_name.to_string()
+    } else {
+        format!("{} Match {}", round_name, number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn teams(count: usize) -> Vec<String> {
+        (1..=count).map(|seed| format!("team{}", seed)).collect()
+    }
+
+    fn team(name: &str) -> Slot {
+        Slot::Team(name.into())
+    }
+
+    #[test]
+    fn test_single_elimination() {
+        let matches = generate(BracketFormat::SingleElimination, &teams(4)).unwrap();
+        let names: Vec<&str> = matches.iter().map(|bracket_match| bracket_match.name.as_str()).collect();
+        assert_eq!(names, vec!["Round 1 Match 1", "Round 1 Match 2", "Final"]);
+        assert_eq!(matches[0].slots, [team("team1"), team("team4")]);
+        assert_eq!(matches[1].slots, [team("team2"), team("team3")]);
+        assert_eq!(matches[2].slots, [Slot::WinnerOf(0), Slot::WinnerOf(1)]);
+    }
+
+    #[test]
+    fn test_single_elimination_with_byes() {
+        // the top three seeds get byes into the second round
+        let matches = generate(BracketFormat::SingleElimination, &teams(5)).unwrap();
+        assert_eq!(matches.len(), 4);
+        assert_eq!(matches[0].slots, [team("team4"), team("team5")]);
+        assert_eq!(matches[1].slots, [team("team1"), Slot::WinnerOf(0)]);
+        assert_eq!(matches[2].slots, [team("team2"), team("team3")]);
+        assert_eq!(matches[3].slots, [Slot::WinnerOf(1), Slot::WinnerOf(2)]);
+    }
+
+    #[test]
+    fn test_double_elimination() {
+        let matches = generate(BracketFormat::DoubleElimination, &teams(4)).unwrap();
+        let names: Vec<&str> = matches.iter().map(|bracket_match| bracket_match.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Winners Round 1 Match 1", "Winners Round 1 Match 2", "Winners Final", "Losers Round 1", "Losers Final", "Grand Final"]
+        );
+        assert_eq!(matches[3].slots, [Slot::LoserOf(0), Slot::LoserOf(1)]);
+        assert_eq!(matches[4].slots, [Slot::WinnerOf(3), Slot::LoserOf(2)]);
+        assert_eq!(matches[5].slots, [Slot::WinnerOf(2), Slot::WinnerOf(4)]);
+
+        // every match has exactly one loser. Everyone but the two finalists is out after two losses,
+        // and the finalists lose twice between them
+        for count in [8, 16, 32] {
+            assert_eq!(generate(BracketFormat::DoubleElimination, &teams(count)).unwrap().len(), 2 * count - 2);
+        }
+    }
+
+    #[test]
+    fn test_every_match_feeds_at_most_one_later_match() {
+        for (format, count) in [(BracketFormat::SingleElimination, 11), (BracketFormat::DoubleElimination, 16)] {
+            let matches = generate(format, &teams(count)).unwrap();
+            for index in 0..matches.len() {
+                let fed = |slot: &Slot| matches!(slot, Slot::WinnerOf(fed) if *fed == index);
+                let fed_by_loss = |slot: &Slot| matches!(slot, Slot::LoserOf(fed) if *fed == index);
+                let feeds: Vec<usize> = (0..matches.len()).filter(|&later| matches[later].slots.iter().any(fed)).collect();
+                assert!(feeds.iter().all(|&later| later > index));
+                assert_eq!(feeds.len(), usize::from(index + 1 != matches.len()));
+                assert!(matches.iter().flat_map(|later| &later.slots).filter(|slot| fed_by_loss(slot)).count() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejected_brackets() {
+        generate(BracketFormat::SingleElimination, &teams(1)).expect_err("should need two teams");
+        generate(BracketFormat::SingleElimination, &["a".into(), "a".into()]).expect_err("should not repeat a team");
+        generate(BracketFormat::DoubleElimination, &teams(6)).expect_err("should need a power of two");
+        generate(BracketFormat::DoubleElimination, &teams(2)).expect_err("should need four teams");
+    }
+}
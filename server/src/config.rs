@@ -0,0 +1,117 @@
+use anyhow::{anyhow, bail};
+use common::{Rake, RakeDestination, StakeLimits};
+use std::str::FromStr;
+
+// Server wide settings. Everything has a default, and can be overridden with a `LAN_BET_*`
+// environment variable
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // the rake applied to newly created wagers
+    pub default_rake: Rake,
+    // the most the house may lose on a fixed-odds wager if any one option wins. Unlimited if unset
+    pub max_liability_per_option: Option<u64>,
+    // applied to every wager that does not set its own
+    pub stake_limits: StakeLimits,
+    // the share of a bet's value the house keeps when it is cashed out, in basis points
+    pub cash_out_haircut: u32,
+    // how many seconds a resolution can be disputed for before its payouts become final. Resolutions
+    // are final straight away if this is 0
+    pub dispute_window: u64,
+    // the share of a wager's electorate that has to vote before the vote can settle it, in basis points.
+    // More than half by default, so one side of a head to head match cannot settle it alone
+    pub vote_quorum: u32,
+    // the share of the votes cast that have to agree on the winner, in basis points. Always more than half
+    pub vote_supermajority: u32,
+    // how many seconds a wager's electorate has to settle it, counted from the first vote. Once it runs
+    // out the vote counts as deadlocked and an admin has to resolve the wager
+    pub vote_window: u64,
+    // where game clients post their game state to. Every interface by default, so games on other
+    // machines on the LAN can reach it
+    pub gsi_address: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            default_rake: Rake::default(),
+            max_liability_per_option: None,
+            stake_limits: StakeLimits::default(),
+            cash_out_haircut: 0,
+            dispute_window: 0,
+            vote_quorum: 5_001,
+            vote_supermajority: 6_667,
+            vote_window: 3_600,
+            gsi_address: "0.0.0.0:3000".into(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(basis_points) = env_var::<u32>("LAN_BET_RAKE_BASIS_POINTS")? {
+            if basis_points > 10_000 {
+                bail!("LAN_BET_RAKE_BASIS_POINTS cannot be more than 10000 (100%)");
+            }
+            config.default_rake.basis_points = basis_points;
+        }
+        if let Some(destination) = env_var::<String>("LAN_BET_RAKE_DESTINATION")? {
+            config.default_rake.destination = match destination.as_str() {
+                "house" => RakeDestination::House,
+                "jackpot" => RakeDestination::Jackpot,
+                _ => bail!("LAN_BET_RAKE_DESTINATION must be either house or jackpot"),
+            };
+        }
+        config.max_liability_per_option = env_var("LAN_BET_MAX_LIABILITY_PER_OPTION")?;
+        config.stake_limits = StakeLimits {
+            min_stake: env_var("LAN_BET_MIN_STAKE")?,
+            max_stake: env_var("LAN_BET_MAX_STAKE")?,
+            max_exposure: env_var("LAN_BET_MAX_EXPOSURE")?,
+        };
+        if let Some(basis_points) = env_var::<u32>("LAN_BET_CASH_OUT_HAIRCUT_BASIS_POINTS")? {
+            if basis_points > 10_000 {
+                bail!("LAN_BET_CASH_OUT_HAIRCUT_BASIS_POINTS cannot be more than 10000 (100%)");
+            }
+            config.cash_out_haircut = basis_points;
+        }
+        if let Some(seconds) = env_var("LAN_BET_DISPUTE_WINDOW_SECONDS")? {
+            config.dispute_window = seconds;
+        }
+        if let Some(basis_points) = env_var::<u32>("LAN_BET_VOTE_QUORUM_BASIS_POINTS")? {
+            if basis_points == 0 || basis_points > 10_000 {
+                bail!("LAN_BET_VOTE_QUORUM_BASIS_POINTS must be between 1 and 10000 (100%)");
+            }
+            config.vote_quorum = basis_points;
+        }
+        if let Some(basis_points) = env_var::<u32>("LAN_BET_VOTE_SUPERMAJORITY_BASIS_POINTS")? {
+            if basis_points <= 5_000 || basis_points > 10_000 {
+                bail!("LAN_BET_VOTE_SUPERMAJORITY_BASIS_POINTS must be more than 5000 (50%) and at most 10000 (100%)");
+            }
+            config.vote_supermajority = basis_points;
+        }
+        if let Some(seconds) = env_var::<u64>("LAN_BET_VOTE_WINDOW_SECONDS")? {
+            if seconds == 0 {
+                bail!("LAN_BET_VOTE_WINDOW_SECONDS must be at least 1");
+            }
+            config.vote_window = seconds;
+        }
+        if let Some(address) = env_var("LAN_BET_GSI_ADDRESS")? {
+            config.gsi_address = address;
+        }
+
+        Ok(config)
+    }
+}
+
+fn env_var<T: FromStr>(name: &str) -> anyhow::Result<Option<T>> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(
+            value
+                .parse()
+                .map_err(|_| anyhow!("invalid value for {}: {}", name, value))?,
+        )),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
@@ -1,15 +1,17 @@
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::net::TcpListener;
-use common::network::{Connection, Packet, Request, Response};
+use common::network::{Connection, Packet, Rejection, Request, Response};
 use std::io::ErrorKind;
 use anyhow::{anyhow, bail};
 use surrealdb::sql::{Id, Thing};
+use crate::database::{DbBet, DbChallenge, DbGsiLink, DbWinner, TABLE_BET, TABLE_CHALLENGE, TABLE_USER, TABLE_WAGER, TABLE_WAGER_OPTION};
 use crate::database_manager::DatabaseRequest;
-use crate::wager_manager::WagerRequest;
+use crate::wager_manager::{AddressedNotification, WagerRequest};
 
 pub async fn hande_listen_server(
     db_tx: mpsc::Sender<DatabaseRequest>,
     wager_tx: mpsc::Sender<WagerRequest>,
+    notification_tx: broadcast::Sender<AddressedNotification>,
 ) {
     let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
 
@@ -17,10 +19,11 @@ pub async fn hande_listen_server(
         let (connection, _) = listener.accept().await.unwrap();
         let tx = db_tx.clone();
         let wage_tx = wager_tx.clone();
+        let notifications = notification_tx.subscribe();
 
         tokio::spawn(async move {
             let connection = Connection::from_tcp_stream(connection).await.unwrap();
-            handle_connection(connection, tx, wage_tx).await;
+            handle_connection(connection, tx, wage_tx, notifications).await;
         });
     }
 }
@@ -29,10 +32,11 @@ async fn handle_connection(
     mut connection: Connection,
     mut db_tx: mpsc::Sender<DatabaseRequest>,
     wager_tx: mpsc::Sender<WagerRequest>,
+    notifications: broadcast::Receiver<AddressedNotification>,
 ) {
     let user = handle_login(&mut connection, &mut db_tx).await;
     if let Ok(username) = user {
-        match handle_client(username, &mut connection, db_tx, wager_tx).await {
+        match handle_client(username, &mut connection, db_tx, wager_tx, notifications).await {
             Ok(()) => {}
             Err(_) => {
                 connection.send(Packet::Error).await.unwrap();
@@ -80,14 +84,35 @@ async fn handle_login(
     }
 }
 
+// Rejections are passed on to the client so it can tell the user what went wrong, anything else is
+// reported as a generic error
+fn failure_packet(error: &anyhow::Error) -> Packet {
+    match error.downcast_ref::<Rejection>() {
+        Some(rejection) => Packet::ResponsePacket(Response::Rejected(rejection.clone())),
+        None => Packet::Error,
+    }
+}
+
 async fn handle_client(
     username: String,
     connection: &mut Connection,
     db_tx: mpsc::Sender<DatabaseRequest>,
     wager_tx: mpsc::Sender<WagerRequest>,
+    mut notifications: broadcast::Receiver<AddressedNotification>,
 ) -> anyhow::Result<()> {
     loop {
-        let packet = connection.read().await;
+        let packet = tokio::select! {
+            packet = connection.read() => packet,
+            notification = notifications.recv() => {
+                // a lagging client just misses out on the notifications it was too slow for
+                if let Ok(notification) = notification {
+                    if notification.is_for(&Id::String(username.clone()).to_string()) {
+                        connection.send(Packet::NotificationPacket(notification.notification)).await?;
+                    }
+                }
+                continue;
+            }
+        };
         if let Ok(Packet::RequestPacket(request)) = packet {
             match request {
                 Request::Login { user: _ } => {
@@ -100,45 +125,759 @@ async fn handle_client(
                         .send(Packet::ResponsePacket(Response::WhoAmI(username.clone())))
                         .await?;
                 }
-                Request::WagerData => {
+                Request::WagerData(filter) => {
                     let (resp_tx, resp_rx) = oneshot::channel();
                     db_tx
-                        .send(DatabaseRequest::GetAllWagerInfo { responder: resp_tx })
+                        .send(DatabaseRequest::GetWagerInfoMatching {
+                            filter,
+                            responder: resp_tx,
+                        })
                         .await?;
-                    let response = resp_rx.await?;
-                    if let Ok(wager_info) = response {
-                        connection
-                            .send(Packet::ResponsePacket(Response::WagerData(wager_info)))
-                            .await?;
-                    } else {
-                        connection.send(Packet::Error).await?;
+                    match resp_rx.await? {
+                        Ok(wager_info) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::WagerData(wager_info)))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
                     }
                 }
-                Request::ResolveWager {
-                    wager_id,
-                    winning_option_id,
-                } => {
+                Request::ResolveWager { wager_id, winners } => {
                     let (resp_tx, resp_rx) = oneshot::channel();
+                    let winners = winners
+                        .into_iter()
+                        .map(|winner| DbWinner {
+                            option: Thing {
+                                tb: "wager_option".into(),
+                                id: Id::String(winner.option_id),
+                            },
+                            weight: winner.weight,
+                        })
+                        .collect();
                     wager_tx
                         .send(WagerRequest::ResolveWager {
                             wager_id: Thing {
                                 tb: "wager".into(),
                                 id: Id::String(wager_id),
                             },
-                            winning_option: Thing {
-                                tb: "wager_option".into(),
-                                id: Id::String(winning_option_id),
+                            winners,
+                            user: Some(Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(resolution) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::WagerResolved(resolution)))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ResolveNumericWager { wager_id, outcome } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::ResolveNumericWager {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            outcome,
+                            user: Some(Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(resolution) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::WagerResolved(resolution)))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::DisputeResolution { wager_id, reason } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::DisputeResolution {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            reason,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ConfirmResolution { wager_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::ConfirmResolution {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::OverturnResolution { wager_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::OverturnResolution {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::VoteOnOutcome { wager_id, wager_option_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::VoteOnOutcome {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            option: Thing {
+                                tb: TABLE_WAGER_OPTION.into(),
+                                id: Id::String(wager_option_id),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(resolution) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::VoteCast { resolution }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::LockWager { wager_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::LockWager {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Some(Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ReopenWager { wager_id, closes_at } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::ReopenWager {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            closes_at,
+                            user: Some(Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::CancelWager { wager_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::CancelWager {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            user: Some(Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::SetOdds {
+                    wager_option_id,
+                    odds,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::SetOdds {
+                            wager_option_id: Thing {
+                                tb: TABLE_WAGER_OPTION.into(),
+                                id: Id::String(wager_option_id),
+                            },
+                            odds,
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ChangeBet { bet_id, val } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::ChangeBet {
+                            bet: Thing {
+                                tb: TABLE_BET.into(),
+                                id: Id::String(bet_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            val,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok((balance, bet)) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::BetChanged {
+                                    balance,
+                                    bet: bet.into(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::CashOutBet { bet_id, stake } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::CashOutBet {
+                            bet: Thing {
+                                tb: TABLE_BET.into(),
+                                id: Id::String(bet_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            stake,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok((balance, payout)) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::BetCashedOut { balance, payout }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::LinkGsi { wager_id, link } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    let link = DbGsiLink::new(
+                        Thing {
+                            tb: TABLE_WAGER.into(),
+                            id: Id::String(wager_id),
+                        },
+                        link,
+                    );
+                    let token = link.id.id.to_string();
+                    wager_tx
+                        .send(WagerRequest::LinkGsi {
+                            link,
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
                             },
                             responder: resp_tx,
                         })
                         .await?;
-                    let result = resp_rx.await?;
-                    if let Ok(()) = result {
-                        connection
-                            .send(Packet::ResponsePacket(Response::None))
-                            .await?;
-                    } else {
-                        connection.send(Packet::Error).await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::GsiLinked { token }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::SetStakeLimits { wager_id, limits } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::SetStakeLimits {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            limits,
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::WithdrawBet { bet_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    db_tx
+                        .send(DatabaseRequest::WithdrawBet {
+                            bet: Thing {
+                                tb: TABLE_BET.into(),
+                                id: Id::String(bet_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(balance) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::BetWithdrawn { balance }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::PlaceParlay {
+                    wager_option_ids,
+                    val,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::PlaceParlay {
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            wager_options: wager_option_ids
+                                .into_iter()
+                                .map(|wager_option_id| Thing {
+                                    tb: TABLE_WAGER_OPTION.into(),
+                                    id: Id::String(wager_option_id),
+                                })
+                                .collect(),
+                            val,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok((balance, parlay)) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::ParlayPlaced {
+                                    balance,
+                                    parlay: parlay.into(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ProposeChallenge {
+                    opponent,
+                    stake,
+                    terms,
+                    arbiter,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    let challenge = DbChallenge::new(
+                        Thing {
+                            tb: TABLE_USER.into(),
+                            id: Id::String(username.clone()),
+                        },
+                        Thing {
+                            tb: TABLE_USER.into(),
+                            id: Id::String(opponent),
+                        },
+                        stake,
+                        terms,
+                        arbiter.map(|arbiter| Thing {
+                            tb: TABLE_USER.into(),
+                            id: Id::String(arbiter),
+                        }),
+                    );
+                    db_tx
+                        .send(DatabaseRequest::ProposeChallenge {
+                            challenge,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(challenge) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::ChallengeProposed(challenge.into())))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::AcceptChallenge { challenge_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    db_tx
+                        .send(DatabaseRequest::AcceptChallenge {
+                            id: Thing {
+                                tb: TABLE_CHALLENGE.into(),
+                                id: Id::String(challenge_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok((balance, challenge)) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::ChallengeAccepted {
+                                    balance,
+                                    challenge: challenge.into(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::DeclineChallenge { challenge_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    db_tx
+                        .send(DatabaseRequest::DeclineChallenge {
+                            id: Thing {
+                                tb: TABLE_CHALLENGE.into(),
+                                id: Id::String(challenge_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::SettleChallenge {
+                    challenge_id,
+                    winner,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::SettleChallenge {
+                            challenge: Thing {
+                                tb: TABLE_CHALLENGE.into(),
+                                id: Id::String(challenge_id),
+                            },
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            winner: winner.map(|winner| Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(winner),
+                            }),
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(challenge) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::ChallengeSettled(challenge.into())))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::ChallengeData => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    db_tx
+                        .send(DatabaseRequest::GetChallengesForUser {
+                            user: Thing {
+                                tb: TABLE_USER.into(),
+                                id: Id::String(username.clone()),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(challenges) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::ChallengeData(
+                                    challenges.into_iter().map(Into::into).collect(),
+                                )))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::CreateBracket(definition) => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::CreateBracket {
+                            definition,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(bracket) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::BracketCreated(bracket)))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::CreateWager { wager, options } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::CreateWager {
+                            wager,
+                            options,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(wager) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::WagerCreated {
+                                    wager_id: wager.id.id.to_string(),
+                                    option_ids: wager.options.iter().map(|option| option.id.to_string()).collect(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::EditWager {
+                    wager_id,
+                    name,
+                    description,
+                    pot,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::EditWager {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            name,
+                            description,
+                            pot,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::AddWagerOption { wager_id, option } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::AddWagerOption {
+                            wager_id: Thing {
+                                tb: TABLE_WAGER.into(),
+                                id: Id::String(wager_id),
+                            },
+                            option,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(wager_option_id) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::WagerOptionAdded {
+                                    wager_option_id: wager_option_id.id.to_string(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::EditWagerOption {
+                    wager_option_id,
+                    name,
+                    description,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::EditWagerOption {
+                            wager_option_id: Thing {
+                                tb: TABLE_WAGER_OPTION.into(),
+                                id: Id::String(wager_option_id),
+                            },
+                            name,
+                            description,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::RemoveWagerOption { wager_option_id } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    wager_tx
+                        .send(WagerRequest::RemoveWagerOption {
+                            wager_option_id: Thing {
+                                tb: TABLE_WAGER_OPTION.into(),
+                                id: Id::String(wager_option_id),
+                            },
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok(()) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::None))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
+                    }
+                }
+                Request::PlaceBet {
+                    wager_option_id,
+                    val,
+                } => {
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    let bet = DbBet::new(
+                        Thing {
+                            tb: TABLE_USER.into(),
+                            id: Id::String(username.clone()),
+                        },
+                        Thing {
+                            tb: TABLE_WAGER_OPTION.into(),
+                            id: Id::String(wager_option_id),
+                        },
+                        val,
+                    );
+                    wager_tx
+                        .send(WagerRequest::PlaceBet {
+                            bet,
+                            responder: resp_tx,
+                        })
+                        .await?;
+                    match resp_rx.await? {
+                        Ok((balance, bet)) => {
+                            connection
+                                .send(Packet::ResponsePacket(Response::BetPlaced {
+                                    balance,
+                                    bet: bet.into(),
+                                }))
+                                .await?;
+                        }
+                        Err(error) => connection.send(failure_packet(&error)).await?,
                     }
                 }
             }
@@ -1,5 +1,7 @@
+use common::{BracketFormat, ChallengeStatus, Electorate, GsiGame, LegStatus, NoWinnerPolicy, OutcomeRange, ParlayStatus, Rake, RakeDestination, StakeLimits, WagerFilter, WagerKind, WagerStatus};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use surrealdb::engine::local::{Db, Mem};
 use surrealdb::opt::auth::Root;
 use surrealdb::sql::{Id, Thing};
@@ -7,15 +9,29 @@ use surrealdb::{
     engine::remote::ws::{Client, Ws},
     Result,
 };
-use surrealdb::{Connection, Surreal};
+use surrealdb::{Connection, Response, Surreal};
+use surrealdb::error::Db as DbError;
 
 use surrealdb::sql::statements::BeginStatement;
 use surrealdb::sql::statements::CommitStatement;
 
+use crate::payout;
+
 pub const TABLE_USER: &str = "user";
 pub const TABLE_WAGER: &str = "wager";
 pub const TABLE_WAGER_OPTION: &str = "wager_option";
 pub const TABLE_BET: &str = "bet";
+pub const TABLE_ACCOUNT: &str = "account";
+pub const TABLE_PARLAY: &str = "parlay";
+pub const TABLE_CHALLENGE: &str = "challenge";
+pub const TABLE_BRACKET: &str = "bracket";
+pub const TABLE_BRACKET_MATCH: &str = "bracket_match";
+pub const TABLE_DISPUTE: &str = "dispute";
+pub const TABLE_VOTE: &str = "vote";
+pub const TABLE_GSI_LINK: &str = "gsi_link";
+
+pub const ACCOUNT_HOUSE: &str = "house";
+pub const ACCOUNT_JACKPOT: &str = "jackpot";
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Record {
@@ -28,6 +44,9 @@ pub struct DbUser {
     pub id: Thing,
     pub name: String,
     pub balance: u64,
+    // admins may settle any challenge
+    #[serde(default)]
+    pub admin: bool,
 }
 
 impl DbUser {
@@ -39,6 +58,7 @@ impl DbUser {
             },
             name: name.into(),
             balance,
+            admin: false,
         }
     }
 }
@@ -58,9 +78,78 @@ pub struct DbWager {
     pub name: String,
     pub description: String,
     pub pot: u64,
+    #[serde(default)]
+    pub status: WagerStatus,
+    #[serde(default)]
+    pub closes_at: Option<u64>,
+    #[serde(default)]
+    pub resolution: Option<DbResolution>,
+    #[serde(default)]
+    pub rake: Rake,
+    #[serde(default)]
+    pub kind: WagerKind,
+    #[serde(default)]
+    pub no_winner_policy: NoWinnerPolicy,
+    #[serde(default)]
+    pub stake_limits: StakeLimits,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub game: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub electorate: Electorate,
+    #[serde(default)]
+    pub vote_deadlocked: bool,
     pub options: Vec<Thing>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbResolution {
+    pub winners: Vec<DbWinner>,
+    #[serde(default)]
+    pub no_winner_policy: Option<NoWinnerPolicy>,
+    #[serde(default)]
+    pub outcome: Option<i64>,
+    pub resolved_at: u64,
+    // set until the resolution is final, to when its dispute window closes. Payouts are held in the
+    // ledger as pending until then
+    #[serde(default)]
+    pub pending_until: Option<u64>,
+    // a disputed resolution only becomes final once an admin confirms it
+    #[serde(default)]
+    pub disputed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbWinner {
+    pub option: Thing,
+    pub weight: Option<u32>,
+}
+
+impl From<DbResolution> for common::Resolution {
+    fn from(value: DbResolution) -> Self {
+        Self {
+            winners: value.winners.into_iter().map(Into::into).collect(),
+            no_winner_policy: value.no_winner_policy,
+            outcome: value.outcome,
+            resolved_at: value.resolved_at,
+            pending_until: value.pending_until,
+            disputed: value.disputed,
+        }
+    }
+}
+
+impl From<DbWinner> for common::Winner {
+    fn from(value: DbWinner) -> Self {
+        Self {
+            option_id: value.option.id.to_string(),
+            weight: value.weight,
+        }
+    }
+}
+
 impl DbWager {
     pub fn new(name: impl Into<String>, description: impl Into<String>, pot: u64) -> Self {
         Self {
@@ -71,6 +160,18 @@ impl DbWager {
             name: name.into(),
             description: description.into(),
             pot,
+            status: WagerStatus::Open,
+            closes_at: None,
+            resolution: None,
+            rake: Rake::default(),
+            kind: WagerKind::PariMutuel,
+            no_winner_policy: NoWinnerPolicy::Refund,
+            stake_limits: StakeLimits::default(),
+            category: None,
+            game: None,
+            tags: vec![],
+            electorate: Electorate::Nobody,
+            vote_deadlocked: false,
             options: vec![],
         }
     }
@@ -82,7 +183,19 @@ impl Into<common::Wager> for DbWager {
             id: self.id.id.to_string(),
             name: self.name,
             description: self.description,
+            category: self.category,
+            game: self.game,
+            tags: self.tags,
             pot: self.pot,
+            status: self.status,
+            closes_at: self.closes_at,
+            resolution: self.resolution.map(Into::into),
+            rake: self.rake,
+            kind: self.kind,
+            no_winner_policy: self.no_winner_policy,
+            stake_limits: self.stake_limits,
+            electorate: self.electorate,
+            vote_deadlocked: self.vote_deadlocked,
             options: vec![],
         }
     }
@@ -100,9 +213,23 @@ pub struct DbWagerOption {
     pub name: String,
     pub description: String,
     pub wager: Thing,
+    #[serde(default)]
+    pub odds: Option<u32>,
+    #[serde(default)]
+    pub range: Option<OutcomeRange>,
+    #[serde(default)]
+    pub status: OptionStatus,
     pub bets: Vec<Thing>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum OptionStatus {
+    #[default]
+    Active,
+    // the option's wager was cancelled
+    Cancelled,
+}
+
 impl DbWagerOption {
     pub fn new(name: impl Into<String>, description: impl Into<String>, wager: Thing) -> Self {
         Self {
@@ -113,6 +240,9 @@ impl DbWagerOption {
             name: name.into(),
             description: description.into(),
             wager,
+            odds: None,
+            range: None,
+            status: OptionStatus::Active,
             bets: vec![],
         }
     }
@@ -124,17 +254,36 @@ impl Into<common::WagerOption> for DbWagerOption {
             id: self.id.id.to_string(),
             name: self.name,
             description: self.description,
+            odds: self.odds,
+            range: self.range,
+            stats: common::OptionStats::default(),
             bets: vec![],
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum BetStatus {
+    #[default]
+    Active,
+    // the stake was refunded because the wager was cancelled
+    Cancelled,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct DbBet {
     pub id: Thing,
     pub user: Thing,
     pub wager_option: Thing,
     pub val: u64,
+    #[serde(default)]
+    pub status: BetStatus,
+    #[serde(default)]
+    pub potential_payout: Option<u64>,
+    // the option's implied chance of winning when the bet was placed, in basis points. What the bet can
+    // be cashed out for is measured against it
+    #[serde(default)]
+    pub placed_probability: Option<u32>,
 }
 
 impl DbBet {
@@ -147,20 +296,434 @@ impl DbBet {
             user,
             wager_option,
             val,
+            status: BetStatus::Active,
+            potential_payout: None,
+            placed_probability: None,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbParlay {
+    pub id: Thing,
+    pub user: Thing,
+    pub stake: u64,
+    pub legs: Vec<DbParlayLeg>,
+    #[serde(default)]
+    pub status: ParlayStatus,
+    #[serde(default)]
+    pub payout: Option<u64>,
+    // the least the parlay pays if it wins, which counts against the liability of its fixed-odds options
+    #[serde(default)]
+    pub potential_payout: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbParlayLeg {
+    pub wager: Thing,
+    pub wager_option: Thing,
+    pub odds: Option<u32>,
+    #[serde(default)]
+    pub status: LegStatus,
+}
+
+impl DbParlay {
+    pub fn new(user: Thing, stake: u64, legs: Vec<DbParlayLeg>) -> Self {
+        Self {
+            id: Thing {
+                tb: TABLE_PARLAY.into(),
+                id: Id::rand(),
+            },
+            user,
+            stake,
+            legs,
+            status: ParlayStatus::Active,
+            payout: None,
+            potential_payout: 0,
+        }
+    }
+}
+
+impl From<DbParlay> for common::Parlay {
+    fn from(value: DbParlay) -> Self {
+        Self {
+            id: value.id.id.to_string(),
+            user_id: value.user.id.to_string(),
+            stake: value.stake,
+            legs: value
+                .legs
+                .into_iter()
+                .map(|leg| common::ParlayLeg {
+                    wager_id: leg.wager.id.to_string(),
+                    wager_option_id: leg.wager_option.id.to_string(),
+                    odds: leg.odds,
+                    status: leg.status,
+                })
+                .collect(),
+            status: value.status,
+            payout: value.payout,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbChallenge {
+    pub id: Thing,
+    pub challenger: Thing,
+    pub opponent: Thing,
+    pub stake: u64,
+    pub terms: String,
+    pub arbiter: Option<Thing>,
+    #[serde(default)]
+    pub status: ChallengeStatus,
+    #[serde(default)]
+    pub wager: Option<Thing>,
+    #[serde(default)]
+    pub winner: Option<Thing>,
+}
+
+impl DbChallenge {
+    pub fn new(challenger: Thing, opponent: Thing, stake: u64, terms: impl Into<String>, arbiter: Option<Thing>) -> Self {
+        Self {
+            id: Thing {
+                tb: TABLE_CHALLENGE.into(),
+                id: Id::rand(),
+            },
+            challenger,
+            opponent,
+            stake,
+            terms: terms.into(),
+            arbiter,
+            status: ChallengeStatus::Proposed,
+            wager: None,
+            winner: None,
+        }
+    }
+
+    // The locked wager that holds an accepted challenge's stakes, with an option and a bet for each side
+    fn escrow(&self) -> (DbWager, Vec<DbWagerOption>, Vec<DbBet>) {
+        let mut wager = DbWager::new(
+            format!("{} vs {}", self.challenger.id, self.opponent.id),
+            self.terms.clone(),
+            0,
+        );
+        wager.status = WagerStatus::Locked;
+        let (mut options, mut bets) = (Vec::new(), Vec::new());
+        for side in [&self.challenger, &self.opponent] {
+            let mut option = DbWagerOption::new(side.id.to_string(), side.id.to_string(), wager.id.clone());
+            let bet = DbBet::new(side.clone(), option.id.clone(), self.stake);
+            option.bets.push(bet.id.clone());
+            wager.options.push(option.id.clone());
+            options.push(option);
+            bets.push(bet);
+        }
+        (wager, options, bets)
+    }
+}
+
+impl From<DbChallenge> for common::Challenge {
+    fn from(value: DbChallenge) -> Self {
+        Self {
+            id: value.id.id.to_string(),
+            challenger_id: value.challenger.id.to_string(),
+            opponent_id: value.opponent.id.to_string(),
+            stake: value.stake,
+            terms: value.terms,
+            arbiter_id: value.arbiter.map(|arbiter| arbiter.id.to_string()),
+            status: value.status,
+            wager_id: value.wager.map(|wager| wager.id.to_string()),
+            winner_id: value.winner.map(|winner| winner.id.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbBracket {
+    pub id: Thing,
+    pub name: String,
+    pub format: BracketFormat,
+    pub matches: Vec<Thing>,
+}
+
+// A match in a bracket, played out on its own wager with an option for each side
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbBracketMatch {
+    pub id: Thing,
+    pub bracket: Thing,
+    pub name: String,
+    pub wager: Thing,
+    pub options: Vec<Thing>,
+    pub teams: Vec<Option<String>>,
+    // where the winner and loser go next. The loser only goes anywhere in double elimination
+    pub winner_to: Option<BracketSlot>,
+    pub loser_to: Option<BracketSlot>,
+}
+
+// A user's objection to a resolution that is not final yet
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbDispute {
+    pub id: Thing,
+    pub wager: Thing,
+    pub user: Thing,
+    pub reason: String,
+    pub filed_at: u64,
+}
+
+impl DbDispute {
+    pub fn new(wager: Thing, user: Thing, reason: impl Into<String>, filed_at: u64) -> Self {
+        Self {
+            id: Thing {
+                tb: TABLE_DISPUTE.into(),
+                id: Id::rand(),
+            },
+            wager,
+            user,
+            reason: reason.into(),
+            filed_at,
+        }
+    }
+}
+
+// A user's say in which option won a wager. Each user has at most one vote per wager
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbVote {
+    pub id: Thing,
+    pub wager: Thing,
+    pub user: Thing,
+    pub option: Thing,
+    pub cast_at: u64,
+}
+
+impl DbVote {
+    pub fn new(wager: Thing, user: Thing, option: Thing, cast_at: u64) -> Self {
+        Self {
+            id: Thing {
+                tb: TABLE_VOTE.into(),
+                id: Id::rand(),
+            },
+            wager,
+            user,
+            option,
+            cast_at,
+        }
+    }
+}
+
+// Ties a game client's state posts to a wager. The id doubles as the token the client authenticates with
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbGsiLink {
+    pub id: Thing,
+    pub wager: Thing,
+    pub game: GsiGame,
+    pub map_name: Option<String>,
+    pub match_id: Option<String>,
+    pub teams: Vec<DbGsiTeam>,
+    // a link only resolves its wager once, so the posts that keep coming after a game ends cannot resolve
+    // it again after an admin overturns the result
+    #[serde(default)]
+    pub spent: bool,
+}
+
+// `team` is kept lowercase
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DbGsiTeam {
+    pub team: String,
+    pub option: Thing,
+}
+
+impl DbGsiLink {
+    pub fn new(wager: Thing, link: common::GsiLink) -> Self {
+        Self {
+            id: Thing {
+                tb: TABLE_GSI_LINK.into(),
+                id: Id::rand(),
+            },
+            wager,
+            game: link.game,
+            map_name: link.map_name.map(|map_name| map_name.trim().to_lowercase()),
+            match_id: link.match_id.map(|match_id| match_id.trim().to_string()),
+            teams: link
+                .teams
+                .into_iter()
+                .map(|team| DbGsiTeam {
+                    team: team.team.trim().to_lowercase(),
+                    option: Thing {
+                        tb: TABLE_WAGER_OPTION.into(),
+                        id: Id::String(team.wager_option_id),
+                    },
+                })
+                .collect(),
+            spent: false,
+        }
+    }
+}
+
+// One side of a bracket match
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BracketSlot {
+    pub bracket_match: Thing,
+    pub slot: usize,
+}
+
+impl From<DbBracketMatch> for common::BracketMatch {
+    fn from(value: DbBracketMatch) -> Self {
+        Self {
+            id: value.id.id.to_string(),
+            name: value.name,
+            wager_id: value.wager.id.to_string(),
+            teams: value.teams,
+        }
+    }
+}
+
+// Everything that makes up a new bracket, to be created in one go
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BracketLayout {
+    pub bracket: DbBracket,
+    pub matches: Vec<DbBracketMatch>,
+    pub wagers: Vec<DbWager>,
+    pub options: Vec<DbWagerOption>,
+}
+
+// A parlay after one of its legs settled, and the money that moves because of it
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ParlaySettlement {
+    pub parlay: DbParlay,
+    // paid to the parlay's owner
+    pub payout: u64,
+    // what the house made or lost backing the parlay
+    pub house: i64,
+}
+
+// What was paid to a single bet on a wager, and the option it was on
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct OptionPayout {
+    pub option: Thing,
+    pub amount: i64,
+}
+
+// Money owned by the server itself rather than any user, such as the house or the jackpot fund, lives
+// in the account table. Unlike a user, the house can go into debt
+pub fn account_id(name: &str) -> Thing {
+    Thing {
+        tb: TABLE_ACCOUNT.into(),
+        id: Id::String(name.into()),
+    }
+}
+
+pub fn rake_account(destination: RakeDestination) -> Thing {
+    match destination {
+        RakeDestination::House => account_id(ACCOUNT_HOUSE),
+        RakeDestination::Jackpot => account_id(ACCOUNT_JACKPOT),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LedgerEntryKind {
+    Payout,
+    Rake,
+    // what the house won or lost covering the other side of a fixed-odds wager, or took because
+    // nobody backed a winner
+    HouseSettlement,
+    // money carried over into the pot of another wager
+    Rollover,
+    ParlayPayout,
+    // a bet sold back before its wager locked. The user is paid the price and the house keeps the rest
+    // of the stake
+    CashOut,
+}
+
+// Money moved into (or, when negative, out of) an account when a wager is resolved
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct AccountPayment {
+    pub account: Thing,
+    pub amount: i64,
+    pub kind: LedgerEntryKind,
+}
+
+// What a single winning bet is paid when its wager is resolved
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BetPayout {
+    pub bet: Thing,
+    pub amount: u64,
+}
+
+// Money added to the pot of another wager when a wager is resolved
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PotRollover {
+    pub wager: Thing,
+    pub amount: u64,
+}
+
+// Everything a resolution pays out, and where to
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct Settlement {
+    pub payouts: Vec<BetPayout>,
+    pub account_payments: Vec<AccountPayment>,
+    pub rollover: Option<PotRollover>,
+}
+
 impl Into<common::Bet> for DbBet {
     fn into(self) -> common::Bet {
         common::Bet {
             id: self.id.id.to_string(),
-            user_id: self.id.id.to_string(),
+            user_id: self.user.id.to_string(),
             val: self.val,
+            potential_payout: self.potential_payout,
         }
     }
 }
 
+// A failed transaction reports every statement that did not run as `QueryNotExecuted`. This pulls
+// out the statement that actually failed (usually a `THROW`), so callers get a meaningful error
+fn take_transaction_error(response: &mut Response) -> Option<surrealdb::Error> {
+    let mut errors: Vec<(usize, surrealdb::Error)> = response.take_errors().into_iter().collect();
+    errors.sort_by_key(|(index, _)| *index);
+    let position = errors
+        .iter()
+        .position(|(_, error)| !matches!(error, surrealdb::Error::Db(DbError::QueryNotExecuted)))
+        .unwrap_or(0);
+    (!errors.is_empty()).then(|| errors.swap_remove(position).1)
+}
+
+// Guards a status change of `$wager`, which must exist and currently be in one of `$allowed_from`
+const WAGER_TRANSITION_GUARD: &str = "IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };
+    IF ($wager.status ?? \"Open\") NOTINSIDE $allowed_from { THROW \"illegal wager status transition\" };";
+
+// Guards a change to `$bet` by `$user`, who must own it, while its wager is still taking bets
+const BET_OWNER_GUARD: &str = "IF (SELECT * FROM ONLY $bet) = NONE { THROW \"no such bet\" };
+    IF $bet.user != $user { THROW \"only the owner of a bet can change it\" };
+    IF ($bet.status ?? \"Active\") != \"Active\" { THROW \"bet is no longer active\" };";
+
+const OPEN_BET_GUARD: &str = "IF ($bet.wager_option.wager.status ?? \"Open\") != \"Open\" { THROW \"wager is not open for betting\" };
+    IF $bet.wager_option.wager.closes_at != NONE AND $bet.wager_option.wager.closes_at <= time::unix(time::now()) { THROW \"betting on this wager has closed\" };";
+
+const PENDING_RESOLUTION_GUARD: &str = "IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };
+    IF ($wager.status ?? \"Open\") != \"Resolved\" OR $wager.resolution.pending_until = NONE { THROW \"wager has no pending resolution\" };";
+
+// Refunds `$bet` to whoever placed it and deletes it
+const BET_REFUND: &str = "LET $refunded = (SELECT * FROM ONLY $bet);
+    IF $refunded = NONE { THROW \"no such bet\" };
+    LET $refunded_option = $refunded.wager_option;
+    UPDATE $refunded_option SET bets = array::remove(bets, array::find_index(bets, $bet));
+    LET $refunded_user = $refunded.user;
+    UPDATE $refunded_user SET balance += $refunded.val;
+    DELETE $bet;";
+
+// What the house could lose if `$option` wins: the payouts owed on it by fixed-odds bets and by active
+// parlays with a leg on it, less the stakes of those parlays and of every single bet on its wager
+const OPTION_LIABILITY: &str = "LET $option_payouts = math::sum((SELECT VALUE potential_payout FROM bet WHERE wager_option = $option AND (status ?? \"Active\") = \"Active\"));
+    LET $parlay_payouts = math::sum((SELECT VALUE (potential_payout ?? stake) - stake FROM parlay WHERE (status ?? \"Active\") = \"Active\" AND $option INSIDE legs.wager_option));
+    LET $wager_stakes = math::sum((SELECT VALUE val FROM bet WHERE wager_option.wager = $option.wager AND (status ?? \"Active\") = \"Active\"));
+    LET $liability = $option_payouts + $parlay_payouts - $wager_stakes;";
+
+fn allowed_transitions_to(status: WagerStatus) -> Vec<WagerStatus> {
+    WagerStatus::ALL
+        .into_iter()
+        .filter(|from| from.can_transition_to(status))
+        .collect()
+}
+
 pub struct DatabaseConnection<Type: Connection> {
     connection: Surreal<Type>,
 }
@@ -177,7 +740,9 @@ impl DatabaseConnection<Client> {
 
         db.use_ns("test").use_db("lan_bet").await?;
 
-        Ok(Self { connection: db })
+        let connection = Self { connection: db };
+        connection.define_indexes().await?;
+        Ok(connection)
     }
 }
 
@@ -187,11 +752,26 @@ impl DatabaseConnection<Db> {
 
         connection.use_ns("test").use_db("lan_bet").await?;
 
-        Ok(Self { connection })
+        let connection = Self { connection };
+        connection.define_indexes().await?;
+        Ok(connection)
     }
 }
 
 impl<Type: Connection> DatabaseConnection<Type> {
+    // Indexes for the fields wagers are filtered on, and to keep users to one vote per wager. Defining an
+    // index again just replaces it
+    async fn define_indexes(&self) -> Result<()> {
+        self.connection
+            .query("DEFINE INDEX wager_category ON TABLE wager COLUMNS category;")
+            .query("DEFINE INDEX wager_game ON TABLE wager COLUMNS game;")
+            .query("DEFINE INDEX wager_tags ON TABLE wager COLUMNS tags;")
+            .query("DEFINE INDEX vote_voter ON TABLE vote COLUMNS wager, user UNIQUE;")
+            .await?
+            .check()?;
+        Ok(())
+    }
+
     pub async fn select<FetchedType: DeserializeOwned>(
         &self,
         id: &Thing,
@@ -226,7 +806,8 @@ impl<Type: Connection> DatabaseConnection<Type> {
             tb: TABLE_WAGER_OPTION.into(),
             id: Id::String(wager_id.into()),
         };
-        let wager_option = DbWagerOption::new(&option.name, &option.description, wager_id);
+        let mut wager_option = DbWagerOption::new(&option.name, &option.description, wager_id);
+        wager_option.range = option.range;
 
         self.add_wager_option_db(&wager_option).await
     }
@@ -235,7 +816,7 @@ impl<Type: Connection> DatabaseConnection<Type> {
         debug_assert_eq!(&option.id.tb, &TABLE_WAGER_OPTION.to_string());
         let mut response = self.connection
             .query(BeginStatement)
-            .query("CREATE $id SET name = $name, description = $description, wager = $wager, bets = $bets")
+            .query("CREATE $id SET name = $name, description = $description, wager = $wager, odds = $odds, range = $range, bets = $bets")
             .bind(&option)
             .query("UPDATE $wager SET options = array::add($wager.options, $id);")
             .bind(("id", &option.id))
@@ -246,6 +827,25 @@ impl<Type: Connection> DatabaseConnection<Type> {
         Ok(response.take(0)?)
     }
 
+    // Creates a wager together with all of its options in one transaction
+    pub async fn create_wager(&mut self, wager: &DbWager, options: &[DbWagerOption]) -> Result<()> {
+        debug_assert_eq!(&wager.id.tb, &TABLE_WAGER.to_string());
+        debug_assert!(options.iter().all(|option| option.wager == wager.id));
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("CREATE $wager.id CONTENT $wager;")
+            .query("FOR $option IN $options { CREATE $option.id CONTENT $option; };")
+            .query(CommitStatement)
+            .bind(("wager", wager))
+            .bind(("options", options))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
     pub async fn add_bet(
         &mut self,
         bet: &common::Bet,
@@ -269,19 +869,751 @@ impl<Type: Connection> DatabaseConnection<Type> {
         let mut response = self
             .connection
             .query(BeginStatement)
-            .query("CREATE $id SET user = $user, wager_option = $wager_option, val = $val;")
+            .query("CREATE $id SET user = $user, wager_option = $wager_option, val = $val, status = $status;")
             .bind(&bet)
             .query("UPDATE $wager_option SET bets = array::add($wager_option.bets, $id);")
             .bind(("id", &bet.id))
             .bind(("wager_option", &bet.wager_option))
             .query(CommitStatement)
             .await?;
-
-        Ok(response.take(0)?)
+
+        Ok(response.take(0)?)
+    }
+
+    // Debits the stake from the betting user and creates the bet in one transaction, so a bet
+    // can never exist without having been paid for. Returns the user's new balance and the bet.
+    // A bet on a fixed-odds wager must carry the payout for the option's current odds, and is refused
+    // if covering it could cost the house more than `max_liability` should that option win
+    pub async fn place_bet(&mut self, bet: &DbBet, max_liability: Option<u64>) -> Result<(u64, DbBet)> {
+        debug_assert_eq!(&bet.id.tb, &TABLE_BET.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager_option) = NONE { THROW \"no such wager option\" };")
+            .query("IF ($wager_option.wager.status ?? \"Open\") != \"Open\" { THROW \"wager is not open for betting\" };")
+            .query("IF $wager_option.wager.closes_at != NONE AND $wager_option.wager.closes_at <= time::unix(time::now()) { THROW \"betting on this wager has closed\" };")
+            .query("IF ($wager_option.wager.kind ?? \"PariMutuel\") = \"FixedOdds\" {
+                IF $wager_option.odds = NONE { THROW \"wager option has no odds\" };
+                IF $potential_payout = NONE OR $potential_payout * 100 > $val * $wager_option.odds OR ($potential_payout + 1) * 100 <= $val * $wager_option.odds { THROW \"the odds have changed\" };
+            } ELSE IF $potential_payout != NONE { THROW \"only fixed-odds bets have a potential payout\" };")
+            .query(format!("IF $max_liability != NONE AND $potential_payout != NONE {{
+                LET $option = $wager_option;
+                {OPTION_LIABILITY}
+                IF $liability + $potential_payout - $val > $max_liability {{ THROW \"bet would exceed the maximum liability for this option\" }};
+            }};"))
+            .query("LET $balance = (SELECT VALUE balance FROM ONLY $user);")
+            .query("IF $balance = NONE { THROW \"no such user\" };")
+            .query("IF $val <= 0 { THROW \"bet value must be positive\" };")
+            .query("IF $balance < $val { THROW \"insufficient balance\" };")
+            .query("UPDATE $wager_option SET bets = array::add($wager_option.bets, $id);")
+            .query("UPDATE $user SET balance -= $val;")
+            .query("CREATE $id SET user = $user, wager_option = $wager_option, val = $val, status = $status, potential_payout = $potential_payout, placed_probability = $placed_probability;")
+            .query(CommitStatement)
+            .bind(bet)
+            .bind(("max_liability", max_liability))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let user: Option<DbUser> = response.take(last - 1)?;
+        let created: Option<DbBet> = response.take(last)?;
+        match (user, created) {
+            (Some(user), Some(created)) => Ok((user.balance, created)),
+            _ => Err(DbError::NoRecordFound.into()),
+        }
+    }
+
+    // Moves a wager to a new lifecycle status, rejecting any transition the status machine does not allow.
+    // The check happens inside the transaction so two racing transitions cannot both succeed
+    pub async fn set_wager_status(&mut self, wager_id: &Thing, status: WagerStatus) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(WAGER_TRANSITION_GUARD)
+            .query("UPDATE $wager SET status = $status;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("allowed_from", allowed_transitions_to(status)))
+            .bind(("status", status))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Reopens a locked wager for betting with a new closing time, or none to leave it open until it is
+    // locked by hand. The old closing time has usually passed, which would lock it again straight away
+    pub async fn reopen_wager(&mut self, wager_id: &Thing, closes_at: Option<u64>) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(WAGER_TRANSITION_GUARD)
+            .query("IF $closes_at != NONE AND $closes_at <= time::unix(time::now()) { THROW \"a wager cannot close in the past\" };")
+            .query("UPDATE $wager SET status = \"Open\", closes_at = $closes_at;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("allowed_from", allowed_transitions_to(WagerStatus::Open)))
+            .bind(("closes_at", closes_at))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Cancels a wager and refunds every active stake on it in one transaction. Unlike `remove_wager`
+    // nothing is deleted: the wager, its options and its bets are kept, marked as cancelled, so they can
+    // be audited
+    pub async fn cancel_wager(&mut self, wager_id: &Thing) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(WAGER_TRANSITION_GUARD)
+            .query("LET $active_bets = (SELECT * FROM bet WHERE wager_option.wager = $wager AND (status ?? \"Active\") = \"Active\");")
+            .query("FOR $bet IN $active_bets { LET $user = $bet.user; UPDATE $user SET balance += $bet.val; };")
+            .query("UPDATE $active_bets.id SET status = \"Cancelled\";")
+            .query("UPDATE wager_option SET status = \"Cancelled\" WHERE wager = $wager;")
+            .query("UPDATE $wager SET status = \"Cancelled\";")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("allowed_from", allowed_transitions_to(WagerStatus::Cancelled)))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Pays out every bet, account (such as the rake) and rollover in the settlement, records them in the
+    // ledger, and records the resolution in one transaction, so a resolution either completes fully or
+    // leaves no trace at all. A resolution that is still pending only records the ledger entries, which
+    // are paid out by `finalise_resolution`
+    pub async fn resolve_wager(
+        &mut self,
+        wager_id: &Thing,
+        resolution: &DbResolution,
+        settlement: &Settlement,
+    ) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(WAGER_TRANSITION_GUARD)
+            .query("FOR $payout IN $payouts {
+                IF $payout.bet.wager_option.wager != $wager { THROW \"payout for a bet on another wager\" };
+                LET $user = $payout.bet.user;
+                IF !$pending { UPDATE $user SET balance += $payout.amount; };
+                CREATE ledger SET account = $user, wager = $wager, bet = $payout.bet, kind = $payout_kind, amount = $payout.amount, created_at = $resolution.resolved_at, pending = $pending;
+            };")
+            .query("FOR $payment IN $account_payments {
+                IF $payment.amount != 0 {
+                    LET $account = $payment.account;
+                    IF !$pending { UPDATE $account SET balance += $payment.amount; };
+                    CREATE ledger SET account = $account, wager = $wager, bet = NONE, kind = $payment.kind, amount = $payment.amount, created_at = $resolution.resolved_at, pending = $pending;
+                };
+            };")
+            .query("IF $rollover != NONE {
+                LET $target = $rollover.wager;
+                IF $target = $wager { THROW \"cannot roll a wager over into itself\" };
+                IF (SELECT * FROM ONLY $target) = NONE { THROW \"no such wager to roll over into\" };
+                IF ($target.status ?? \"Open\") NOTINSIDE [\"Open\", \"Locked\"] { THROW \"can only roll over into an unresolved wager\" };
+                IF !$pending { UPDATE $target SET pot += $rollover.amount; };
+                CREATE ledger SET account = $target, wager = $wager, bet = NONE, kind = $rollover_kind, amount = $rollover.amount, created_at = $resolution.resolved_at, pending = $pending;
+            };")
+            .query("UPDATE $wager SET status = \"Resolved\", resolution = $resolution;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("allowed_from", allowed_transitions_to(WagerStatus::Resolved)))
+            .bind(("resolution", resolution))
+            .bind(("pending", resolution.pending_until.is_some()))
+            .bind(("payouts", &settlement.payouts))
+            .bind(("account_payments", &settlement.account_payments))
+            .bind(("rollover", &settlement.rollover))
+            .bind(("payout_kind", LedgerEntryKind::Payout))
+            .bind(("rollover_kind", LedgerEntryKind::Rollover))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Pays out everything a pending resolution held back in the ledger and makes the resolution final.
+    // Money rolled over goes into the other wager's pot rather than a balance. If that wager finished
+    // during the dispute window its pot would never be paid out, so the resolution has to be overturned
+    // and settled another way instead
+    pub async fn finalise_resolution(&mut self, wager_id: &Thing) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(PENDING_RESOLUTION_GUARD)
+            .query("FOR $entry IN (SELECT * FROM ledger WHERE wager = $wager AND pending = true) {
+                LET $account = $entry.account;
+                IF meta::tb($account) = $wager_table {
+                    IF ($account.status ?? \"Open\") NOTINSIDE [\"Open\", \"Locked\"] { THROW \"the wager this resolution rolls over into has finished\" };
+                    UPDATE $account SET pot += $entry.amount;
+                } ELSE {
+                    UPDATE $account SET balance += $entry.amount;
+                };
+                LET $held = $entry.id;
+                UPDATE $held SET pending = false;
+            };")
+            .query("UPDATE $wager SET resolution.pending_until = NONE;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("wager_table", TABLE_WAGER))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Throws away a pending resolution and everything it held back, leaving the wager locked so it can
+    // be resolved again. Any votes that led to it are thrown away too, and a challenge settled by it goes
+    // back to waiting to be settled
+    pub async fn overturn_resolution(&mut self, wager_id: &Thing) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(PENDING_RESOLUTION_GUARD)
+            .query("DELETE ledger WHERE wager = $wager AND pending = true;")
+            .query("DELETE vote WHERE wager = $wager;")
+            .query("UPDATE $wager SET status = \"Locked\", resolution = NONE, vote_deadlocked = false;")
+            .query("UPDATE challenge SET status = \"Accepted\", winner = NONE WHERE wager = $wager AND status = \"Settled\";")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Records a dispute against a pending resolution, as long as its dispute window is still open, and
+    // marks the resolution as disputed
+    pub async fn add_dispute(&mut self, dispute: &DbDispute) -> Result<()> {
+        debug_assert_eq!(&dispute.id.tb, &TABLE_DISPUTE.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(PENDING_RESOLUTION_GUARD)
+            .query("IF $wager.resolution.pending_until <= $dispute.filed_at { THROW \"the dispute window has closed\" };")
+            .query("CREATE $dispute.id CONTENT $dispute;")
+            .query("UPDATE $wager SET resolution.disputed = true;")
+            .query(CommitStatement)
+            .bind(("wager", &dispute.wager))
+            .bind(("dispute", dispute))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Records a vote on the outcome of a locked wager, replacing any earlier vote by the same user, and
+    // returns every vote cast on the wager so far
+    pub async fn cast_vote(&mut self, vote: &DbVote) -> Result<Vec<DbVote>> {
+        debug_assert_eq!(&vote.id.tb, &TABLE_VOTE.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };")
+            .query("IF ($wager.status ?? \"Open\") != \"Locked\" { THROW \"only locked wagers can be voted on\" };")
+            .query("IF (SELECT * FROM ONLY $option) = NONE OR $option.wager != $wager { THROW \"option is not part of this wager\" };")
+            .query("DELETE vote WHERE wager = $wager AND user = $vote.user;")
+            .query("CREATE $vote.id CONTENT $vote;")
+            .query("SELECT * FROM vote WHERE wager = $wager;")
+            .query(CommitStatement)
+            .bind(("wager", &vote.wager))
+            .bind(("option", &vote.option))
+            .bind(("vote", vote))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        response.take(last)
+    }
+
+    pub async fn set_vote_deadlocked(&mut self, wager_id: &Thing, deadlocked: bool) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        self.connection
+            .query("UPDATE $wager SET vote_deadlocked = $deadlocked;")
+            .bind(("wager", wager_id))
+            .bind(("deadlocked", deadlocked))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    pub async fn get_votes_for_wager(&self, wager_id: &Thing) -> Result<Vec<DbVote>> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        self.connection
+            .query("SELECT * FROM vote WHERE wager = $wager;")
+            .bind(("wager", wager_id))
+            .await?
+            .take(0)
+    }
+
+    // Every vote on a locked wager whose voters have neither settled it nor deadlocked yet
+    pub async fn get_undecided_votes(&self) -> Result<Vec<DbVote>> {
+        self.connection
+            .query("SELECT * FROM vote WHERE wager.status = \"Locked\" AND (wager.vote_deadlocked ?? false) = false;")
+            .await?
+            .take(0)
+    }
+
+    pub async fn get_admins(&self) -> Result<Vec<DbUser>> {
+        self.connection
+            .query("SELECT * FROM user WHERE admin = true;")
+            .await?
+            .take(0)
+    }
+
+    // Links game state posts to a wager that has not been settled yet. Every team needs a different name
+    // and an option of the wager
+    pub async fn add_gsi_link(&mut self, link: &DbGsiLink) -> Result<()> {
+        debug_assert_eq!(&link.id.tb, &TABLE_GSI_LINK.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };")
+            .query("IF ($wager.status ?? \"Open\") NOTINSIDE [\"Open\", \"Locked\"] { THROW \"wager has already been settled\" };")
+            .query("IF array::len($link.teams) < 2 { THROW \"a link needs at least two teams\" };")
+            .query("IF array::len(array::distinct($link.teams.team)) != array::len($link.teams) { THROW \"every team needs a different name\" };")
+            .query("FOR $team IN $link.teams {
+                LET $option = $team.option;
+                IF $team.team = \"\" { THROW \"every team needs a name\" };
+                IF (SELECT * FROM ONLY $option) = NONE OR $option.wager != $wager { THROW \"option is not part of this wager\" };
+            };")
+            .query("CREATE $link.id CONTENT $link;")
+            .query(CommitStatement)
+            .bind(("wager", &link.wager))
+            .bind(("link", link))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    pub async fn spend_gsi_link(&mut self, link_id: &Thing) -> Result<()> {
+        debug_assert_eq!(&link_id.tb, &TABLE_GSI_LINK.to_string());
+        self.connection
+            .query("UPDATE $link SET spent = true;")
+            .bind(("link", link_id))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    // Resolved wagers whose resolution is not final yet
+    pub async fn get_pending_resolutions(&self) -> Result<Vec<DbWager>> {
+        self.connection
+            .query("SELECT * FROM wager WHERE status = \"Resolved\" AND resolution.pending_until != NONE;")
+            .await?
+            .take(0)
+    }
+
+    // Sets the odds future bets on a fixed-odds wager are placed at. Bets already placed keep their payout
+    pub async fn set_option_odds(&mut self, option_id: &Thing, odds: u32) -> Result<()> {
+        debug_assert_eq!(&option_id.tb, &TABLE_WAGER_OPTION.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager_option) = NONE { THROW \"no such wager option\" };")
+            .query("IF ($wager_option.wager.kind ?? \"PariMutuel\") != \"FixedOdds\" { THROW \"only fixed-odds wagers have odds\" };")
+            .query("IF ($wager_option.wager.status ?? \"Open\") != \"Open\" { THROW \"wager is not open for betting\" };")
+            .query("IF $odds <= 100 { THROW \"odds must pay out more than the stake\" };")
+            .query("UPDATE $wager_option SET odds = $odds;")
+            .query(CommitStatement)
+            .bind(("wager_option", option_id))
+            .bind(("odds", odds))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Changes whichever of the name, description and pot are given, as long as the wager is still open
+    pub async fn edit_wager(
+        &mut self,
+        wager_id: &Thing,
+        name: Option<String>,
+        description: Option<String>,
+        pot: Option<u64>,
+    ) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };")
+            .query("IF ($wager.status ?? \"Open\") != \"Open\" { THROW \"only open wagers can be edited\" };")
+            .query("UPDATE $wager SET name = $name ?? name, description = $description ?? description, pot = $pot ?? pot;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("name", name))
+            .bind(("description", description))
+            .bind(("pot", pot))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Changes whichever of an option's name and description are given, as long as its wager is still open
+    pub async fn edit_wager_option(&mut self, option_id: &Thing, name: Option<String>, description: Option<String>) -> Result<()> {
+        debug_assert_eq!(&option_id.tb, &TABLE_WAGER_OPTION.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager_option) = NONE { THROW \"no such wager option\" };")
+            .query("IF ($wager_option.wager.status ?? \"Open\") != \"Open\" { THROW \"only open wagers can be edited\" };")
+            .query("UPDATE $wager_option SET name = $name ?? name, description = $description ?? description;")
+            .query(CommitStatement)
+            .bind(("wager_option", option_id))
+            .bind(("name", name))
+            .bind(("description", description))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    pub async fn set_stake_limits(&mut self, wager_id: &Thing, limits: StakeLimits) -> Result<()> {
+        debug_assert_eq!(&wager_id.tb, &TABLE_WAGER.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager) = NONE { THROW \"no such wager\" };")
+            .query("IF ($wager.status ?? \"Open\") != \"Open\" { THROW \"stake limits can only be changed while a wager is open\" };")
+            .query("IF $limits.min_stake != NONE AND $limits.max_stake != NONE AND $limits.min_stake > $limits.max_stake { THROW \"minimum stake is above the maximum\" };")
+            .query("UPDATE $wager SET stake_limits = $limits;")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("limits", limits))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Debits the stake and creates the parlay in one transaction. Every leg must be on a different wager
+    // that is still taking bets, and fixed-odds legs must carry their option's current odds. Like a single
+    // bet, the parlay is refused if paying its potential payout on top of what the option of any of its
+    // fixed-odds legs already owes could cost the house more than `max_liability`.
+    // Returns the user's new balance and the parlay
+    pub async fn place_parlay(&mut self, parlay: &DbParlay, max_liability: Option<u64>) -> Result<(u64, DbParlay)> {
+        debug_assert_eq!(&parlay.id.tb, &TABLE_PARLAY.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF array::len($legs) < 2 { THROW \"a parlay needs at least two legs\" };")
+            .query("IF array::len(array::distinct($legs.wager)) != array::len($legs) { THROW \"every leg must be on a different wager\" };")
+            .query(format!("FOR $leg IN $legs {{
+                LET $option = $leg.wager_option;
+                IF (SELECT * FROM ONLY $option) = NONE {{ THROW \"no such wager option\" }};
+                IF $option.wager != $leg.wager {{ THROW \"leg is on the wrong wager\" }};
+                IF ($option.wager.status ?? \"Open\") != \"Open\" {{ THROW \"wager is not open for betting\" }};
+                IF $option.wager.closes_at != NONE AND $option.wager.closes_at <= time::unix(time::now()) {{ THROW \"betting on this wager has closed\" }};
+                IF ($option.wager.kind ?? \"PariMutuel\") = \"FixedOdds\" {{
+                    IF $option.odds = NONE OR $option.odds != $leg.odds {{ THROW \"the odds have changed\" }};
+                    IF $max_liability != NONE {{
+                        {OPTION_LIABILITY}
+                        IF $liability + $potential_payout - $stake > $max_liability {{ THROW \"parlay would exceed the maximum liability for one of its options\" }};
+                    }};
+                }} ELSE IF $leg.odds != NONE {{ THROW \"only fixed-odds legs have odds\" }};
+            }};"))
+            .query("LET $balance = (SELECT VALUE balance FROM ONLY $user);")
+            .query("IF $balance = NONE { THROW \"no such user\" };")
+            .query("IF $stake <= 0 { THROW \"stake must be positive\" };")
+            .query("IF $balance < $stake { THROW \"insufficient balance\" };")
+            .query("UPDATE $user SET balance -= $stake;")
+            .query("CREATE $id SET user = $user, stake = $stake, legs = $legs, status = $status, payout = NONE, potential_payout = $potential_payout;")
+            .query(CommitStatement)
+            .bind(parlay)
+            .bind(("max_liability", max_liability))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let user: Option<DbUser> = response.take(last - 1)?;
+        let created: Option<DbParlay> = response.take(last)?;
+        match (user, created) {
+            (Some(user), Some(created)) => Ok((user.balance, created)),
+            _ => Err(DbError::NoRecordFound.into()),
+        }
+    }
+
+    pub async fn get_active_parlays(&self) -> Result<Vec<DbParlay>> {
+        self.connection
+            .query("SELECT * FROM parlay WHERE (status ?? \"Active\") = \"Active\";")
+            .await?
+            .take(0)
+    }
+
+    // Stores parlays whose legs were settled by `wager`, and pays out any that finished, in one
+    // transaction. A parlay that was already settled is never paid again
+    pub async fn settle_parlays(&mut self, wager_id: &Thing, settlements: &[ParlaySettlement], settled_at: u64) -> Result<()> {
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("FOR $settlement IN $settlements {
+                LET $parlay = $settlement.parlay.id;
+                IF ($parlay.status ?? \"Active\") != \"Active\" { THROW \"parlay is already settled\" };
+                UPDATE $parlay SET legs = $settlement.parlay.legs, status = $settlement.parlay.status, payout = $settlement.parlay.payout;
+                IF $settlement.payout > 0 {
+                    LET $user = $settlement.parlay.user;
+                    UPDATE $user SET balance += $settlement.payout;
+                    CREATE ledger SET account = $user, wager = $wager, bet = NONE, parlay = $parlay, kind = $payout_kind, amount = $settlement.payout, created_at = $settled_at;
+                };
+                IF $settlement.house != 0 {
+                    UPDATE $house SET balance += $settlement.house;
+                    CREATE ledger SET account = $house, wager = $wager, bet = NONE, parlay = $parlay, kind = $house_kind, amount = $settlement.house, created_at = $settled_at;
+                };
+            };")
+            .query(CommitStatement)
+            .bind(("wager", wager_id))
+            .bind(("settlements", settlements))
+            .bind(("settled_at", settled_at))
+            .bind(("house", account_id(ACCOUNT_HOUSE)))
+            .bind(("payout_kind", LedgerEntryKind::ParlayPayout))
+            .bind(("house_kind", LedgerEntryKind::HouseSettlement))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Every payout made to a bet when `wager` was resolved, along with the option the bet was on
+    pub async fn get_payouts_for_wager(&self, wager_id: &Thing) -> Result<Vec<OptionPayout>> {
+        self.connection
+            .query("SELECT bet.wager_option AS option, amount FROM ledger WHERE wager = $wager AND kind = $payout_kind AND bet != NONE;")
+            .bind(("wager", wager_id))
+            .bind(("payout_kind", LedgerEntryKind::Payout))
+            .await?
+            .take(0)
+    }
+
+    pub async fn propose_challenge(&mut self, challenge: &DbChallenge) -> Result<DbChallenge> {
+        debug_assert_eq!(&challenge.id.tb, &TABLE_CHALLENGE.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $opponent) = NONE { THROW \"no such opponent\" };")
+            .query("IF $opponent = $challenger { THROW \"users cannot challenge themselves\" };")
+            .query("IF $arbiter != NONE AND (SELECT * FROM ONLY $arbiter) = NONE { THROW \"no such arbiter\" };")
+            .query("IF $arbiter INSIDE [$challenger, $opponent] { THROW \"the arbiter cannot be one of the players\" };")
+            .query("IF $stake <= 0 { THROW \"stake must be positive\" };")
+            .query("CREATE $id SET challenger = $challenger, opponent = $opponent, stake = $stake, terms = $terms, arbiter = $arbiter, status = $status, wager = NONE, winner = NONE;")
+            .query(CommitStatement)
+            .bind(challenge)
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let created: Option<DbChallenge> = response.take(last)?;
+        created.ok_or(DbError::NoRecordFound.into())
+    }
+
+    // Accepts a challenge on behalf of `user`, who must be its opponent, moving both stakes into escrow
+    // in one transaction. Returns the user's new balance and the accepted challenge
+    pub async fn accept_challenge(&mut self, challenge_id: &Thing, user: &Thing) -> Result<(u64, DbChallenge)> {
+        let challenge: DbChallenge = self.select(challenge_id).await?.ok_or(DbError::NoRecordFound)?;
+        let (wager, options, bets) = challenge.escrow();
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF ($challenge.status ?? \"Proposed\") != \"Proposed\" { THROW \"challenge is no longer open\" };")
+            .query("IF $challenge.opponent != $user { THROW \"only the challenged user can accept a challenge\" };")
+            .query("FOR $bet IN $bets {
+                LET $bettor = $bet.user;
+                IF ($bettor.balance ?? 0) < $bet.val { THROW \"insufficient balance\" };
+                UPDATE $bettor SET balance -= $bet.val;
+                CREATE $bet.id SET user = $bettor, wager_option = $bet.wager_option, val = $bet.val, status = $bet.status;
+            };")
+            .query("FOR $option IN $options { CREATE $option.id CONTENT $option; };")
+            .query("CREATE $wager.id CONTENT $wager;")
+            .query("UPDATE $challenge.id SET status = \"Accepted\", wager = $wager.id;")
+            .query("SELECT * FROM ONLY $user;")
+            .query(CommitStatement)
+            .bind(("challenge", challenge_id))
+            .bind(("user", user))
+            .bind(("bets", bets))
+            .bind(("options", options))
+            .bind(("wager", wager))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let accepted: Option<DbChallenge> = response.take(last - 1)?;
+        let user: Option<DbUser> = response.take(last)?;
+        match (user, accepted) {
+            (Some(user), Some(accepted)) => Ok((user.balance, accepted)),
+            _ => Err(DbError::NoRecordFound.into()),
+        }
+    }
+
+    // Declines a challenge that has not been accepted yet. Either side may do so
+    pub async fn decline_challenge(&mut self, challenge_id: &Thing, user: &Thing) -> Result<()> {
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $challenge) = NONE { THROW \"no such challenge\" };")
+            .query("IF ($challenge.status ?? \"Proposed\") != \"Proposed\" { THROW \"challenge is no longer open\" };")
+            .query("IF $user NOTINSIDE [$challenge.challenger, $challenge.opponent] { THROW \"only the players can decline a challenge\" };")
+            .query("UPDATE $challenge SET status = \"Declined\";")
+            .query(CommitStatement)
+            .bind(("challenge", challenge_id))
+            .bind(("user", user))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Records how an accepted challenge was settled. The stakes are paid out through its wager
+    pub async fn finish_challenge(&mut self, challenge_id: &Thing, status: ChallengeStatus, winner: Option<&Thing>) -> Result<DbChallenge> {
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $challenge) = NONE { THROW \"no such challenge\" };")
+            .query("IF ($challenge.status ?? \"Proposed\") != \"Accepted\" { THROW \"challenge is not waiting to be settled\" };")
+            .query("UPDATE $challenge SET status = $status, winner = $winner;")
+            .query(CommitStatement)
+            .bind(("challenge", challenge_id))
+            .bind(("status", status))
+            .bind(("winner", winner))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let finished: Option<DbChallenge> = response.take(last)?;
+        finished.ok_or(DbError::NoRecordFound.into())
+    }
+
+    // The challenge whose stakes `wager` holds in escrow, if there is one
+    pub async fn get_challenge_for_wager(&self, wager_id: &Thing) -> Result<Option<DbChallenge>> {
+        let challenges: Vec<DbChallenge> = self
+            .connection
+            .query("SELECT * FROM challenge WHERE wager = $wager;")
+            .bind(("wager", wager_id))
+            .await?
+            .take(0)?;
+        Ok(challenges.into_iter().next())
+    }
+
+    pub async fn get_challenges_for_user(&self, user: &Thing) -> Result<Vec<DbChallenge>> {
+        self.connection
+            .query("SELECT * FROM challenge WHERE challenger = $user OR opponent = $user OR arbiter = $user;")
+            .bind(("user", user))
+            .await?
+            .take(0)
+    }
+
+    pub async fn add_bracket(&mut self, layout: &BracketLayout) -> Result<()> {
+        debug_assert_eq!(&layout.bracket.id.tb, &TABLE_BRACKET.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("FOR $wager IN $wagers { CREATE $wager.id CONTENT $wager; };")
+            .query("FOR $option IN $options { CREATE $option.id CONTENT $option; };")
+            .query("FOR $match IN $matches { CREATE $match.id CONTENT $match; };")
+            .query("CREATE $bracket.id CONTENT $bracket;")
+            .query(CommitStatement)
+            .bind(("wagers", &layout.wagers))
+            .bind(("options", &layout.options))
+            .bind(("matches", &layout.matches))
+            .bind(("bracket", &layout.bracket))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    pub async fn get_bracket_match_for_wager(&self, wager_id: &Thing) -> Result<Option<DbBracketMatch>> {
+        let matches: Vec<DbBracketMatch> = self
+            .connection
+            .query("SELECT * FROM bracket_match WHERE wager = $wager;")
+            .bind(("wager", wager_id))
+            .await?
+            .take(0)?;
+        Ok(matches.into_iter().next())
+    }
+
+    // Matches that have been decided and send someone on to another match
+    pub async fn get_resolved_bracket_matches(&self) -> Result<Vec<DbBracketMatch>> {
+        self.connection
+            .query("SELECT * FROM bracket_match WHERE wager.status = \"Resolved\" AND (winner_to != NONE OR loser_to != NONE);")
+            .await?
+            .take(0)
+    }
+
+    // Puts `team` into one side of a bracket match, naming that side's option after it. Once both sides
+    // are known the match's wager opens for betting. Filling a side again with the same team does nothing
+    pub async fn fill_bracket_slot(&mut self, slot: &BracketSlot, team: &str) -> Result<()> {
+        let bracket_match: DbBracketMatch = self.select(&slot.bracket_match).await?.ok_or(DbError::NoRecordFound)?;
+        let (Some(current), Some(option)) = (bracket_match.teams.get(slot.slot), bracket_match.options.get(slot.slot)) else {
+            return Err(DbError::NoRecordFound.into());
+        };
+        if current.as_deref() == Some(team) {
+            return Ok(());
+        }
+        let mut expected = bracket_match.teams.clone();
+        expected[slot.slot] = None;
+        let mut teams = expected.clone();
+        teams[slot.slot] = Some(team.to_string());
+        let open = teams.iter().all(Option::is_some);
+
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF $bracket_match.teams != $expected { THROW \"that side of the match has already been filled\" };")
+            .query("UPDATE $bracket_match SET teams = $teams;")
+            .query("UPDATE $option SET name = $team;")
+            .query("IF $open { UPDATE $wager SET status = \"Open\" WHERE (status ?? \"Open\") = \"Waiting\" };")
+            .query(CommitStatement)
+            .bind(("bracket_match", &slot.bracket_match))
+            .bind(("expected", expected))
+            .bind(("teams", teams))
+            .bind(("option", option))
+            .bind(("team", team))
+            .bind(("open", open))
+            .bind(("wager", &bracket_match.wager))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+        Ok(())
     }
 
     pub async fn remove_wager(&mut self, wager_id: &Thing) -> Result<()> {
-        let wager: DbWager = self.connection.select(wager_id).await?.unwrap();
+        let wager: DbWager = self.connection.select(wager_id).await?.ok_or(DbError::NoRecordFound)?;
         for option in &wager.options {
             self.remove_wager_option(option).await?;
         }
@@ -289,39 +1621,151 @@ impl<Type: Connection> DatabaseConnection<Type> {
         Ok(())
     }
 
+    // Takes an option off its wager while the wager is still open, refunding every bet on it, in one
+    // transaction
     pub async fn remove_wager_option(&mut self, option_id: &Thing) -> Result<()> {
-        let option: DbWagerOption = self.connection.select(option_id).await?.unwrap();
-
-        for bet in &option.bets {
-            self.remove_bet(&bet).await?;
-        }
-        self.connection
-            .query("UPDATE $wager SET options = array::remove($wager.options, array::find_index($wager.options, $option_id));")
-            .bind(("wager", &option.wager))
-            .bind(("option_id", option_id))
+        debug_assert_eq!(&option_id.tb, &TABLE_WAGER_OPTION.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query("IF (SELECT * FROM ONLY $wager_option) = NONE { THROW \"no such wager option\" };")
+            .query("IF ($wager_option.wager.status ?? \"Open\") != \"Open\" { THROW \"only open wagers can be edited\" };")
+            .query(format!("FOR $bet IN $wager_option.bets {{ {BET_REFUND} }};"))
+            .query("LET $wager = $wager_option.wager;")
+            .query("UPDATE $wager SET options = array::remove(options, array::find_index(options, $wager_option));")
+            .query("DELETE $wager_option;")
+            .query(CommitStatement)
+            .bind(("wager_option", option_id))
             .await?;
-        let _: Option<DbWagerOption> = self.connection.delete(option_id).await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
         Ok(())
     }
 
     pub async fn remove_bet(&mut self, bet_id: &Thing) -> Result<()> {
-        let bet: DbBet = self.connection.select(bet_id).await?.unwrap();
-        self.connection
+        let mut response = self
+            .connection
             .query(BeginStatement)
-            .query("UPDATE $wager_option SET bets = array::remove($wager_option.bets, array::find_index($wager_option.bets, $bet));")
-            .bind(("bet", &bet.id))
-            .bind(("wager_option", &bet.wager_option))
-            .query("UPDATE $user SET balance += $val;")
-            .bind(("user", &bet.user))
-            .bind(("val", &bet.val))
-            .query("DELETE $bet;")
-            .bind(("bet", &bet.id))
+            .query(BET_REFUND)
             .query(CommitStatement)
+            .bind(("bet", bet_id))
             .await?;
-
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
         Ok(())
     }
 
+    // Lets `user` take back one of their own bets while its wager is still open, refunding the stake.
+    // Returns the user's new balance
+    pub async fn withdraw_bet(&mut self, bet_id: &Thing, user: &Thing) -> Result<u64> {
+        debug_assert_eq!(&bet_id.tb, &TABLE_BET.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(BET_OWNER_GUARD)
+            .query(OPEN_BET_GUARD)
+            .query(BET_REFUND)
+            .query("SELECT VALUE balance FROM ONLY $user;")
+            .query(CommitStatement)
+            .bind(("bet", bet_id))
+            .bind(("user", user))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let balance: Option<u64> = response.take(response.num_statements() - 1)?;
+        balance.ok_or(DbError::NoRecordFound.into())
+    }
+
+    // Sells `stake` of one of the user's bets back for `price` while its wager is still open, handing the
+    // rest of the stake to the house. Selling the whole stake removes the bet, otherwise it shrinks and a
+    // fixed-odds bet's potential payout becomes `remaining_payout`. Returns the user's new balance
+    pub async fn cash_out_bet(
+        &mut self,
+        bet_id: &Thing,
+        user: &Thing,
+        stake: u64,
+        price: u64,
+        remaining_payout: Option<u64>,
+    ) -> Result<u64> {
+        debug_assert_eq!(&bet_id.tb, &TABLE_BET.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(BET_OWNER_GUARD)
+            .query(OPEN_BET_GUARD)
+            .query("IF $stake <= 0 OR $stake > $bet.val { THROW \"can only cash out part of the stake that was placed\" };")
+            .query("IF $price > $stake { THROW \"cannot cash out for more than the stake\" };")
+            .query("LET $wager = $bet.wager_option.wager;")
+            .query("IF $stake = $bet.val {
+                LET $option = $bet.wager_option;
+                UPDATE $option SET bets = array::remove(bets, array::find_index(bets, $bet));
+                DELETE $bet;
+            } ELSE {
+                UPDATE $bet SET val -= $stake, potential_payout = $remaining_payout;
+            };")
+            .query("UPDATE $user SET balance += $price;")
+            .query("CREATE ledger SET account = $user, wager = $wager, bet = $bet, kind = $kind, amount = $price, created_at = time::unix(time::now());")
+            .query("IF $stake > $price {
+                UPDATE $house SET balance += $stake - $price;
+                CREATE ledger SET account = $house, wager = $wager, bet = $bet, kind = $kind, amount = $stake - $price, created_at = time::unix(time::now());
+            };")
+            .query("SELECT VALUE balance FROM ONLY $user;")
+            .query(CommitStatement)
+            .bind(("bet", bet_id))
+            .bind(("user", user))
+            .bind(("stake", stake))
+            .bind(("price", price))
+            .bind(("remaining_payout", remaining_payout))
+            .bind(("house", account_id(ACCOUNT_HOUSE)))
+            .bind(("kind", LedgerEntryKind::CashOut))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let balance: Option<u64> = response.take(response.num_statements() - 1)?;
+        balance.ok_or(DbError::NoRecordFound.into())
+    }
+
+    // Lets `user` raise or lower the stake of one of their own bets while its wager is still open,
+    // charging or refunding the difference. The bet is then priced at `placed_probability`, as if it had
+    // been placed with the new stake. Fixed-odds bets keep the payout they were placed at, so they can
+    // only be withdrawn. Returns the user's new balance and the bet
+    pub async fn change_bet_stake(&mut self, bet_id: &Thing, user: &Thing, val: u64, placed_probability: Option<u32>) -> Result<(u64, DbBet)> {
+        debug_assert_eq!(&bet_id.tb, &TABLE_BET.to_string());
+        let mut response = self
+            .connection
+            .query(BeginStatement)
+            .query(BET_OWNER_GUARD)
+            .query(OPEN_BET_GUARD)
+            .query("IF $bet.potential_payout != NONE { THROW \"fixed-odds bets cannot be changed, only withdrawn\" };")
+            .query("IF $val <= 0 { THROW \"bet value must be positive\" };")
+            .query("IF (SELECT VALUE balance FROM ONLY $user) + $bet.val < $val { THROW \"insufficient balance\" };")
+            .query("UPDATE $user SET balance += $bet.val - $val;")
+            .query("UPDATE $bet SET val = $val, placed_probability = $placed_probability;")
+            .query(CommitStatement)
+            .bind(("bet", bet_id))
+            .bind(("user", user))
+            .bind(("val", val))
+            .bind(("placed_probability", placed_probability))
+            .await?;
+        if let Some(error) = take_transaction_error(&mut response) {
+            return Err(error);
+        }
+
+        let last = response.num_statements() - 1;
+        let user: Option<DbUser> = response.take(last - 1)?;
+        let bet: Option<DbBet> = response.take(last)?;
+        match (user, bet) {
+            (Some(user), Some(bet)) => Ok((user.balance, bet)),
+            _ => Err(DbError::NoRecordFound.into()),
+        }
+    }
+
     pub async fn get_user_by_name(&self, name: impl Into<&str>) -> Result<Option<DbUser>> {
         let mut response = self
             .connection
@@ -347,6 +1791,14 @@ impl<Type: Connection> DatabaseConnection<Type> {
         self.connection.select(TABLE_WAGER).await
     }
 
+    // Every wager that still needs to be locked automatically once its close time passes
+    pub async fn get_open_wagers_with_close_time(&self) -> Result<Vec<DbWager>> {
+        self.connection
+            .query("SELECT * FROM wager WHERE (status ?? \"Open\") = \"Open\" AND closes_at != NONE;")
+            .await?
+            .take(0)
+    }
+
     pub async fn get_wager_by_name(&self, name: impl Into<&str>) -> Result<Option<DbWager>> {
         let mut response = self
             .connection
@@ -396,39 +1848,119 @@ impl<Type: Connection> DatabaseConnection<Type> {
     }
 
     pub async fn get_all_bet_info(&self) -> Result<Vec<common::Wager>> {
-        let mut response = self
+        let wagers = self.get_all_wagers().await?;
+        let mut wager_info = Vec::with_capacity(wagers.len());
+        for wager in wagers {
+            wager_info.push(self.build_wager_info(wager).await?);
+        }
+        Ok(wager_info)
+    }
+
+    // Every wager matching `filter`. Only the conditions that are set go into the query, so that it can
+    // use the indexes on them
+    pub async fn get_wager_info_matching(&self, filter: &WagerFilter) -> Result<Vec<common::Wager>> {
+        let category = common::normalise_label(filter.category.clone());
+        let game = common::normalise_label(filter.game.clone());
+        let mut conditions = Vec::new();
+        if category.is_some() {
+            conditions.push("category = $category");
+        }
+        if game.is_some() {
+            conditions.push("game = $game");
+        }
+        if !filter.tags.is_empty() {
+            conditions.push("tags CONTAINSALL $tags");
+        }
+        if conditions.is_empty() {
+            return self.get_all_bet_info().await;
+        }
+
+        let wagers: Vec<DbWager> = self
             .connection
-            .query("SELECT * FROM wager FETCH options, options.bets")
-            .await?;
-        response.take(0)
+            .query(format!("SELECT * FROM wager WHERE {};", conditions.join(" AND ")))
+            .bind(("category", category))
+            .bind(("game", game))
+            .bind(("tags", common::normalise_tags(filter.tags.clone())))
+            .await?
+            .take(0)?;
+        let mut wager_info = Vec::with_capacity(wagers.len());
+        for wager in wagers {
+            wager_info.push(self.build_wager_info(wager).await?);
+        }
+        Ok(wager_info)
     }
 
     pub async fn get_info_for_wager(&self, wager_id: &Thing) -> Result<Option<common::Wager>> {
         assert_eq!(wager_id.tb, TABLE_WAGER);
+        match self.select::<DbWager>(wager_id).await? {
+            Some(wager) => Ok(Some(self.build_wager_info(wager).await?)),
+            None => Ok(None),
+        }
+    }
+
+    // Assembles the client facing view of a wager, with its options and their bets in the order they
+    // were added
+    async fn build_wager_info(&self, wager: DbWager) -> Result<common::Wager> {
         let mut response = self
             .connection
-            .query("SELECT * FROM wager WHERE id = $id FETCH options, options.bets")
-            .bind(("id", &wager_id))
+            .query("SELECT * FROM wager_option WHERE wager = $wager;")
+            .query("SELECT * FROM bet WHERE wager_option.wager = $wager;")
+            .bind(("wager", &wager.id))
             .await?;
-        response.take(0)
+        let options: Vec<DbWagerOption> = response.take(0)?;
+        let bets: Vec<DbBet> = response.take(1)?;
+
+        let mut bets_by_id: HashMap<String, DbBet> =
+            bets.into_iter().map(|bet| (bet.id.to_string(), bet)).collect();
+        let mut options_by_id: HashMap<String, DbWagerOption> = options
+            .into_iter()
+            .map(|option| (option.id.to_string(), option))
+            .collect();
+
+        let option_order = wager.options.clone();
+        let mut wager_info: common::Wager = wager.into();
+        for option_id in &option_order {
+            if let Some(option) = options_by_id.remove(&option_id.to_string()) {
+                let bets = option
+                    .bets
+                    .iter()
+                    .filter_map(|bet_id| bets_by_id.remove(&bet_id.to_string()))
+                    .map(Into::into)
+                    .collect();
+                let mut option_info: common::WagerOption = option.into();
+                option_info.bets = bets;
+                wager_info.options.push(option_info);
+            }
+        }
+
+        let total_stake: u64 = wager_info.options.iter().flat_map(|option| &option.bets).map(|bet| bet.val).sum();
+        for index in 0..wager_info.options.len() {
+            wager_info.options[index].stats = option_stats(&wager_info, &wager_info.options[index], total_stake);
+        }
+        Ok(wager_info)
     }
+}
 
-    pub async fn provide_payout_for_bet(
-        &mut self,
-        bet_info: &common::Bet,
-        winning_ratio: f64,
-    ) -> Result<()> {
-        let user_id = Thing {
-            tb: TABLE_USER.into(),
-            id: Id::String(bet_info.user_id.clone()),
-        };
-        self.connection
-            .query("UPDATE $winner SET balance += $bet_value * $winning_ratio")
-            .bind(("winner", user_id))
-            .bind(("bet_value", &bet_info.val))
-            .bind(("winning_ratio", winning_ratio))
-            .await?;
-        Ok(())
+// An option's live figures, worked out the same way its payouts would be if the wager were resolved now
+fn option_stats(wager: &common::Wager, option: &common::WagerOption, total_stake: u64) -> common::OptionStats {
+    let option_stake: u64 = option.bets.iter().map(|bet| bet.val).sum();
+    let mut bettors: Vec<&str> = option.bets.iter().map(|bet| bet.user_id.as_str()).collect();
+    bettors.sort_unstable();
+    bettors.dedup();
+
+    let (implied_probability, multiplier) = match (wager.kind, option.odds) {
+        (WagerKind::FixedOdds, Some(odds)) => (payout::odds_implied_probability(odds), Some(odds as u64)),
+        (WagerKind::FixedOdds, None) => (None, None),
+        (WagerKind::PariMutuel, _) => (
+            payout::implied_probability(option_stake, total_stake),
+            payout::pari_mutuel_multiplier(option_stake, total_stake - option_stake, wager.pot, wager.rake.basis_points),
+        ),
+    };
+    common::OptionStats {
+        total_stake: option_stake,
+        bettors: bettors.len() as u32,
+        implied_probability,
+        multiplier,
     }
 }
 
@@ -436,6 +1968,31 @@ impl<Type: Connection> DatabaseConnection<Type> {
 mod test {
     use super::*;
 
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct DbAccount {
+        balance: i64,
+    }
+
+    // A record of money paid into a user or account when a wager settles
+    #[derive(Deserialize, Debug)]
+    struct DbLedgerEntry {
+        account: Thing,
+        bet: Option<Thing>,
+        #[serde(default)]
+        parlay: Option<Thing>,
+        kind: LedgerEntryKind,
+        // negative when money was taken out of the account
+        amount: i64,
+        created_at: u64,
+        // held back until the wager's resolution is final, and not yet in the account's balance
+        #[serde(default)]
+        pending: bool,
+    }
+
+    async fn get_ledger_entries_for_wager(connection: &DatabaseConnection<Db>, wager_id: &Thing) -> Result<Vec<DbLedgerEntry>> {
+        connection.connection.query("SELECT * FROM ledger WHERE wager = $wager;").bind(("wager", wager_id)).await?.take(0)
+    }
+
     #[tokio::test]
     pub async fn test_add_wager() {
         let mut db = DatabaseConnection::<Db>::new().await.unwrap();
@@ -460,6 +2017,38 @@ mod test {
         assert_eq!(fetched_wager.pot, 200);
     }
 
+    #[tokio::test]
+    pub async fn test_filter_wagers() {
+        let mut db = DatabaseConnection::<Db>::new().await.unwrap();
+        for (name, category, game, tags) in [
+            ("final winner", "match winner", "cs2", vec!["final"]),
+            ("final kills", "player props", "cs2", vec!["final", "kills"]),
+            ("semi winner", "match winner", "dota", vec!["semi"]),
+        ] {
+            let mut wager = DbWager::new(name, name, 0);
+            wager.category = Some(category.into());
+            wager.game = Some(game.into());
+            wager.tags = tags.into_iter().map(Into::into).collect();
+            db.add_wager(&wager).await.unwrap().unwrap();
+        }
+
+        let matching = |filter: WagerFilter| {
+            let db = &db;
+            async move {
+                let mut names: Vec<String> = db.get_wager_info_matching(&filter).await.unwrap().into_iter().map(|wager| wager.name).collect();
+                names.sort();
+                names
+            }
+        };
+        assert_eq!(matching(WagerFilter::default()).await.len(), 3);
+        assert_eq!(matching(WagerFilter { category: Some("match winner".into()), ..WagerFilter::default() }).await, vec!["final winner", "semi winner"]);
+        assert_eq!(matching(WagerFilter { category: Some("match winner".into()), game: Some("cs2".into()), ..WagerFilter::default() }).await, vec!["final winner"]);
+        assert_eq!(matching(WagerFilter { tags: vec![" Final ".into()], ..WagerFilter::default() }).await, vec!["final kills", "final winner"]);
+        assert_eq!(matching(WagerFilter { tags: vec!["final".into(), "kills".into()], ..WagerFilter::default() }).await, vec!["final kills"]);
+        assert!(matching(WagerFilter { game: Some("valorant".into()), ..WagerFilter::default() }).await.is_empty());
+        assert_eq!(matching(WagerFilter { category: Some(" Match Winner ".into()), game: Some("CS2".into()), ..WagerFilter::default() }).await, vec!["final winner"]);
+    }
+
     #[tokio::test]
     pub async fn test_fetch_wager_by_name() {
         let mut db = DatabaseConnection::<Db>::new().await.unwrap();
@@ -751,6 +2340,14 @@ mod test {
 
         let fetched_removed_wager_option = setup.database_connection.select::<DbWagerOption>(removed_wager_option).await.unwrap();
         assert_eq!(fetched_removed_wager_option, None);
+        setup.database_connection.remove_wager_option(removed_wager_option).await.expect_err("should not be able to remove an option twice");
+
+        // the options of a wager that is no longer open stay where they are, bets and all
+        let kept_wager_option = setup.wager_options[2].clone();
+        setup.database_connection.set_wager_status(&setup.wagers[1], WagerStatus::Locked).await.unwrap();
+        setup.database_connection.remove_wager_option(&kept_wager_option).await.expect_err("should not be able to remove an option of a locked wager");
+        let fetched_kept_wager_option = setup.database_connection.select::<DbWagerOption>(&kept_wager_option).await.unwrap().expect("wager option should exist");
+        assert!(!fetched_kept_wager_option.bets.is_empty());
     }
 
     #[tokio::test]
@@ -785,6 +2382,682 @@ mod test {
         assert_eq!(fetched_removed_wager, None);
     }
 
+    #[tokio::test]
+    async fn test_place_bet() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users.first().unwrap().clone();
+        let wager_option = setup.wager_options.first().unwrap().clone();
+
+        let (balance, placed_bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), wager_option.clone(), 500), None).await.expect("should be able to place bet");
+        assert_eq!(balance, 1500);
+        assert_eq!(placed_bet.val, 500);
+        assert_eq!(placed_bet.user, user);
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 1500);
+        let fetched_wager_option = setup.database_connection.select::<DbWagerOption>(&wager_option).await.unwrap().expect("wager option should exist");
+        assert!(fetched_wager_option.bets.contains(&placed_bet.id));
+    }
+
+    #[tokio::test]
+    async fn test_place_bet_insufficient_balance() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users.first().unwrap().clone();
+        let wager_option = setup.wager_options.first().unwrap().clone();
+
+        let bet = DbBet::new(user.clone(), wager_option.clone(), 2001);
+        let error = setup.database_connection.place_bet(&bet, None).await.expect_err("should not be able to overdraw");
+        assert!(matches!(error, surrealdb::Error::Db(DbError::Thrown(_))));
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+        let fetched_bet = setup.database_connection.select::<DbBet>(&bet.id).await.unwrap();
+        assert_eq!(fetched_bet, None);
+        let fetched_wager_option = setup.database_connection.select::<DbWagerOption>(&wager_option).await.unwrap().expect("wager option should exist");
+        assert!(!fetched_wager_option.bets.contains(&bet.id));
+    }
+
+    #[tokio::test]
+    async fn test_place_bet_invalid_wager_option() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users.first().unwrap().clone();
+        let wager_option = Thing {
+            tb: TABLE_WAGER_OPTION.into(),
+            id: Id::String("does_not_exist".into()),
+        };
+
+        setup.database_connection.place_bet(&DbBet::new(user.clone(), wager_option, 200), None).await.expect_err("should not be able to bet on a missing option");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+    }
+
+    #[tokio::test]
+    async fn test_place_bet_on_locked_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users.first().unwrap().clone();
+        let wager = setup.wagers.first().unwrap().clone();
+        let wager_option = setup.wager_options.first().unwrap().clone();
+
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.expect("should be able to lock wager");
+        setup.database_connection.place_bet(&DbBet::new(user.clone(), wager_option, 200), None).await.expect_err("should not be able to bet on a locked wager");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_bet() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (user, other_user) = (setup.users[0].clone(), setup.users[1].clone());
+        let (_, bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), setup.wager_options[0].clone(), 500), None).await.unwrap();
+
+        setup.database_connection.withdraw_bet(&bet.id, &other_user).await.expect_err("should not be able to withdraw someone else's bet");
+        let balance = setup.database_connection.withdraw_bet(&bet.id, &user).await.expect("should be able to withdraw bet");
+        assert_eq!(balance, 2000);
+        let fetched_option = setup.database_connection.select::<DbWagerOption>(&setup.wager_options[0]).await.unwrap().expect("wager option should exist");
+        assert!(!fetched_option.bets.contains(&bet.id));
+        assert_eq!(setup.database_connection.select::<DbBet>(&bet.id).await.unwrap(), None);
+
+        setup.database_connection.withdraw_bet(&bet.id, &user).await.expect_err("should not be able to withdraw twice");
+        assert_eq!(setup.database_connection.select::<DbUser>(&user).await.unwrap().unwrap().balance, 2000);
+    }
+
+    #[tokio::test]
+    async fn test_cash_out_bet() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (user, other_user) = (setup.users[0].clone(), setup.users[1].clone());
+        let (_, bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), setup.wager_options[0].clone(), 500), None).await.unwrap();
+
+        setup.database_connection.cash_out_bet(&bet.id, &other_user, 100, 90, None).await.expect_err("should not be able to cash out someone else's bet");
+        setup.database_connection.cash_out_bet(&bet.id, &user, 100, 101, None).await.expect_err("should not be paid more than the stake");
+        setup.database_connection.cash_out_bet(&bet.id, &user, 501, 90, None).await.expect_err("should not be able to sell more than was staked");
+
+        let balance = setup.database_connection.cash_out_bet(&bet.id, &user, 100, 90, None).await.expect("should be able to cash out part of a bet");
+        assert_eq!(balance, 1590);
+        assert_eq!(setup.database_connection.select::<DbBet>(&bet.id).await.unwrap().unwrap().val, 400);
+        let balance = setup.database_connection.cash_out_bet(&bet.id, &user, 400, 300, None).await.expect("should be able to cash out the rest");
+        assert_eq!(balance, 1890);
+        assert_eq!(setup.database_connection.select::<DbBet>(&bet.id).await.unwrap(), None);
+        let fetched_option = setup.database_connection.select::<DbWagerOption>(&setup.wager_options[0]).await.unwrap().expect("wager option should exist");
+        assert!(!fetched_option.bets.contains(&bet.id));
+
+        // the house keeps whatever was not paid back
+        let house = setup.database_connection.select::<DbAccount>(&account_id(ACCOUNT_HOUSE)).await.unwrap().expect("house should exist");
+        assert_eq!(house.balance, 110);
+
+        // the pool is frozen once the wager locks, so nobody can take their stake back out of it
+        let (_, bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), setup.wager_options[0].clone(), 500), None).await.unwrap();
+        setup.database_connection.set_wager_status(&setup.wagers[0], WagerStatus::Locked).await.unwrap();
+        setup.database_connection.cash_out_bet(&bet.id, &user, 100, 90, None).await.expect_err("should not be able to cash out once locked");
+        assert_eq!(setup.database_connection.select::<DbBet>(&bet.id).await.unwrap().unwrap().val, 500);
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_bet_after_lock() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users[0].clone();
+        let (_, bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), setup.wager_options[0].clone(), 500), None).await.unwrap();
+        setup.database_connection.set_wager_status(&setup.wagers[0], WagerStatus::Locked).await.unwrap();
+
+        setup.database_connection.withdraw_bet(&bet.id, &user).await.expect_err("should not be able to withdraw once locked");
+        setup.database_connection.change_bet_stake(&bet.id, &user, 100, None).await.expect_err("should not be able to change once locked");
+        assert_eq!(setup.database_connection.select::<DbUser>(&user).await.unwrap().unwrap().balance, 1500);
+    }
+
+    #[tokio::test]
+    async fn test_change_bet_stake() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (user, other_user) = (setup.users[0].clone(), setup.users[1].clone());
+        let (_, bet) = setup.database_connection.place_bet(&DbBet::new(user.clone(), setup.wager_options[0].clone(), 500), None).await.unwrap();
+
+        let (balance, changed_bet) = setup.database_connection.change_bet_stake(&bet.id, &user, 800, None).await.expect("should be able to increase bet");
+        assert_eq!((balance, changed_bet.val), (1200, 800));
+        let (balance, changed_bet) = setup.database_connection.change_bet_stake(&bet.id, &user, 300, None).await.expect("should be able to decrease bet");
+        assert_eq!((balance, changed_bet.val), (1700, 300));
+
+        setup.database_connection.change_bet_stake(&bet.id, &user, 2001, None).await.expect_err("should not be able to overdraw");
+        setup.database_connection.change_bet_stake(&bet.id, &user, 0, None).await.expect_err("should not be able to stake nothing");
+        setup.database_connection.change_bet_stake(&bet.id, &other_user, 100, None).await.expect_err("should not be able to change someone else's bet");
+        assert_eq!(setup.database_connection.select::<DbUser>(&user).await.unwrap().unwrap().balance, 1700);
+        assert_eq!(setup.database_connection.select::<DbBet>(&bet.id).await.unwrap().unwrap().val, 300);
+    }
+
+    #[tokio::test]
+    async fn test_place_bet_after_close_time() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users.first().unwrap().clone();
+
+        let mut closed_wager = DbWager::new("closed", "closed", 0);
+        closed_wager.closes_at = Some(1);
+        let closed_wager = setup.database_connection.add_wager(&closed_wager).await.unwrap().unwrap().id;
+        let wager_option = setup.database_connection.add_wager_option_db(&DbWagerOption::new("closed_option", "closed_option", closed_wager)).await.unwrap().unwrap().id;
+
+        setup.database_connection.place_bet(&DbBet::new(user.clone(), wager_option, 200), None).await.expect_err("should not be able to bet after the close time");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+    }
+
+    async fn add_fixed_odds_wager(setup: &mut DatabaseSetup) -> (Thing, Thing, Thing) {
+        let mut wager = DbWager::new("fixed", "fixed", 0);
+        wager.kind = WagerKind::FixedOdds;
+        let wager = setup.database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let option1 = setup.database_connection.add_wager_option_db(&DbWagerOption::new("fixed_option1", "fixed_option1", wager.clone())).await.unwrap().unwrap().id;
+        let option2 = setup.database_connection.add_wager_option_db(&DbWagerOption::new("fixed_option2", "fixed_option2", wager.clone())).await.unwrap().unwrap().id;
+        setup.database_connection.set_option_odds(&option1, 250).await.expect("should be able to set odds");
+        setup.database_connection.set_option_odds(&option2, 150).await.expect("should be able to set odds");
+        (wager, option1, option2)
+    }
+
+    #[tokio::test]
+    async fn test_set_option_odds() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (_, fixed_option, _) = add_fixed_odds_wager(&mut setup).await;
+
+        let fetched_option = setup.database_connection.select::<DbWagerOption>(&fixed_option).await.unwrap().expect("option should exist");
+        assert_eq!(fetched_option.odds, Some(250));
+
+        setup.database_connection.set_option_odds(&fixed_option, 100).await.expect_err("odds must pay more than the stake");
+        setup.database_connection.set_option_odds(&setup.wager_options[0], 250).await.expect_err("pari-mutuel wagers have no odds");
+    }
+
+    #[tokio::test]
+    async fn test_place_fixed_odds_bet() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users[0].clone();
+        let (_, fixed_option, _) = add_fixed_odds_wager(&mut setup).await;
+
+        let mut bet = DbBet::new(user.clone(), fixed_option.clone(), 101);
+        setup.database_connection.place_bet(&bet, None).await.expect_err("fixed-odds bets need a potential payout");
+        bet.potential_payout = Some(300);
+        setup.database_connection.place_bet(&bet, None).await.expect_err("should not be able to bet at other odds");
+
+        // 101 at 2.5 is 252.5, which rounds down
+        bet.potential_payout = Some(252);
+        let (balance, placed_bet) = setup.database_connection.place_bet(&bet, None).await.expect("should be able to place bet");
+        assert_eq!(balance, 1899);
+        assert_eq!(placed_bet.potential_payout, Some(252));
+
+        let mut pari_mutuel_bet = DbBet::new(user, setup.wager_options[0].clone(), 100);
+        pari_mutuel_bet.potential_payout = Some(200);
+        setup.database_connection.place_bet(&pari_mutuel_bet, None).await.expect_err("pari-mutuel bets have no potential payout");
+    }
+
+    #[tokio::test]
+    async fn test_place_fixed_odds_bet_over_max_liability() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (_, fixed_option1, fixed_option2) = add_fixed_odds_wager(&mut setup).await;
+
+        let mut bet = DbBet::new(setup.users[0].clone(), fixed_option1.clone(), 400);
+        bet.potential_payout = Some(1000);
+        setup.database_connection.place_bet(&bet, Some(500)).await.expect_err("paying 1000 for 400 staked is a liability of 600");
+
+        let mut hedge = DbBet::new(setup.users[1].clone(), fixed_option2, 200);
+        hedge.potential_payout = Some(300);
+        setup.database_connection.place_bet(&hedge, Some(500)).await.expect("should be able to place bet");
+        // stakes on the other option count against the liability
+        setup.database_connection.place_bet(&bet, Some(500)).await.expect("should be able to place bet");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 1600);
+    }
+
+    #[tokio::test]
+    async fn test_get_open_wagers_with_close_time() {
+        let mut setup = setup_testing_database().await.unwrap();
+
+        let mut scheduled_wager = DbWager::new("scheduled", "scheduled", 0);
+        scheduled_wager.closes_at = Some(4_000_000_000);
+        let scheduled_wager = setup.database_connection.add_wager(&scheduled_wager).await.unwrap().unwrap().id;
+        let mut locked_wager = DbWager::new("locked", "locked", 0);
+        locked_wager.closes_at = Some(4_000_000_000);
+        locked_wager.status = WagerStatus::Locked;
+        setup.database_connection.add_wager(&locked_wager).await.unwrap().unwrap();
+
+        let fetched_wagers = setup.database_connection.get_open_wagers_with_close_time().await.unwrap();
+        assert_eq!(fetched_wagers.len(), 1);
+        assert_eq!(fetched_wagers[0].id, scheduled_wager);
+        assert_eq!(fetched_wagers[0].closes_at, Some(4_000_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_set_wager_status() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.expect("open wagers can be locked");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Resolved).await.expect("locked wagers can be resolved");
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.status, WagerStatus::Resolved);
+
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Resolved).await.expect_err("resolved wagers cannot be resolved again");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Open).await.expect_err("resolved wagers cannot be reopened");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Cancelled).await.expect_err("resolved wagers cannot be cancelled");
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.status, WagerStatus::Resolved);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+        let (_, placed_bet) = setup.database_connection.place_bet(&DbBet::new(setup.users[0].clone(), setup.wager_options[1].clone(), 500), None).await.unwrap();
+
+        setup.database_connection.cancel_wager(&wager).await.expect("should be able to cancel wager");
+
+        // user 1 bet 200 without paying for it in the setup, then paid 500 for the placed bet
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2200);
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[1]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2200);
+
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("cancelled wager should be kept");
+        assert_eq!(fetched_wager.status, WagerStatus::Cancelled);
+        assert_eq!(fetched_wager.options, vec![setup.wager_options[0].clone(), setup.wager_options[1].clone()]);
+        for option in &setup.wager_options[..2] {
+            let fetched_option = setup.database_connection.select::<DbWagerOption>(option).await.unwrap().expect("cancelled option should be kept");
+            assert_eq!(fetched_option.status, OptionStatus::Cancelled);
+        }
+        for bet in [&setup.bets[0], &setup.bets[1], &placed_bet.id] {
+            let fetched_bet = setup.database_connection.select::<DbBet>(bet).await.unwrap().expect("cancelled bet should be kept");
+            assert_eq!(fetched_bet.status, BetStatus::Cancelled);
+        }
+
+        // bets and options of other wagers are untouched
+        let fetched_bet = setup.database_connection.select::<DbBet>(&setup.bets[2]).await.unwrap().unwrap();
+        assert_eq!(fetched_bet.status, BetStatus::Active);
+        let fetched_option = setup.database_connection.select::<DbWagerOption>(&setup.wager_options[2]).await.unwrap().unwrap();
+        assert_eq!(fetched_option.status, OptionStatus::Active);
+
+        setup.database_connection.cancel_wager(&wager).await.expect_err("should not be able to cancel twice");
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2200);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_resolved_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Resolved).await.unwrap();
+
+        setup.database_connection.cancel_wager(&wager).await.expect_err("should not be able to cancel a resolved wager");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+        let fetched_bet = setup.database_connection.select::<DbBet>(&setup.bets[0]).await.unwrap().unwrap();
+        assert_eq!(fetched_bet.status, BetStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+
+        let payouts = vec![BetPayout { bet: setup.bets[0].clone(), amount: 600 }];
+        let resolution = DbResolution { winners: vec![DbWinner { option: setup.wager_options[0].clone(), weight: None }], no_winner_policy: None, outcome: None, resolved_at: 1000, pending_until: None, disputed: false };
+        let rake = AccountPayment { account: rake_account(RakeDestination::Jackpot), amount: 10, kind: LedgerEntryKind::Rake };
+        let settlement = Settlement { payouts, account_payments: vec![rake.clone()], rollover: None };
+        setup.database_connection.resolve_wager(&wager, &resolution, &settlement).await.expect("should be able to resolve wager");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2600);
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[1]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.status, WagerStatus::Resolved);
+        assert_eq!(fetched_wager.resolution, Some(resolution.clone()));
+        let fetched_jackpot = setup.database_connection.select::<DbAccount>(&rake_account(RakeDestination::Jackpot)).await.unwrap().expect("jackpot should exist");
+        assert_eq!(fetched_jackpot.balance, 10);
+
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap();
+        assert_eq!(ledger.len(), 2);
+        let payout_entry = ledger.iter().find(|entry| entry.kind == LedgerEntryKind::Payout).expect("payout should be recorded");
+        assert_eq!((&payout_entry.account, &payout_entry.bet, payout_entry.amount), (&setup.users[0], &Some(setup.bets[0].clone()), 600));
+        let rake_entry = ledger.iter().find(|entry| entry.kind == LedgerEntryKind::Rake).expect("rake should be recorded");
+        assert_eq!((&rake_entry.account, rake_entry.amount, rake_entry.created_at), (&rake.account, 10, 1000));
+
+        setup.database_connection.resolve_wager(&wager, &resolution, &settlement).await.expect_err("should not be able to resolve twice");
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2600);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager_is_atomic() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+
+        // the second payout belongs to a bet on a different wager, which must undo the first
+        let payouts = vec![
+            BetPayout { bet: setup.bets[0].clone(), amount: 600 },
+            BetPayout { bet: setup.bets[3].clone(), amount: 600 },
+        ];
+        let resolution = DbResolution { winners: vec![DbWinner { option: setup.wager_options[0].clone(), weight: None }], no_winner_policy: None, outcome: None, resolved_at: 1000, pending_until: None, disputed: false };
+        let rake = AccountPayment { account: rake_account(RakeDestination::House), amount: 10, kind: LedgerEntryKind::Rake };
+        setup.database_connection.resolve_wager(&wager, &resolution, &Settlement { payouts, account_payments: vec![rake], rollover: None }).await.expect_err("should not be able to pay bets on another wager");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+        let fetched_user = setup.database_connection.select::<DbUser>(&setup.users[1]).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2000);
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.status, WagerStatus::Open);
+        assert_eq!(fetched_wager.resolution, None);
+        let fetched_house = setup.database_connection.select::<DbAccount>(&rake_account(RakeDestination::House)).await.unwrap();
+        assert_eq!(fetched_house, None);
+        assert!(get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager_with_rollover() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+        let resolution = DbResolution { winners: vec![], no_winner_policy: Some(NoWinnerPolicy::Rollover { wager_id: setup.wagers[1].id.to_string() }), outcome: None, resolved_at: 1000, pending_until: None, disputed: false };
+
+        let into_itself = Settlement { rollover: Some(PotRollover { wager: wager.clone(), amount: 600 }), ..Settlement::default() };
+        setup.database_connection.resolve_wager(&wager, &resolution, &into_itself).await.expect_err("should not be able to roll over into the same wager");
+        setup.database_connection.set_wager_status(&setup.wagers[1], WagerStatus::Cancelled).await.unwrap();
+        let into_cancelled = Settlement { rollover: Some(PotRollover { wager: setup.wagers[1].clone(), amount: 600 }), ..Settlement::default() };
+        setup.database_connection.resolve_wager(&wager, &resolution, &into_cancelled).await.expect_err("should not be able to roll over into a cancelled wager");
+
+        let target = setup.database_connection.add_wager(&DbWager::new("target", "target", 50)).await.unwrap().unwrap().id;
+        let settlement = Settlement { rollover: Some(PotRollover { wager: target.clone(), amount: 600 }), ..Settlement::default() };
+        setup.database_connection.resolve_wager(&wager, &resolution, &settlement).await.expect("should be able to roll over");
+
+        let fetched_target = setup.database_connection.select::<DbWager>(&target).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_target.pot, 650);
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap();
+        assert_eq!(ledger.len(), 1);
+        assert_eq!((&ledger[0].account, ledger[0].kind, ledger[0].amount), (&target, LedgerEntryKind::Rollover, 600));
+    }
+
+    #[tokio::test]
+    async fn test_finalise_pending_resolution() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+        let target = setup.database_connection.add_wager(&DbWager::new("target", "target", 50)).await.unwrap().unwrap().id;
+        let resolution = DbResolution { winners: vec![DbWinner { option: setup.wager_options[0].clone(), weight: None }], no_winner_policy: None, outcome: None, resolved_at: 1000, pending_until: Some(2000), disputed: false };
+        let settlement = Settlement {
+            payouts: vec![BetPayout { bet: setup.bets[0].clone(), amount: 300 }],
+            rollover: Some(PotRollover { wager: target.clone(), amount: 100 }),
+            ..Settlement::default()
+        };
+        setup.database_connection.finalise_resolution(&wager).await.expect_err("should not be able to finalise an unresolved wager");
+        setup.database_connection.resolve_wager(&wager, &resolution, &settlement).await.expect("should be able to resolve wager");
+
+        // nothing moves until the resolution is final
+        assert_eq!(setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().unwrap().balance, 2000);
+        assert_eq!(setup.database_connection.select::<DbWager>(&target).await.unwrap().unwrap().pot, 50);
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap();
+        assert!(ledger.len() == 2 && ledger.iter().all(|entry| entry.pending));
+
+        let late = DbDispute::new(wager.clone(), setup.users[1].clone(), "late", 2000);
+        setup.database_connection.add_dispute(&late).await.expect_err("should not be able to dispute once the window has closed");
+        setup.database_connection.finalise_resolution(&wager).await.expect("should be able to finalise resolution");
+        assert_eq!(setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().unwrap().balance, 2300);
+        assert_eq!(setup.database_connection.select::<DbWager>(&target).await.unwrap().unwrap().pot, 150);
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap();
+        assert!(ledger.iter().all(|entry| !entry.pending));
+        setup.database_connection.finalise_resolution(&wager).await.expect_err("should not be able to pay out twice");
+        assert_eq!(setup.database_connection.select::<DbUser>(&setup.users[0]).await.unwrap().unwrap().balance, 2300);
+    }
+
+    #[tokio::test]
+    async fn test_finalise_rollover_into_finished_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+        let target = setup.database_connection.add_wager(&DbWager::new("target", "target", 50)).await.unwrap().unwrap().id;
+        let resolution = DbResolution { winners: vec![], no_winner_policy: Some(NoWinnerPolicy::Rollover { wager_id: target.to_string() }), outcome: None, resolved_at: 1000, pending_until: Some(2000), disputed: false };
+        let settlement = Settlement { rollover: Some(PotRollover { wager: target.clone(), amount: 600 }), ..Settlement::default() };
+        setup.database_connection.resolve_wager(&wager, &resolution, &settlement).await.expect("should be able to resolve wager");
+
+        // the target finishes while the resolution is still open to disputes
+        setup.database_connection.set_wager_status(&target, WagerStatus::Resolved).await.unwrap();
+        setup.database_connection.finalise_resolution(&wager).await.expect_err("should not be able to roll over into a finished wager");
+        assert_eq!(setup.database_connection.select::<DbWager>(&target).await.unwrap().unwrap().pot, 50);
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap();
+        assert!(ledger.len() == 1 && ledger[0].pending);
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().unwrap();
+        assert_eq!(fetched_wager.resolution.and_then(|resolution| resolution.pending_until), Some(2000));
+
+        // overturning it frees the wager to be settled another way
+        setup.database_connection.overturn_resolution(&wager).await.expect("should be able to overturn the resolution");
+        assert!(get_ledger_entries_for_wager(&setup.database_connection, &wager).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bracket_placeholder_waits_for_its_teams() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let definition = common::BracketDefinition { name: "cup".into(), format: BracketFormat::SingleElimination, teams: ["a", "b"].map(String::from).to_vec() };
+        let mut layout = crate::bracket::layout(&definition, Rake::default()).unwrap();
+        // turn the final into a placeholder for a match that has not been played yet
+        let placeholder = layout.matches[0].clone();
+        layout.matches[0].teams = vec![None, None];
+        layout.wagers[0].status = WagerStatus::Waiting;
+        setup.database_connection.add_bracket(&layout).await.expect("should be able to add bracket");
+        let wager = placeholder.wager.clone();
+        let option = placeholder.options[0].clone();
+
+        let resolution = DbResolution { winners: vec![DbWinner { option: option.clone(), weight: None }], no_winner_policy: None, outcome: None, resolved_at: 1000, pending_until: None, disputed: false };
+        setup.database_connection.resolve_wager(&wager, &resolution, &Settlement::default()).await.expect_err("should not be able to resolve a match before its teams are known");
+        setup.database_connection.cast_vote(&DbVote::new(wager.clone(), setup.users[0].clone(), option.clone(), 1000)).await.expect_err("should not be able to vote on a match before its teams are known");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.expect_err("should not be able to lock a match before its teams are known");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Open).await.expect_err("should not be able to open a match before its teams are known");
+
+        for (slot, team) in ["a", "b"].into_iter().enumerate() {
+            let status = setup.database_connection.select::<DbWager>(&wager).await.unwrap().unwrap().status;
+            assert_eq!(status, WagerStatus::Waiting);
+            setup.database_connection.fill_bracket_slot(&BracketSlot { bracket_match: placeholder.id.clone(), slot }, team).await.expect("should be able to fill the slot");
+        }
+        assert_eq!(setup.database_connection.select::<DbWager>(&wager).await.unwrap().unwrap().status, WagerStatus::Open);
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.unwrap();
+        setup.database_connection.resolve_wager(&wager, &resolution, &Settlement::default()).await.expect("should be able to resolve a filled match");
+    }
+
+    #[tokio::test]
+    async fn test_cast_vote() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+        let vote = |user: usize, option: usize| DbVote::new(wager.clone(), setup.users[user].clone(), setup.wager_options[option].clone(), 1000);
+
+        setup.database_connection.cast_vote(&vote(0, 0)).await.expect_err("should not be able to vote while the wager is open");
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.unwrap();
+        setup.database_connection.cast_vote(&vote(0, 2)).await.expect_err("should not be able to vote for another wager's option");
+        setup.database_connection.cast_vote(&vote(0, 0)).await.expect("should be able to vote on a locked wager");
+        setup.database_connection.cast_vote(&vote(1, 0)).await.expect("should be able to vote on a locked wager");
+        let votes = setup.database_connection.cast_vote(&vote(0, 1)).await.expect("should be able to change a vote");
+        let mut cast: Vec<(Thing, Thing)> = votes.into_iter().map(|vote| (vote.user, vote.option)).collect();
+        cast.sort_by_key(|(user, _)| user.to_string());
+        let mut expected = vec![(setup.users[0].clone(), setup.wager_options[1].clone()), (setup.users[1].clone(), setup.wager_options[0].clone())];
+        expected.sort_by_key(|(user, _)| user.to_string());
+        assert_eq!(cast, expected);
+        assert_eq!(setup.database_connection.get_votes_for_wager(&wager).await.unwrap().len(), 2);
+        assert_eq!(setup.database_connection.get_undecided_votes().await.unwrap().len(), 2);
+
+        // a deadlocked wager is waiting on an admin rather than its voters
+        setup.database_connection.set_vote_deadlocked(&wager, true).await.unwrap();
+        assert!(setup.database_connection.get_undecided_votes().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_gsi_link() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let link = |wager: usize, teams: &[(&str, usize)]| {
+            let teams = teams
+                .iter()
+                .map(|(team, option)| common::GsiTeam { team: team.to_string(), wager_option_id: setup.wager_options[*option].id.to_string() })
+                .collect();
+            DbGsiLink::new(setup.wagers[wager].clone(), common::GsiLink { game: GsiGame::Dota2, map_name: None, match_id: None, teams })
+        };
+
+        let valid = link(0, &[("Radiant", 0), ("dire", 1)]);
+        assert_eq!(valid.teams[0].team, "radiant");
+        setup.database_connection.add_gsi_link(&valid).await.expect("should be able to link a wager");
+        let fetched: DbGsiLink = setup.database_connection.select(&valid.id).await.unwrap().expect("link should exist");
+        assert_eq!(fetched, valid);
+
+        setup.database_connection.add_gsi_link(&link(0, &[("radiant", 0)])).await.expect_err("a link needs two teams");
+        setup.database_connection.add_gsi_link(&link(0, &[("radiant", 0), (" RADIANT ", 1)])).await.expect_err("teams need different names");
+        setup.database_connection.add_gsi_link(&link(0, &[("radiant", 0), ("", 1)])).await.expect_err("teams need names");
+        setup.database_connection.add_gsi_link(&link(0, &[("radiant", 0), ("dire", 2)])).await.expect_err("options must be part of the wager");
+        setup.database_connection.set_wager_status(&setup.wagers[1], WagerStatus::Cancelled).await.unwrap();
+        setup.database_connection.add_gsi_link(&link(1, &[("radiant", 2), ("dire", 3)])).await.expect_err("settled wagers cannot be linked");
+    }
+
+    #[tokio::test]
+    async fn test_set_stake_limits() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+
+        let limits = StakeLimits { min_stake: Some(10), max_stake: None, max_exposure: Some(500) };
+        setup.database_connection.set_stake_limits(&wager, limits).await.expect("should be able to set stake limits");
+        let backwards = StakeLimits { min_stake: Some(100), max_stake: Some(10), max_exposure: None };
+        setup.database_connection.set_stake_limits(&wager, backwards).await.expect_err("minimum should not be above the maximum");
+
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.stake_limits, limits);
+
+        // the limits of a wager that is no longer taking bets are left alone
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.unwrap();
+        let lifted = StakeLimits { min_stake: None, max_stake: None, max_exposure: None };
+        setup.database_connection.set_stake_limits(&wager, lifted).await.expect_err("should not be able to change the limits of a locked wager");
+        let fetched_wager = setup.database_connection.select::<DbWager>(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(fetched_wager.stake_limits, limits);
+    }
+
+    fn parlay_leg(wager: &Thing, wager_option: &Thing) -> DbParlayLeg {
+        DbParlayLeg { wager: wager.clone(), wager_option: wager_option.clone(), odds: None, status: LegStatus::Pending }
+    }
+
+    #[tokio::test]
+    async fn test_place_parlay() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let user = setup.users[0].clone();
+        let (wager1, wager2) = (setup.wagers[0].clone(), setup.wagers[1].clone());
+        let legs = vec![parlay_leg(&wager1, &setup.wager_options[0]), parlay_leg(&wager2, &setup.wager_options[2])];
+
+        let single_leg = DbParlay::new(user.clone(), 100, legs[..1].to_vec());
+        setup.database_connection.place_parlay(&single_leg, None).await.expect_err("a parlay needs more than one leg");
+        let same_wager = DbParlay::new(user.clone(), 100, vec![legs[0].clone(), parlay_leg(&wager1, &setup.wager_options[1])]);
+        setup.database_connection.place_parlay(&same_wager, None).await.expect_err("legs should be on different wagers");
+        let wrong_wager = DbParlay::new(user.clone(), 100, vec![legs[0].clone(), parlay_leg(&wager2, &setup.wager_options[1])]);
+        setup.database_connection.place_parlay(&wrong_wager, None).await.expect_err("a leg's option should belong to its wager");
+        let too_large = DbParlay::new(user.clone(), 5000, legs.clone());
+        setup.database_connection.place_parlay(&too_large, None).await.expect_err("should not be able to stake more than the balance");
+
+        let (balance, parlay) = setup.database_connection.place_parlay(&DbParlay::new(user.clone(), 100, legs.clone()), None).await.expect("should be able to place parlay");
+        assert_eq!(balance, 1900);
+        assert_eq!((parlay.status, parlay.legs.clone()), (ParlayStatus::Active, legs));
+        assert_eq!(setup.database_connection.get_active_parlays().await.unwrap(), vec![parlay.clone()]);
+
+        let mut won = parlay.clone();
+        won.legs[0].status = LegStatus::Won { numerator: 3, denominator: 2 };
+        won.legs[1].status = LegStatus::Void;
+        (won.status, won.payout) = (ParlayStatus::Won, Some(150));
+        let settlements = vec![ParlaySettlement { parlay: won, payout: 150, house: -50 }];
+        setup.database_connection.settle_parlays(&wager1, &settlements, 1000).await.expect("should be able to settle parlay");
+        setup.database_connection.settle_parlays(&wager1, &settlements, 1000).await.expect_err("should not pay a parlay twice");
+
+        let fetched_user = setup.database_connection.select::<DbUser>(&user).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_user.balance, 2050);
+        assert!(setup.database_connection.get_active_parlays().await.unwrap().is_empty());
+        let ledger = get_ledger_entries_for_wager(&setup.database_connection, &wager1).await.unwrap();
+        assert_eq!(ledger.len(), 2);
+        assert!(ledger.iter().all(|entry| entry.parlay == Some(parlay.id.clone())));
+    }
+
+    #[tokio::test]
+    async fn test_challenge() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let (challenger, opponent) = (setup.users[0].clone(), setup.users[1].clone());
+        let propose = |arbiter: Option<Thing>, stake: u64| DbChallenge::new(challenger.clone(), opponent.clone(), stake, "first to ten", arbiter);
+
+        setup.database_connection.propose_challenge(&DbChallenge::new(challenger.clone(), challenger.clone(), 100, "", None)).await.expect_err("users should not challenge themselves");
+        setup.database_connection.propose_challenge(&propose(Some(opponent.clone()), 100)).await.expect_err("the arbiter should not be a player");
+        let too_large = setup.database_connection.propose_challenge(&propose(None, 5000)).await.expect("stakes are only checked on acceptance");
+        setup.database_connection.accept_challenge(&too_large.id, &opponent).await.expect_err("both sides should be able to cover the stake");
+
+        let challenge = setup.database_connection.propose_challenge(&propose(None, 500)).await.expect("should be able to propose challenge");
+        assert_eq!(challenge.status, ChallengeStatus::Proposed);
+        setup.database_connection.accept_challenge(&challenge.id, &challenger).await.expect_err("only the opponent should be able to accept");
+        let (balance, accepted) = setup.database_connection.accept_challenge(&challenge.id, &opponent).await.expect("should be able to accept challenge");
+        assert_eq!((balance, accepted.status), (1500, ChallengeStatus::Accepted));
+        setup.database_connection.accept_challenge(&challenge.id, &opponent).await.expect_err("should not be able to accept twice");
+        setup.database_connection.decline_challenge(&challenge.id, &opponent).await.expect_err("should not be able to decline once accepted");
+
+        let fetched_challenger = setup.database_connection.select::<DbUser>(&challenger).await.unwrap().expect("user should exist");
+        assert_eq!(fetched_challenger.balance, 1500);
+        let wager = setup.database_connection.get_info_for_wager(accepted.wager.as_ref().unwrap()).await.unwrap().expect("escrow wager should exist");
+        assert_eq!(wager.status, WagerStatus::Locked);
+        let stakes: Vec<(&str, u64)> = wager.options.iter().flat_map(|option| &option.bets).map(|bet| (bet.user_id.as_str(), bet.val)).collect();
+        assert_eq!(stakes, vec![("user1", 500), ("user2", 500)]);
+
+        setup.database_connection.decline_challenge(&too_large.id, &challenger).await.expect("the challenger should be able to withdraw");
+        let challenges = setup.database_connection.get_challenges_for_user(&opponent).await.unwrap();
+        assert_eq!(challenges.len(), 2);
+
+        // overturning the resolution that settled a challenge leaves the challenge to be settled again
+        let escrow = accepted.wager.clone().unwrap();
+        assert_eq!(setup.database_connection.get_challenge_for_wager(&escrow).await.unwrap(), Some(accepted.clone()));
+        let winning_option = Thing { tb: TABLE_WAGER_OPTION.into(), id: Id::String(wager.options[0].id.clone()) };
+        let resolution = DbResolution { winners: vec![DbWinner { option: winning_option, weight: None }], no_winner_policy: None, outcome: None, resolved_at: 1000, pending_until: Some(2000), disputed: false };
+        setup.database_connection.resolve_wager(&escrow, &resolution, &Settlement::default()).await.unwrap();
+        setup.database_connection.finish_challenge(&challenge.id, ChallengeStatus::Settled, Some(&challenger)).await.unwrap();
+        setup.database_connection.overturn_resolution(&escrow).await.expect("should be able to overturn the resolution");
+        let overturned = setup.database_connection.select::<DbChallenge>(&challenge.id).await.unwrap().expect("challenge should exist");
+        assert_eq!((overturned.status, overturned.winner), (ChallengeStatus::Accepted, None));
+    }
+
+    #[tokio::test]
+    async fn test_get_info_for_wager() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers.first().unwrap().clone();
+        setup.database_connection.set_wager_status(&wager, WagerStatus::Locked).await.unwrap();
+
+        let wager_info = setup.database_connection.get_info_for_wager(&wager).await.unwrap().expect("wager should exist");
+        assert_eq!(wager_info.id, wager.id.to_string());
+        assert_eq!(wager_info.status, WagerStatus::Locked);
+        assert_eq!(wager_info.pot, 200);
+        let option_ids: Vec<String> = wager_info.options.iter().map(|option| option.id.clone()).collect();
+        assert_eq!(option_ids, vec![setup.wager_options[0].id.to_string(), setup.wager_options[1].id.to_string()]);
+        assert_eq!(wager_info.options[0].bets, vec![common::Bet {
+            id: setup.bets[0].id.to_string(),
+            user_id: setup.users[0].id.to_string(),
+            val: 200,
+            potential_payout: None,
+        }]);
+
+        let all_wager_info = setup.database_connection.get_all_bet_info().await.unwrap();
+        assert_eq!(all_wager_info.len(), 2);
+        assert!(all_wager_info.contains(&wager_info));
+    }
+
+    #[tokio::test]
+    async fn test_option_stats() {
+        let mut setup = setup_testing_database().await.unwrap();
+        let wager = setup.wagers[0].clone();
+        setup.database_connection.add_bet_db(&DbBet::new(setup.users[0].clone(), setup.wager_options[0].clone(), 100)).await.unwrap();
+        setup.database_connection.add_bet_db(&DbBet::new(setup.users[1].clone(), setup.wager_options[0].clone(), 100)).await.unwrap();
+        setup.database_connection.add_wager_option_db(&DbWagerOption::new("empty", "empty", wager.clone())).await.unwrap();
+
+        // 400 on the first option and 200 on the second, with a pot of 200 on top
+        let wager_info = setup.database_connection.get_info_for_wager(&wager).await.unwrap().expect("wager should exist");
+        let stats: Vec<common::OptionStats> = wager_info.options.iter().map(|option| option.stats).collect();
+        assert_eq!(stats, vec![
+            common::OptionStats { total_stake: 400, bettors: 2, implied_probability: Some(6666), multiplier: Some(200) },
+            common::OptionStats { total_stake: 200, bettors: 1, implied_probability: Some(3333), multiplier: Some(400) },
+            common::OptionStats::default(),
+        ]);
+
+        let mut fixed = DbWager::new("fixed", "fixed", 0);
+        fixed.kind = WagerKind::FixedOdds;
+        let fixed = setup.database_connection.add_wager(&fixed).await.unwrap().unwrap().id;
+        let option = setup.database_connection.add_wager_option_db(&DbWagerOption::new("option", "option", fixed.clone())).await.unwrap().unwrap().id;
+        setup.database_connection.set_option_odds(&option, 250).await.unwrap();
+        let wager_info = setup.database_connection.get_info_for_wager(&fixed).await.unwrap().expect("wager should exist");
+        assert_eq!((wager_info.options[0].stats.implied_probability, wager_info.options[0].stats.multiplier), (Some(4000), Some(250)));
+    }
+
     #[tokio::test]
     async fn test_get_all_wagers() {
         let setup = setup_testing_database().await.unwrap();
@@ -798,7 +3071,19 @@ mod test {
                     setup.wager_options.get(2).clone().unwrap().to_owned(),
                     setup.wager_options.get(3).clone().unwrap().to_owned()
                 ],
-                pot: 200
+                pot: 200,
+                status: WagerStatus::Open,
+                closes_at: None,
+                resolution: None,
+                rake: Rake::default(),
+                kind: WagerKind::PariMutuel,
+                no_winner_policy: NoWinnerPolicy::Refund,
+                stake_limits: StakeLimits::default(),
+                category: None,
+                game: None,
+                tags: vec![],
+                electorate: Electorate::Nobody,
+                vote_deadlocked: false,
             },
             DbWager {
                 id: setup.wagers.get(0).clone().unwrap().to_owned(),
@@ -808,7 +3093,19 @@ mod test {
                     setup.wager_options.get(0).clone().unwrap().to_owned(),
                     setup.wager_options.get(1).clone().unwrap().to_owned()
                 ],
-                pot: 200
+                pot: 200,
+                status: WagerStatus::Open,
+                closes_at: None,
+                resolution: None,
+                rake: Rake::default(),
+                kind: WagerKind::PariMutuel,
+                no_winner_policy: NoWinnerPolicy::Refund,
+                stake_limits: StakeLimits::default(),
+                category: None,
+                game: None,
+                tags: vec![],
+                electorate: Electorate::Nobody,
+                vote_deadlocked: false,
             },
         };
 
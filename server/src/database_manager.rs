@@ -1,7 +1,11 @@
+use common::{ChallengeStatus, StakeLimits, WagerFilter, WagerStatus};
 use surrealdb::Connection;
 use surrealdb::sql::Thing;
 use tokio::sync::{mpsc, oneshot};
-use crate::database::{DatabaseConnection, DbUser};
+use crate::database::{
+    BracketLayout, BracketSlot, DatabaseConnection, DbBet, DbBracketMatch, DbChallenge, DbDispute, DbParlay, DbResolution,
+    DbGsiLink, DbUser, DbVote, DbWager, DbWagerOption, OptionPayout, ParlaySettlement, Settlement,
+};
 
 pub type Responder<T> = oneshot::Sender<anyhow::Result<T>>;
 
@@ -10,16 +14,217 @@ pub enum DatabaseRequest {
         name: String,
         responder: Responder<Option<DbUser>>,
     },
-    GetAllWagerInfo {
+    GetWagerInfoMatching {
+        filter: WagerFilter,
         responder: Responder<Vec<common::Wager>>,
     },
     GetWagerInfo {
         id: Thing,
         responder: Responder<Option<common::Wager>>,
     },
-    ProvidePayout {
-        bet_info: common::Bet,
-        winning_ratio: f64,
+    GetWagerOption {
+        id: Thing,
+        responder: Responder<Option<DbWagerOption>>,
+    },
+    GetBet {
+        id: Thing,
+        responder: Responder<Option<DbBet>>,
+    },
+    GetOpenWagersWithCloseTime {
+        responder: Responder<Vec<DbWager>>,
+    },
+    CreateWager {
+        wager: DbWager,
+        options: Vec<DbWagerOption>,
+        responder: Responder<()>,
+    },
+    EditWager {
+        id: Thing,
+        name: Option<String>,
+        description: Option<String>,
+        pot: Option<u64>,
+        responder: Responder<()>,
+    },
+    AddWagerOption {
+        option: DbWagerOption,
+        responder: Responder<()>,
+    },
+    EditWagerOption {
+        id: Thing,
+        name: Option<String>,
+        description: Option<String>,
+        responder: Responder<()>,
+    },
+    RemoveWagerOption {
+        id: Thing,
+        responder: Responder<()>,
+    },
+    ReopenWager {
+        id: Thing,
+        closes_at: Option<u64>,
+        responder: Responder<()>,
+    },
+    SetWagerStatus {
+        id: Thing,
+        status: WagerStatus,
+        responder: Responder<()>,
+    },
+    CancelWager {
+        id: Thing,
+        responder: Responder<()>,
+    },
+    SetStakeLimits {
+        id: Thing,
+        limits: StakeLimits,
+        responder: Responder<()>,
+    },
+    SetOptionOdds {
+        id: Thing,
+        odds: u32,
+        responder: Responder<()>,
+    },
+    PlaceBet {
+        bet: DbBet,
+        max_liability: Option<u64>,
+        responder: Responder<(u64, DbBet)>,
+    },
+    ChangeBetStake {
+        bet: Thing,
+        user: Thing,
+        val: u64,
+        placed_probability: Option<u32>,
+        responder: Responder<(u64, DbBet)>,
+    },
+    WithdrawBet {
+        bet: Thing,
+        user: Thing,
+        responder: Responder<u64>,
+    },
+    CashOutBet {
+        bet: Thing,
+        user: Thing,
+        stake: u64,
+        price: u64,
+        remaining_payout: Option<u64>,
+        responder: Responder<u64>,
+    },
+    PlaceParlay {
+        parlay: DbParlay,
+        max_liability: Option<u64>,
+        responder: Responder<(u64, DbParlay)>,
+    },
+    GetActiveParlays {
+        responder: Responder<Vec<DbParlay>>,
+    },
+    ProposeChallenge {
+        challenge: DbChallenge,
+        responder: Responder<DbChallenge>,
+    },
+    AcceptChallenge {
+        id: Thing,
+        user: Thing,
+        responder: Responder<(u64, DbChallenge)>,
+    },
+    DeclineChallenge {
+        id: Thing,
+        user: Thing,
+        responder: Responder<()>,
+    },
+    GetChallenge {
+        id: Thing,
+        responder: Responder<Option<DbChallenge>>,
+    },
+    GetChallengeForWager {
+        wager: Thing,
+        responder: Responder<Option<DbChallenge>>,
+    },
+    GetChallengesForUser {
+        user: Thing,
+        responder: Responder<Vec<DbChallenge>>,
+    },
+    FinishChallenge {
+        id: Thing,
+        status: ChallengeStatus,
+        winner: Option<Thing>,
+        responder: Responder<DbChallenge>,
+    },
+    AddBracket {
+        layout: BracketLayout,
+        responder: Responder<()>,
+    },
+    GetBracketMatchForWager {
+        wager: Thing,
+        responder: Responder<Option<DbBracketMatch>>,
+    },
+    GetResolvedBracketMatches {
+        responder: Responder<Vec<DbBracketMatch>>,
+    },
+    FillBracketSlot {
+        slot: BracketSlot,
+        team: String,
+        responder: Responder<()>,
+    },
+    GetPayoutsForWager {
+        id: Thing,
+        responder: Responder<Vec<OptionPayout>>,
+    },
+    SettleParlays {
+        wager: Thing,
+        settlements: Vec<ParlaySettlement>,
+        settled_at: u64,
+        responder: Responder<()>,
+    },
+    ResolveWager {
+        id: Thing,
+        resolution: DbResolution,
+        settlement: Settlement,
+        responder: Responder<()>,
+    },
+    FinaliseResolution {
+        id: Thing,
+        responder: Responder<()>,
+    },
+    OverturnResolution {
+        id: Thing,
+        responder: Responder<()>,
+    },
+    AddDispute {
+        dispute: DbDispute,
+        responder: Responder<()>,
+    },
+    GetPendingResolutions {
+        responder: Responder<Vec<DbWager>>,
+    },
+    // responds with every vote cast on the wager so far
+    CastVote {
+        vote: DbVote,
+        responder: Responder<Vec<DbVote>>,
+    },
+    SetVoteDeadlocked {
+        id: Thing,
+        deadlocked: bool,
+        responder: Responder<()>,
+    },
+    GetVotesForWager {
+        id: Thing,
+        responder: Responder<Vec<DbVote>>,
+    },
+    GetUndecidedVotes {
+        responder: Responder<Vec<DbVote>>,
+    },
+    GetAdmins {
+        responder: Responder<Vec<DbUser>>,
+    },
+    AddGsiLink {
+        link: DbGsiLink,
+        responder: Responder<()>,
+    },
+    GetGsiLink {
+        id: Thing,
+        responder: Responder<Option<DbGsiLink>>,
+    },
+    SpendGsiLink {
+        id: Thing,
         responder: Responder<()>,
     },
 }
@@ -54,24 +259,280 @@ impl<Conn: Connection> DatabaseManager<Conn> {
                     let resp = transform_err(self.db_connection.get_user_by_name(name.as_ref()).await);
                     let _ = responder.send(resp);
                 }
-                DatabaseRequest::GetAllWagerInfo { responder } => {
-                    let resp = transform_err(self.db_connection.get_all_bet_info().await);
+                DatabaseRequest::GetWagerInfoMatching { filter, responder } => {
+                    let resp = transform_err(self.db_connection.get_wager_info_matching(&filter).await);
                     let _ = responder.send(resp);
                 }
                 DatabaseRequest::GetWagerInfo { id, responder } => {
                     let resp = transform_err(self.db_connection.get_info_for_wager(&id).await);
                     let _ = responder.send(resp);
                 }
-                DatabaseRequest::ProvidePayout {
-                    bet_info,
-                    winning_ratio,
+                DatabaseRequest::GetWagerOption { id, responder } => {
+                    let resp = transform_err(self.db_connection.select(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetBet { id, responder } => {
+                    let resp = transform_err(self.db_connection.select(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetOpenWagersWithCloseTime { responder } => {
+                    let resp = transform_err(self.db_connection.get_open_wagers_with_close_time().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::CreateWager {
+                    wager,
+                    options,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.create_wager(&wager, &options).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::EditWager {
+                    id,
+                    name,
+                    description,
+                    pot,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.edit_wager(&id, name, description, pot).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::AddWagerOption { option, responder } => {
+                    let resp = transform_err(self.db_connection.add_wager_option_db(&option).await.map(|_| ()));
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::EditWagerOption {
+                    id,
+                    name,
+                    description,
                     responder,
                 } => {
-                    let resp = self
-                        .db_connection
-                        .provide_payout_for_bet(&bet_info, winning_ratio)
-                        .await;
-                    let _ = responder.send(transform_err(resp));
+                    let resp = transform_err(self.db_connection.edit_wager_option(&id, name, description).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::RemoveWagerOption { id, responder } => {
+                    let resp = transform_err(self.db_connection.remove_wager_option(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::ReopenWager {
+                    id,
+                    closes_at,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.reopen_wager(&id, closes_at).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SetWagerStatus {
+                    id,
+                    status,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.set_wager_status(&id, status).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::CancelWager { id, responder } => {
+                    let resp = transform_err(self.db_connection.cancel_wager(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SetStakeLimits {
+                    id,
+                    limits,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.set_stake_limits(&id, limits).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SetOptionOdds {
+                    id,
+                    odds,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.set_option_odds(&id, odds).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::PlaceBet {
+                    bet,
+                    max_liability,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.place_bet(&bet, max_liability).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::ChangeBetStake {
+                    bet,
+                    user,
+                    val,
+                    placed_probability,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.change_bet_stake(&bet, &user, val, placed_probability).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::WithdrawBet {
+                    bet,
+                    user,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.withdraw_bet(&bet, &user).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::CashOutBet {
+                    bet,
+                    user,
+                    stake,
+                    price,
+                    remaining_payout,
+                    responder,
+                } => {
+                    let resp = transform_err(
+                        self.db_connection
+                            .cash_out_bet(&bet, &user, stake, price, remaining_payout)
+                            .await,
+                    );
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::PlaceParlay {
+                    parlay,
+                    max_liability,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.place_parlay(&parlay, max_liability).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetActiveParlays { responder } => {
+                    let resp = transform_err(self.db_connection.get_active_parlays().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::ProposeChallenge { challenge, responder } => {
+                    let resp = transform_err(self.db_connection.propose_challenge(&challenge).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::AcceptChallenge { id, user, responder } => {
+                    let resp = transform_err(self.db_connection.accept_challenge(&id, &user).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::DeclineChallenge { id, user, responder } => {
+                    let resp = transform_err(self.db_connection.decline_challenge(&id, &user).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetChallenge { id, responder } => {
+                    let resp = transform_err(self.db_connection.select(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetChallengeForWager { wager, responder } => {
+                    let resp = transform_err(self.db_connection.get_challenge_for_wager(&wager).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetChallengesForUser { user, responder } => {
+                    let resp = transform_err(self.db_connection.get_challenges_for_user(&user).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::FinishChallenge {
+                    id,
+                    status,
+                    winner,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.finish_challenge(&id, status, winner.as_ref()).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::AddBracket { layout, responder } => {
+                    let resp = transform_err(self.db_connection.add_bracket(&layout).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetBracketMatchForWager { wager, responder } => {
+                    let resp = transform_err(self.db_connection.get_bracket_match_for_wager(&wager).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetResolvedBracketMatches { responder } => {
+                    let resp = transform_err(self.db_connection.get_resolved_bracket_matches().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::FillBracketSlot {
+                    slot,
+                    team,
+                    responder,
+                } => {
+                    let resp = transform_err(self.db_connection.fill_bracket_slot(&slot, &team).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetPayoutsForWager { id, responder } => {
+                    let resp = transform_err(self.db_connection.get_payouts_for_wager(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SettleParlays {
+                    wager,
+                    settlements,
+                    settled_at,
+                    responder,
+                } => {
+                    let resp = transform_err(
+                        self.db_connection
+                            .settle_parlays(&wager, &settlements, settled_at)
+                            .await,
+                    );
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::ResolveWager {
+                    id,
+                    resolution,
+                    settlement,
+                    responder,
+                } => {
+                    let resp = transform_err(
+                        self.db_connection
+                            .resolve_wager(&id, &resolution, &settlement)
+                            .await,
+                    );
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::FinaliseResolution { id, responder } => {
+                    let resp = transform_err(self.db_connection.finalise_resolution(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::OverturnResolution { id, responder } => {
+                    let resp = transform_err(self.db_connection.overturn_resolution(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::AddDispute { dispute, responder } => {
+                    let resp = transform_err(self.db_connection.add_dispute(&dispute).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetPendingResolutions { responder } => {
+                    let resp = transform_err(self.db_connection.get_pending_resolutions().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::CastVote { vote, responder } => {
+                    let resp = transform_err(self.db_connection.cast_vote(&vote).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SetVoteDeadlocked { id, deadlocked, responder } => {
+                    let resp = transform_err(self.db_connection.set_vote_deadlocked(&id, deadlocked).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetVotesForWager { id, responder } => {
+                    let resp = transform_err(self.db_connection.get_votes_for_wager(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetUndecidedVotes { responder } => {
+                    let resp = transform_err(self.db_connection.get_undecided_votes().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetAdmins { responder } => {
+                    let resp = transform_err(self.db_connection.get_admins().await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::AddGsiLink { link, responder } => {
+                    let resp = transform_err(self.db_connection.add_gsi_link(&link).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::GetGsiLink { id, responder } => {
+                    let resp = transform_err(self.db_connection.select(&id).await);
+                    let _ = responder.send(resp);
+                }
+                DatabaseRequest::SpendGsiLink { id, responder } => {
+                    let resp = transform_err(self.db_connection.spend_gsi_link(&id).await);
+                    let _ = responder.send(resp);
                 }
             }
         }
@@ -0,0 +1,432 @@
+// Game state integration. Counter-Strike 2 and Dota 2 can be configured to POST their game state as JSON
+// to an HTTP endpoint, authenticated with a token from the config. The token is the id of a `gsi_link`,
+// which says which wager the game settles, and the wager is resolved as soon as the game reports a winner
+use anyhow::{anyhow, bail};
+use common::GsiGame;
+use serde::Deserialize;
+use std::time::Duration;
+use surrealdb::sql::{Id, Thing};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+use crate::database::{DbGsiLink, DbWinner, TABLE_GSI_LINK};
+use crate::database_manager::DatabaseRequest;
+use crate::wager_manager::WagerRequest;
+
+const APP_ID_COUNTER_STRIKE: u32 = 730;
+const APP_ID_DOTA: u32 = 570;
+// game state posts are a few kilobytes. Anything much bigger is not from a game
+const MAX_REQUEST_LENGTH: u64 = 1 << 20;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The parts of a game state post needed to tell who won. Everything else the games send is ignored
+#[derive(Deserialize, Debug)]
+struct GameState {
+    provider: Option<Provider>,
+    map: Option<MapState>,
+    auth: Option<Auth>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Provider {
+    appid: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Auth {
+    token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MapState {
+    name: Option<String>,
+    // Counter-Strike 2 only
+    phase: Option<String>,
+    team_ct: Option<TeamState>,
+    team_t: Option<TeamState>,
+    // Dota 2 only
+    matchid: Option<String>,
+    win_team: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TeamState {
+    score: u32,
+    // only set when the teams have been given names
+    name: Option<String>,
+}
+
+// How a finished map or match turned out
+#[derive(Debug, PartialEq, Eq)]
+pub struct GameResult {
+    pub game: GsiGame,
+    pub map_name: Option<String>,
+    pub match_id: Option<String>,
+    // the side the winner finished on, lowercase
+    pub winning_side: String,
+    // lowercase
+    pub winning_name: Option<String>,
+}
+
+// The token a game state post was sent with, and the result if the game it describes has finished
+pub fn parse_game_state(body: &[u8]) -> anyhow::Result<(String, Option<GameResult>)> {
+    let state: GameState = serde_json::from_slice(body)?;
+    let token = state.auth.ok_or(anyhow!("game state has no auth token"))?.token;
+    let result = match (state.provider, state.map) {
+        (Some(provider), Some(map)) => game_result(provider.appid, map),
+        _ => None,
+    };
+    Ok((token, result))
+}
+
+fn game_result(app_id: u32, map: MapState) -> Option<GameResult> {
+    match app_id {
+        APP_ID_COUNTER_STRIKE => {
+            if map.phase.as_deref() != Some("gameover") {
+                return None;
+            }
+            let (ct, t) = (map.team_ct?, map.team_t?);
+            // a drawn map is left for an admin to settle
+            let (winning_side, winner) = match ct.score.cmp(&t.score) {
+                std::cmp::Ordering::Greater => ("ct", ct),
+                std::cmp::Ordering::Less => ("t", t),
+                std::cmp::Ordering::Equal => return None,
+            };
+            Some(GameResult {
+                game: GsiGame::CounterStrike2,
+                map_name: map.name.map(|name| name.to_lowercase()),
+                match_id: None,
+                winning_side: winning_side.into(),
+                winning_name: winner
+                    .name
+                    .map(|name| name.trim().to_lowercase())
+                    .filter(|name| !name.is_empty()),
+            })
+        }
+        // the winning team is "none" until the ancient falls
+        APP_ID_DOTA => match map.win_team.as_deref() {
+            Some(side @ ("radiant" | "dire")) => Some(GameResult {
+                game: GsiGame::Dota2,
+                map_name: None,
+                match_id: map.matchid,
+                winning_side: side.into(),
+                winning_name: None,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The option a result makes the winner of a link's wager, unless the result is not one the link is
+// waiting for. Teams are matched by name before side
+pub fn winning_option(link: &DbGsiLink, result: &GameResult) -> Option<Thing> {
+    if link.game != result.game
+        || (link.map_name.is_some() && link.map_name != result.map_name)
+        || (link.match_id.is_some() && link.match_id != result.match_id)
+    {
+        return None;
+    }
+    let by_name = result
+        .winning_name
+        .as_ref()
+        .and_then(|name| link.teams.iter().find(|team| team.team == *name));
+    by_name
+        .or_else(|| link.teams.iter().find(|team| team.team == result.winning_side))
+        .map(|team| team.option.clone())
+}
+
+pub async fn handle_gsi_server(
+    listener: TcpListener,
+    db_tx: mpsc::Sender<DatabaseRequest>,
+    wager_tx: mpsc::Sender<WagerRequest>,
+) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                eprintln!("failed to accept game state connection: {error:#}");
+                continue;
+            }
+        };
+        let db_tx = db_tx.clone();
+        let wager_tx = wager_tx.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, db_tx, wager_tx).await {
+                eprintln!("failed to handle game state post: {error:#}");
+            }
+        });
+    }
+}
+
+// Handles a single request and closes the connection. Games send each post on whatever connection they
+// have, so there is nothing to gain from keeping it alive
+async fn handle_connection(
+    mut stream: TcpStream,
+    db_tx: mpsc::Sender<DatabaseRequest>,
+    wager_tx: mpsc::Sender<WagerRequest>,
+) -> anyhow::Result<()> {
+    let (read, mut write) = stream.split();
+    let mut reader = BufReader::new(read.take(MAX_REQUEST_LENGTH));
+    let (method, body) = timeout(READ_TIMEOUT, read_request(&mut reader)).await??;
+
+    let status = if method != "POST" {
+        "405 Method Not Allowed"
+    } else {
+        match handle_game_state(&body, &db_tx, &wager_tx).await {
+            Ok(_) => "200 OK",
+            Err(error) => {
+                eprintln!("rejected game state post: {error:#}");
+                "400 Bad Request"
+            }
+        }
+    };
+    write
+        .write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes())
+        .await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+// Reads the method and body of an HTTP request. Games send their body with a `Content-Length`, so that is
+// the only kind of body supported
+async fn read_request(reader: &mut (impl AsyncBufRead + Unpin)) -> anyhow::Result<(String, Vec<u8>)> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let method = request_line
+        .split_whitespace()
+        .next()
+        .ok_or(anyhow!("empty request"))?
+        .to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            bail!("connection closed before the end of the headers");
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+        }
+    }
+
+    if content_length as u64 > MAX_REQUEST_LENGTH {
+        bail!("request body is too long");
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok((method, body))
+}
+
+// Resolves the wager linked to a post's token if the post reports a result for it. Returns the
+// resolution if this post resolved the wager
+async fn handle_game_state(
+    body: &[u8],
+    db_tx: &mpsc::Sender<DatabaseRequest>,
+    wager_tx: &mpsc::Sender<WagerRequest>,
+) -> anyhow::Result<Option<common::Resolution>> {
+    let (token, result) = parse_game_state(body)?;
+    let (link_tx, link_rx) = oneshot::channel();
+    db_tx
+        .send(DatabaseRequest::GetGsiLink {
+            id: Thing {
+                tb: TABLE_GSI_LINK.into(),
+                id: Id::String(token),
+            },
+            responder: link_tx,
+        })
+        .await?;
+    let link = link_rx.await??.ok_or(anyhow!("unknown game state token"))?;
+    if link.spent {
+        return Ok(None);
+    }
+    let Some(option) = result.and_then(|result| winning_option(&link, &result)) else {
+        return Ok(None);
+    };
+
+    let (resolve_tx, resolve_rx) = oneshot::channel();
+    wager_tx
+        .send(WagerRequest::ResolveWager {
+            wager_id: link.wager,
+            winners: vec![DbWinner { option, weight: None }],
+            user: None,
+            responder: resolve_tx,
+        })
+        .await?;
+    let resolution = resolve_rx.await??;
+
+    let (spend_tx, spend_rx) = oneshot::channel();
+    db_tx
+        .send(DatabaseRequest::SpendGsiLink {
+            id: link.id,
+            responder: spend_tx,
+        })
+        .await?;
+    spend_rx.await??;
+    Ok(Some(resolution))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::database::*;
+    use crate::database_manager::DatabaseManager;
+    use crate::wager_manager::WagerManager;
+    use common::WagerStatus;
+    use surrealdb::engine::local::Db;
+    use tokio::sync::broadcast;
+
+    fn fixture(name: &str, token: &str) -> Vec<u8> {
+        let path = format!("{}/fixtures/gsi/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(path).unwrap().replace("LINK_TOKEN", token).into_bytes()
+    }
+
+    fn team(team: &str, option: &str) -> DbGsiTeam {
+        DbGsiTeam { team: team.into(), option: Thing::from((TABLE_WAGER_OPTION, option)) }
+    }
+
+    fn link(game: GsiGame, teams: Vec<DbGsiTeam>) -> DbGsiLink {
+        let mut link = DbGsiLink::new(Thing::from((TABLE_WAGER, "wager")), common::GsiLink { game, map_name: None, match_id: None, teams: vec![] });
+        link.teams = teams;
+        link
+    }
+
+    #[test]
+    fn test_parse_counter_strike_fixtures() {
+        let (token, result) = parse_game_state(&fixture("cs2_live", "token")).unwrap();
+        assert_eq!((token.as_str(), result), ("token", None), "a map still being played has no result");
+
+        let (_, result) = parse_game_state(&fixture("cs2_gameover", "token")).unwrap();
+        assert_eq!(
+            result,
+            Some(GameResult {
+                game: GsiGame::CounterStrike2,
+                map_name: Some("de_mirage".into()),
+                match_id: None,
+                winning_side: "t".into(),
+                winning_name: Some("red rockets".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_dota_fixtures() {
+        let (_, result) = parse_game_state(&fixture("dota2_in_progress", "token")).unwrap();
+        assert_eq!(result, None);
+
+        let (_, result) = parse_game_state(&fixture("dota2_post_game", "token")).unwrap();
+        assert_eq!(
+            result,
+            Some(GameResult {
+                game: GsiGame::Dota2,
+                map_name: None,
+                match_id: Some("7987654321".into()),
+                winning_side: "dire".into(),
+                winning_name: None,
+            })
+        );
+        parse_game_state(b"{\"provider\": {\"appid\": 570}}").expect_err("posts need a token");
+        parse_game_state(b"not json").expect_err("posts need to be json");
+    }
+
+    #[test]
+    fn test_winning_option() {
+        let (_, result) = parse_game_state(&fixture("cs2_gameover", "token")).unwrap();
+        let result = result.unwrap();
+
+        let by_name = link(GsiGame::CounterStrike2, vec![team("blue bandits", "blue"), team("red rockets", "red")]);
+        assert_eq!(winning_option(&by_name, &result), Some(Thing::from((TABLE_WAGER_OPTION, "red"))));
+        let by_side = link(GsiGame::CounterStrike2, vec![team("ct", "ct"), team("t", "t")]);
+        assert_eq!(winning_option(&by_side, &result), Some(Thing::from((TABLE_WAGER_OPTION, "t"))));
+
+        let mut other_map = by_name.clone();
+        other_map.map_name = Some("de_inferno".into());
+        assert_eq!(winning_option(&other_map, &result), None, "only results on the linked map count");
+        let other_game = link(GsiGame::Dota2, vec![team("radiant", "radiant"), team("dire", "dire")]);
+        assert_eq!(winning_option(&other_game, &result), None);
+
+        let (_, result) = parse_game_state(&fixture("dota2_post_game", "token")).unwrap();
+        let result = result.unwrap();
+        assert_eq!(winning_option(&other_game, &result), Some(Thing::from((TABLE_WAGER_OPTION, "dire"))));
+        let mut other_match = other_game.clone();
+        other_match.match_id = Some("1".into());
+        assert_eq!(winning_option(&other_match, &result), None, "only the linked match counts");
+    }
+
+    async fn post(address: std::net::SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn post_request(body: &[u8]) -> Vec<u8> {
+        let mut request = format!("POST / HTTP/1.1\r\nHost: 127.0.0.1:3000\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        request.extend_from_slice(body);
+        request
+    }
+
+    #[tokio::test]
+    async fn test_post_resolves_linked_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner = database_connection.add_user(&DbUser::new("winner", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let wager = database_connection.add_wager(&DbWager::new("mirage", "mirage", 0)).await.unwrap().unwrap().id;
+        let red = database_connection.add_wager_option_db(&DbWagerOption::new("red", "red", wager.clone())).await.unwrap().unwrap().id;
+        let blue = database_connection.add_wager_option_db(&DbWagerOption::new("blue", "blue", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(winner, red.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(loser, blue.clone(), 100), None).await.unwrap();
+        let teams = vec![
+            common::GsiTeam { team: "Red Rockets".into(), wager_option_id: red.id.to_string() },
+            common::GsiTeam { team: "Blue Bandits".into(), wager_option_id: blue.id.to_string() },
+        ];
+        let link = DbGsiLink::new(wager.clone(), common::GsiLink { game: GsiGame::CounterStrike2, map_name: Some("de_mirage".into()), match_id: None, teams });
+        database_connection.add_gsi_link(&link).await.unwrap();
+        let token = link.id.id.to_string();
+
+        let (db_tx, db_rx) = mpsc::channel(32);
+        let mut db_manager = DatabaseManager::new(database_connection, db_rx);
+        tokio::spawn(async move {
+            db_manager.manage().await;
+        });
+        let (notification_tx, _) = broadcast::channel(32);
+        let (wager_tx, wager_rx) = mpsc::channel(32);
+        let mut wager_manager = WagerManager::new(wager_rx, db_tx.clone(), notification_tx, ServerConfig::default());
+        tokio::spawn(async move {
+            wager_manager.manage().await;
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(handle_gsi_server(listener, db_tx.clone(), wager_tx));
+
+        assert!(post(address, &post_request(&fixture("cs2_live", &token))).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(post(address, &post_request(&fixture("cs2_gameover", "unknown"))).await.starts_with("HTTP/1.1 400"));
+        assert!(post(address, b"GET / HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 405"));
+        let (wager_tx, wager_rx) = oneshot::channel();
+        db_tx.send(DatabaseRequest::GetWagerInfo { id: wager.clone(), responder: wager_tx }).await.unwrap();
+        assert_eq!(wager_rx.await.unwrap().unwrap().unwrap().status, WagerStatus::Open);
+
+        // games keep posting once the map is over, which changes nothing
+        for _ in 0..2 {
+            assert!(post(address, &post_request(&fixture("cs2_gameover", &token))).await.starts_with("HTTP/1.1 200 OK"));
+        }
+        let (wager_tx, wager_rx) = oneshot::channel();
+        db_tx.send(DatabaseRequest::GetWagerInfo { id: wager.clone(), responder: wager_tx }).await.unwrap();
+        let wager_info = wager_rx.await.unwrap().unwrap().unwrap();
+        assert_eq!(wager_info.status, WagerStatus::Resolved);
+        assert_eq!(wager_info.resolution.unwrap().winners, vec![common::Winner { option_id: red.id.to_string(), weight: None }]);
+        let (user_tx, user_rx) = oneshot::channel();
+        db_tx.send(DatabaseRequest::GetUser { name: "winner".into(), responder: user_tx }).await.unwrap();
+        assert_eq!(user_rx.await.unwrap().unwrap().unwrap().balance, 1100);
+    }
+}
@@ -1,22 +1,29 @@
 use surrealdb::Connection;
 use surrealdb::engine::remote::ws::Client;
 use tokio::join;
-use tokio::sync::mpsc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
 
+mod config;
 mod database;
 mod database_manager;
 mod wager_manager;
 mod connection_manager;
+mod payout;
+mod bracket;
+mod gsi;
 
+use config::ServerConfig;
 use database::*;
 use database_manager::DatabaseManager;
 use wager_manager::WagerManager;
 
 #[tokio::main]
 async fn main() {
+    let config = ServerConfig::from_env().unwrap();
     let mut database = DatabaseConnection::<Client>::new("127.0.0.1:8000").await.unwrap();
 
-    let _ = generate_test_data(&mut database).await;
+    let _ = generate_test_data(&mut database, &config).await;
 
     let (db_tx, db_rx) = mpsc::channel(32);
     let mut db_manager = DatabaseManager::new(database, db_rx);
@@ -25,32 +32,54 @@ async fn main() {
         db_manager.manage().await;
     });
 
+    let (notification_tx, _) = broadcast::channel(32);
+
     let (wager_tx, wager_rx) = mpsc::channel(32);
-    let mut wager_manager = WagerManager::new(wager_rx, db_tx.clone());
+    let mut wager_manager = WagerManager::new(wager_rx, db_tx.clone(), notification_tx.clone(), config.clone());
 
     let wager_task = tokio::spawn(async move {
         wager_manager.manage().await;
     });
 
+    // bound up front, so a taken port stops the server instead of leaving games with nowhere to post to
+    let gsi_listener = TcpListener::bind(&config.gsi_address)
+        .await
+        .unwrap_or_else(|error| panic!("failed to listen for game state on {}: {}", config.gsi_address, error));
+    let gsi_db_tx = db_tx.clone();
+    let gsi_wager_tx = wager_tx.clone();
+    let gsi_task = tokio::spawn(async move {
+        gsi::handle_gsi_server(gsi_listener, gsi_db_tx, gsi_wager_tx).await;
+    });
+
     let listen_server_task = tokio::spawn(async move {
-        connection_manager::hande_listen_server(db_tx, wager_tx).await;
+        connection_manager::hande_listen_server(db_tx, wager_tx, notification_tx).await;
     });
 
-    let (res1, res2, res3) = join!(db_task, wager_task, listen_server_task);
+    let (res1, res2, res3, res4) = join!(db_task, wager_task, gsi_task, listen_server_task);
     res1.unwrap();
     res2.unwrap();
     res3.unwrap();
+    res4.unwrap();
 }
 
-async fn generate_test_data(database_connection: &mut DatabaseConnection<impl Connection>) -> anyhow::Result<()> {
-    let user_id = database_connection.add_user(&DbUser::new("aidan", 2000)).await?;
+async fn generate_test_data(database_connection: &mut DatabaseConnection<impl Connection>, config: &ServerConfig) -> anyhow::Result<()> {
+    let mut user = DbUser::new("aidan", 2000);
+    user.admin = true;
+    let user_id = database_connection.add_user(&user).await?;
     let user_id = if let Some(record) = user_id {
         record.id
     } else {
         database_connection.get_user_by_name("aidan").await?.unwrap().id // presumably user already exists if we get none. A fatal error if it doesn't exist here
     };
 
-    let wager_id = database_connection.add_wager(&DbWager::new("test_wager1", "a test wager", 200)).await?;
+    let mut wager = DbWager::new("test_wager1", "a test wager", 200);
+    wager.rake = config.default_rake;
+    wager.category = Some("match winner".into());
+    wager.game = Some("counter-strike 2".into());
+    wager.tags = vec!["grand final".into()];
+    let wager_id = database_connection.add_wager(&wager).await?;
+    // only a freshly seeded wager gets a bet, so restarting does not keep adding them
+    let new_wager = wager_id.is_some();
     let wager_id = if let Some(record) = wager_id {
         record.id
     } else {
@@ -65,6 +94,21 @@ async fn generate_test_data(database_connection: &mut DatabaseConnection<impl Co
     };
 
     //we truly do not care if this doesn't work
-    let _bet_id = database_connection.add_bet_db(&DbBet::new(user_id, wager_option_id, 200)).await;
+    if new_wager {
+        let _bet = database_connection.place_bet(&DbBet::new(user_id, wager_option_id, 200), None).await;
+    }
+
+    let mut numeric_wager = DbWager::new("test_numeric_wager1", "total kills", 0);
+    numeric_wager.rake = config.default_rake;
+    numeric_wager.category = Some("player props".into());
+    numeric_wager.game = Some("counter-strike 2".into());
+    numeric_wager.tags = vec!["grand final".into(), "kills".into()];
+    if let Some(record) = database_connection.add_wager(&numeric_wager).await? {
+        for (name, range) in common::OutcomeRange::over_under(4550) {
+            let mut option = DbWagerOption::new(name.clone(), name, record.id.clone());
+            option.range = Some(range);
+            database_connection.add_wager_option_db(&option).await?;
+        }
+    }
     Ok(())
 }
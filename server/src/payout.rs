@@ -0,0 +1,341 @@
+// Integer payout arithmetic. Money is only ever moved in whole units, so every split here hands out
+// exactly what it is given: nothing is created or destroyed by rounding
+
+// Splits `pool` between `weights` in proportion to their size. Each share is rounded down, then the
+// units left over are handed out one at a time by largest remainder, ties going to the earlier weight.
+// If there is no weight at all, nothing can be split and every share is zero
+pub fn split_proportionally(pool: u64, weights: &[u64]) -> Vec<u64> {
+    let total_weight: u128 = weights.iter().map(|&weight| weight as u128).sum();
+    if total_weight == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let mut shares = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    for (index, &weight) in weights.iter().enumerate() {
+        let exact_share = weight as u128 * pool as u128;
+        shares.push((exact_share / total_weight) as u64);
+        remainders.push((exact_share % total_weight, index));
+    }
+
+    let leftover = pool - shares.iter().sum::<u64>();
+    remainders.sort_by(|(remainder_a, index_a), (remainder_b, index_b)| {
+        remainder_b.cmp(remainder_a).then(index_a.cmp(index_b))
+    });
+    for &(_, index) in remainders.iter().take(leftover as usize) {
+        shares[index] += 1;
+    }
+    shares
+}
+
+// The house's cut of a losing pool. Rounded down, so the rake never takes more than advertised
+pub fn rake(losing_total: u64, basis_points: u32) -> u64 {
+    (losing_total as u128 * basis_points as u128 / 10_000) as u64
+}
+
+// What a fixed-odds bet pays if it wins, stake included. Odds are in hundredths and the payout is
+// rounded down
+pub fn fixed_odds_payout(stake: u64, odds: u32) -> u64 {
+    (stake as u128 * odds as u128 / 100) as u64
+}
+
+// Pari-mutuel payouts for the winning bets, given in the order they were placed. The winners share
+// everything staked on the wager plus its pot, in proportion to their stakes
+pub fn pari_mutuel_payouts(winning_stakes: &[u64], losing_total: u64, pot: u64) -> Vec<u64> {
+    let winning_total: u64 = winning_stakes.iter().sum();
+    split_proportionally(winning_total + losing_total + pot, winning_stakes)
+}
+
+// Payouts for a pari-mutuel wager with several winning options, such as a draw or a dead heat. The
+// losing pool and pot are split between the winning options by weight, then within each option in
+// proportion to stake. A winning option nobody bet on takes no share. Payouts come back per option,
+// in the order given
+pub fn dead_heat_payouts(winning_options: &[(u32, Vec<u64>)], losing_total: u64, pot: u64) -> Vec<Vec<u64>> {
+    let weights: Vec<u64> = winning_options
+        .iter()
+        .map(|(weight, stakes)| if stakes.iter().sum::<u64>() == 0 { 0 } else { *weight as u64 })
+        .collect();
+    let option_pools = split_proportionally(losing_total + pot, &weights);
+    winning_options
+        .iter()
+        .zip(option_pools)
+        .map(|((_, stakes), pool)| pari_mutuel_payouts(stakes, pool, 0))
+        .collect()
+}
+
+// A fixed-odds bet on one of several tied winners is paid its share of the promised payout, rounded down
+pub fn dead_heat_fixed_odds_payout(potential_payout: u64, weight: u32, total_weight: u32) -> u64 {
+    (potential_payout as u128 * weight as u128 / total_weight as u128) as u64
+}
+
+// The chance of an option winning implied by its share of everything staked, in basis points and
+// rounded down
+pub fn implied_probability(option_stake: u64, total_stake: u64) -> Option<u32> {
+    if option_stake == 0 || total_stake == 0 {
+        return None;
+    }
+    Some((option_stake as u128 * 10_000 / total_stake as u128) as u32)
+}
+
+// The chance of winning implied by fixed odds in hundredths, in basis points and rounded down
+pub fn odds_implied_probability(odds: u32) -> Option<u32> {
+    if odds == 0 {
+        return None;
+    }
+    Some((1_000_000 / odds as u64) as u32)
+}
+
+// What a pari-mutuel option pays per unit staked if it is the only winner, stake included, in hundredths
+// and rounded down. As when resolving, the rake comes out of the losing pool and the pot is paid out too
+pub fn pari_mutuel_multiplier(option_stake: u64, losing_total: u64, pot: u64, rake_basis_points: u32) -> Option<u64> {
+    if option_stake == 0 {
+        return None;
+    }
+    let paid_out = option_stake as u128 + (losing_total - rake(losing_total, rake_basis_points)) as u128 + pot as u128;
+    u64::try_from(paid_out * 100 / option_stake as u128).ok()
+}
+
+// What a winning parlay pays: the stake multiplied by the multiplier of every leg, each given as a
+// numerator and denominator. The product is kept exact and only rounded down at the end, unless it
+// grows too large for that, in which case it is rounded down as it goes
+pub fn parlay_payout(stake: u64, multipliers: &[(u64, u64)]) -> u64 {
+    let (mut numerator, mut denominator) = (stake as u128, 1u128);
+    for &(leg_numerator, leg_denominator) in multipliers {
+        let (leg_numerator, leg_denominator) = (leg_numerator as u128, leg_denominator as u128);
+        match (numerator.checked_mul(leg_numerator), denominator.checked_mul(leg_denominator)) {
+            (Some(product_numerator), Some(product_denominator)) => {
+                let divisor = gcd(product_numerator, product_denominator);
+                numerator = product_numerator / divisor;
+                denominator = product_denominator / divisor;
+            }
+            _ => {
+                numerator = (numerator / denominator).saturating_mul(leg_numerator) / leg_denominator;
+                denominator = 1;
+            }
+        }
+    }
+    u64::try_from(numerator / denominator).unwrap_or(u64::MAX)
+}
+
+// What selling back `stake` of a bet before its wager locks is worth: the stake scaled by how much the
+// option's implied chance of winning has moved since the bet was placed, capped at the stake, less the
+// haircut. Probabilities are in basis points, and one that is not known counts as unchanged. Rounded down
+pub fn cash_out_value(stake: u64, placed_probability: Option<u32>, current_probability: Option<u32>, haircut_basis_points: u32) -> u64 {
+    let value = match (placed_probability, current_probability) {
+        (Some(placed), Some(current)) if placed > 0 => (stake as u128 * current as u128 / placed as u128).min(stake as u128),
+        _ => stake as u128,
+    };
+    (value * (10_000 - haircut_basis_points.min(10_000)) as u128 / 10_000) as u64
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_split_proportionally() {
+        assert_eq!(split_proportionally(100, &[1, 1, 1]), vec![34, 33, 33]);
+        assert_eq!(split_proportionally(100, &[2, 1, 1]), vec![50, 25, 25]);
+        assert_eq!(split_proportionally(10, &[1, 3, 3]), vec![2, 4, 4]);
+        assert_eq!(split_proportionally(11, &[3, 3, 1]), vec![5, 5, 1]);
+        assert_eq!(split_proportionally(10, &[0, 0]), vec![0, 0]);
+        assert_eq!(split_proportionally(10, &[]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_pari_mutuel_payouts() {
+        // 600 staked in total plus a pot of 200, shared by the two winners 2:1
+        assert_eq!(pari_mutuel_payouts(&[200, 100], 300, 200), vec![533, 267]);
+        assert_eq!(pari_mutuel_payouts(&[200], 200, 200), vec![600]);
+    }
+
+    #[test]
+    fn test_dead_heat_payouts() {
+        // a 300 losing pool split evenly between two winning options
+        assert_eq!(dead_heat_payouts(&[(1, vec![200, 100]), (1, vec![100])], 300, 0), vec![vec![300, 150], vec![250]]);
+        assert_eq!(dead_heat_payouts(&[(3, vec![100]), (1, vec![100])], 400, 0), vec![vec![400], vec![200]]);
+        // nobody bet on the second winner, so the first takes everything
+        assert_eq!(dead_heat_payouts(&[(1, vec![100]), (1, vec![])], 300, 100), vec![vec![500], vec![]]);
+        assert_eq!(dead_heat_payouts(&[(1, vec![200, 100])], 300, 200), vec![pari_mutuel_payouts(&[200, 100], 300, 200)]);
+    }
+
+    #[test]
+    fn test_dead_heat_fixed_odds_payout() {
+        assert_eq!(dead_heat_fixed_odds_payout(300, 1, 2), 150);
+        assert_eq!(dead_heat_fixed_odds_payout(301, 1, 3), 100);
+        assert_eq!(dead_heat_fixed_odds_payout(300, 1, 1), 300);
+    }
+
+    #[test]
+    fn test_parlay_payout() {
+        assert_eq!(parlay_payout(100, &[(250, 100), (3, 2)]), 375);
+        // rounding happens once, not per leg
+        assert_eq!(parlay_payout(10, &[(4, 3), (3, 2)]), 20);
+        assert_eq!(parlay_payout(100, &[]), 100);
+        assert_eq!(parlay_payout(u64::MAX, &[(u64::MAX, 1), (u64::MAX, 1)]), u64::MAX);
+    }
+
+    #[test]
+    fn test_implied_probability() {
+        assert_eq!(implied_probability(100, 400), Some(2500));
+        assert_eq!(implied_probability(1, 3), Some(3333));
+        assert_eq!(implied_probability(0, 400), None);
+        assert_eq!(odds_implied_probability(250), Some(4000));
+        assert_eq!(odds_implied_probability(0), None);
+    }
+
+    #[test]
+    fn test_pari_mutuel_multiplier() {
+        assert_eq!(pari_mutuel_multiplier(100, 300, 0, 0), Some(400));
+        // 5% of the 300 losing pool is raked, and the pot of 200 is shared too
+        assert_eq!(pari_mutuel_multiplier(100, 300, 200, 500), Some(585));
+        assert_eq!(pari_mutuel_multiplier(0, 300, 0, 0), None);
+    }
+
+    #[test]
+    fn test_fixed_odds_payout() {
+        assert_eq!(fixed_odds_payout(100, 250), 250);
+        assert_eq!(fixed_odds_payout(101, 250), 252);
+        assert_eq!(fixed_odds_payout(u64::MAX, 100), u64::MAX);
+    }
+
+    #[test]
+    fn test_rake() {
+        assert_eq!(rake(1000, 250), 25);
+        assert_eq!(rake(999, 250), 24);
+        assert_eq!(rake(1000, 0), 0);
+        assert_eq!(rake(1000, 10_000), 1000);
+    }
+
+    #[test]
+    fn test_cash_out_value() {
+        // the option has gone from a 40% to a 30% chance, and 10% is kept back
+        assert_eq!(cash_out_value(100, Some(4_000), Some(3_000), 1_000), 67);
+        // shortening never pays out more than the stake
+        assert_eq!(cash_out_value(100, Some(3_000), Some(6_000), 1_000), 90);
+        assert_eq!(cash_out_value(100, None, Some(6_000), 0), 100);
+        assert_eq!(cash_out_value(100, Some(4_000), Some(3_000), 10_000), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn cash_out_never_pays_more_than_the_stake(
+            stake in 0u64..u64::MAX,
+            placed in prop::option::of(0u32..=10_000),
+            current in prop::option::of(0u32..=10_000),
+            haircut in 0u32..=10_000,
+        ) {
+            prop_assert!(cash_out_value(stake, placed, current, haircut) <= stake);
+        }
+
+        #[test]
+        fn payouts_add_up_to_everything_staked_plus_the_pot(
+            winning_stakes in prop::collection::vec(1u64..1_000_000_000, 1..50),
+            losing_total in 0u64..1_000_000_000_000,
+            pot in 0u64..1_000_000_000_000,
+        ) {
+            let payouts = pari_mutuel_payouts(&winning_stakes, losing_total, pot);
+            let winning_total: u64 = winning_stakes.iter().sum();
+            prop_assert_eq!(payouts.len(), winning_stakes.len());
+            prop_assert_eq!(payouts.iter().sum::<u64>(), winning_total + losing_total + pot);
+        }
+
+        #[test]
+        fn payouts_and_rake_add_up_to_everything_staked_plus_the_pot(
+            winning_stakes in prop::collection::vec(1u64..1_000_000_000, 1..50),
+            losing_total in 0u64..1_000_000_000_000,
+            pot in 0u64..1_000_000_000_000,
+            basis_points in 0u32..=10_000,
+        ) {
+            let rake = rake(losing_total, basis_points);
+            prop_assert!(rake <= losing_total);
+            let payouts = pari_mutuel_payouts(&winning_stakes, losing_total - rake, pot);
+            let winning_total: u64 = winning_stakes.iter().sum();
+            prop_assert_eq!(payouts.iter().sum::<u64>() + rake, winning_total + losing_total + pot);
+        }
+
+        #[test]
+        fn winners_get_at_least_their_stake_back(
+            winning_stakes in prop::collection::vec(1u64..1_000_000_000, 1..50),
+            losing_total in 0u64..1_000_000_000_000,
+            pot in 0u64..1_000_000_000_000,
+        ) {
+            let payouts = pari_mutuel_payouts(&winning_stakes, losing_total, pot);
+            for (payout, stake) in payouts.iter().zip(&winning_stakes) {
+                prop_assert!(payout >= stake);
+            }
+        }
+
+        #[test]
+        fn dead_heat_payouts_add_up_and_return_stakes(
+            winning_options in prop::collection::vec((1u32..10, prop::collection::vec(1u64..1_000_000_000, 1..10)), 1..5),
+            losing_total in 0u64..1_000_000_000_000,
+            pot in 0u64..1_000_000_000_000,
+        ) {
+            let payouts = dead_heat_payouts(&winning_options, losing_total, pot);
+            let winning_total: u64 = winning_options.iter().flat_map(|(_, stakes)| stakes).sum();
+            prop_assert_eq!(payouts.iter().flatten().sum::<u64>(), winning_total + losing_total + pot);
+            for (option_payouts, (_, stakes)) in payouts.iter().zip(&winning_options) {
+                for (payout, stake) in option_payouts.iter().zip(stakes) {
+                    prop_assert!(payout >= stake);
+                }
+            }
+        }
+
+        #[test]
+        fn parlay_payouts_are_exact_products(
+            stake in 1u64..1_000_000,
+            multipliers in prop::collection::vec((1u64..1000, 1u64..1000), 0..4),
+        ) {
+            let numerator = multipliers.iter().fold(stake as u128, |product, (numerator, _)| product * *numerator as u128);
+            let denominator = multipliers.iter().fold(1u128, |product, (_, denominator)| product * *denominator as u128);
+            prop_assert_eq!(parlay_payout(stake, &multipliers) as u128, numerator / denominator);
+        }
+
+        #[test]
+        fn winning_parlays_return_at_least_the_stake(
+            stake in 1u64..1_000_000_000_000,
+            multipliers in prop::collection::vec((1u64..u64::MAX, 1u64..u64::MAX), 0..10)
+                .prop_map(|legs| legs.into_iter().map(|(a, b)| (a.max(b), a.min(b))).collect::<Vec<_>>()),
+        ) {
+            prop_assert!(parlay_payout(stake, &multipliers) >= stake);
+        }
+
+        #[test]
+        fn multipliers_match_resolved_payouts(
+            winning_stakes in prop::collection::vec(1u64..1_000_000, 1..20),
+            losing_total in 0u64..1_000_000_000,
+            pot in 0u64..1_000_000_000,
+            basis_points in 0u32..=10_000,
+        ) {
+            let winning_total: u64 = winning_stakes.iter().sum();
+            let multiplier = pari_mutuel_multiplier(winning_total, losing_total, pot, basis_points).unwrap();
+            let paid_out: u64 = pari_mutuel_payouts(&winning_stakes, losing_total - rake(losing_total, basis_points), pot).iter().sum();
+            prop_assert_eq!(multiplier as u128, paid_out as u128 * 100 / winning_total as u128);
+        }
+
+        #[test]
+        fn shares_are_within_one_unit_of_exact(
+            pool in 0u64..1_000_000_000_000,
+            weights in prop::collection::vec(0u64..1_000_000_000, 1..50),
+        ) {
+            let total_weight: u128 = weights.iter().map(|&weight| weight as u128).sum();
+            prop_assume!(total_weight > 0);
+            let shares = split_proportionally(pool, &weights);
+            prop_assert_eq!(shares.iter().sum::<u64>(), pool);
+            for (share, weight) in shares.iter().zip(&weights) {
+                let exact_share = *weight as u128 * pool as u128;
+                let floor = (exact_share / total_weight) as u64;
+                prop_assert!(*share == floor || *share == floor + 1);
+            }
+        }
+    }
+}
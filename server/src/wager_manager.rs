@@ -1,20 +1,455 @@
-use tokio::sync::{mpsc, oneshot};
-use surrealdb::sql::Thing;
-use anyhow::anyhow;
-use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::sleep;
+use surrealdb::sql::{Id, Thing};
+use anyhow::{anyhow, bail};
+use common::network::{Notification, Rejection};
+use common::{ChallengeStatus, Electorate, LegStatus, NoWinnerPolicy, ParlayStatus, StakeLimits, WagerKind, WagerStatus};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::config::ServerConfig;
+use crate::database::{
+    account_id, rake_account, AccountPayment, BetPayout, DbBet, DbBracketMatch, DbChallenge, DbDispute, DbParlay, DbParlayLeg, DbResolution,
+    DbGsiLink, DbUser, DbVote, DbWager, DbWagerOption, DbWinner, LedgerEntryKind, ParlaySettlement, PotRollover, Settlement, ACCOUNT_HOUSE,
+    TABLE_BET, TABLE_USER, TABLE_WAGER, TABLE_WAGER_OPTION,
+};
 use crate::database_manager::{DatabaseRequest, Responder};
+use crate::{bracket, payout};
 
 pub enum WagerRequest {
+    // the requests that change a wager's status by hand carry who asked for it, or no one if the server
+    // itself did, like a linked game reporting its result
     ResolveWager {
         wager_id: Thing,
-        winning_option: Thing,
+        winners: Vec<DbWinner>,
+        user: Option<Thing>,
+        responder: Responder<common::Resolution>,
+    },
+    ResolveNumericWager {
+        wager_id: Thing,
+        outcome: i64,
+        user: Option<Thing>,
+        responder: Responder<common::Resolution>,
+    },
+    DisputeResolution {
+        wager_id: Thing,
+        user: Thing,
+        reason: String,
+        responder: Responder<()>,
+    },
+    ConfirmResolution {
+        wager_id: Thing,
+        user: Thing,
+        responder: Responder<()>,
+    },
+    OverturnResolution {
+        wager_id: Thing,
+        user: Thing,
+        responder: Responder<()>,
+    },
+    // responds with the resolution if the vote settled the wager
+    VoteOnOutcome {
+        wager_id: Thing,
+        user: Thing,
+        option: Thing,
+        responder: Responder<Option<common::Resolution>>,
+    },
+    LockWager {
+        wager_id: Thing,
+        user: Option<Thing>,
+        responder: Responder<()>,
+    },
+    ReopenWager {
+        wager_id: Thing,
+        closes_at: Option<u64>,
+        user: Option<Thing>,
+        responder: Responder<()>,
+    },
+    CancelWager {
+        wager_id: Thing,
+        user: Option<Thing>,
+        responder: Responder<()>,
+    },
+    SetOdds {
+        wager_option_id: Thing,
+        odds: u32,
+        user: Thing,
+        responder: Responder<()>,
+    },
+    SetStakeLimits {
+        wager_id: Thing,
+        limits: StakeLimits,
+        user: Thing,
+        responder: Responder<()>,
+    },
+    PlaceBet {
+        bet: DbBet,
+        responder: Responder<(u64, DbBet)>,
+    },
+    ChangeBet {
+        bet: Thing,
+        user: Thing,
+        val: u64,
+        responder: Responder<(u64, DbBet)>,
+    },
+    // responds with the user's new balance and what they were paid
+    CashOutBet {
+        bet: Thing,
+        user: Thing,
+        stake: u64,
+        responder: Responder<(u64, u64)>,
+    },
+    PlaceParlay {
+        user: Thing,
+        wager_options: Vec<Thing>,
+        val: u64,
+        responder: Responder<(u64, DbParlay)>,
+    },
+    SettleChallenge {
+        challenge: Thing,
+        user: Thing,
+        winner: Option<Thing>,
+        responder: Responder<DbChallenge>,
+    },
+    CreateBracket {
+        definition: common::BracketDefinition,
+        responder: Responder<common::Bracket>,
+    },
+    CreateWager {
+        wager: common::PotentialWager,
+        options: Vec<common::PotentialWagerOption>,
+        responder: Responder<DbWager>,
+    },
+    EditWager {
+        wager_id: Thing,
+        name: Option<String>,
+        description: Option<String>,
+        pot: Option<u64>,
+        responder: Responder<()>,
+    },
+    AddWagerOption {
+        wager_id: Thing,
+        option: common::PotentialWagerOption,
+        responder: Responder<Thing>,
+    },
+    EditWagerOption {
+        wager_option_id: Thing,
+        name: Option<String>,
+        description: Option<String>,
         responder: Responder<()>,
     },
+    RemoveWagerOption {
+        wager_option_id: Thing,
+        responder: Responder<()>,
+    },
+    LinkGsi {
+        link: DbGsiLink,
+        user: Thing,
+        responder: Responder<()>,
+    },
+}
+
+// A notification along with who should hear about it. Everyone connected does unless it names recipients
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressedNotification {
+    pub notification: Notification,
+    // user ids, in the same form as the ones in `common::Bet`
+    pub recipients: Option<HashSet<String>>,
+}
+
+impl AddressedNotification {
+    pub fn to_everyone(notification: Notification) -> Self {
+        Self {
+            notification,
+            recipients: None,
+        }
+    }
+
+    pub fn is_for(&self, user_id: &str) -> bool {
+        self.recipients.as_ref().is_none_or(|recipients| recipients.contains(user_id))
+    }
 }
 
 pub struct WagerManager {
     work_queue: mpsc::Receiver<WagerRequest>,
     database_requester: mpsc::Sender<DatabaseRequest>,
+    notifier: broadcast::Sender<AddressedNotification>,
+    config: ServerConfig,
+    // wagers waiting to be locked, keyed by the unix timestamp they close at
+    lock_schedule: BTreeMap<u64, Vec<Thing>>,
+    // wagers with a pending resolution, keyed by the unix timestamp their dispute window closes at
+    finalise_schedule: BTreeMap<u64, Vec<Thing>>,
+    // wagers being voted on, keyed by the unix timestamp their vote runs out at
+    vote_schedule: BTreeMap<u64, Vec<Thing>>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Whether a recorded resolution names the same winners with the same weights, in any order
+fn same_winners(recorded: &[common::Winner], winners: &[DbWinner]) -> bool {
+    let mut recorded: Vec<(String, u32)> = recorded
+        .iter()
+        .map(|winner| (winner.option_id.clone(), winner.weight()))
+        .collect();
+    let mut winners: Vec<(String, u32)> = winners
+        .iter()
+        .map(|winner| (winner.option.id.to_string(), winner.weight.unwrap_or(1)))
+        .collect();
+    recorded.sort();
+    winners.sort();
+    recorded == winners
+}
+
+fn bet_payouts(bets: Vec<&common::Bet>, amounts: impl IntoIterator<Item = u64>) -> Vec<BetPayout> {
+    bets.into_iter()
+        .zip(amounts)
+        .map(|(bet, amount)| BetPayout {
+            bet: Thing {
+                tb: TABLE_BET.into(),
+                id: Id::String(bet.id.clone()),
+            },
+            amount,
+        })
+        .collect()
+}
+
+// Checks that a potential wager and its options could be created as they are, apart from anything that
+// needs the database
+fn check_potential_wager(wager: &common::PotentialWager, options: &[common::PotentialWagerOption], now: u64) -> anyhow::Result<()> {
+    if wager.name.trim().is_empty() {
+        bail!("a wager needs a name");
+    }
+    if wager.closes_at.is_some_and(|closes_at| closes_at <= now) {
+        bail!("a wager cannot close in the past");
+    }
+    if wager.rake.is_some_and(|rake| rake.basis_points > 10_000) {
+        bail!("rake cannot be more than the whole losing pool");
+    }
+    if let (Some(min_stake), Some(max_stake)) = (wager.stake_limits.min_stake, wager.stake_limits.max_stake) {
+        if min_stake > max_stake {
+            bail!("minimum stake is above the maximum");
+        }
+    }
+    match &wager.electorate {
+        Electorate::Nobody => {}
+        Electorate::Participants { user_ids } if user_ids.is_empty() => {
+            bail!("a wager voted on by its participants needs participants");
+        }
+        _ if options.iter().any(|option| option.range.is_some()) => {
+            bail!("numeric wagers are resolved by an admin with the number they ended on");
+        }
+        _ => {}
+    }
+    check_wager_options(wager.kind, options)
+}
+
+// Checks that a wager of `kind` could have exactly these options
+fn check_wager_options(kind: WagerKind, options: &[common::PotentialWagerOption]) -> anyhow::Result<()> {
+    if options.len() < 2 {
+        bail!("a wager needs at least two options");
+    }
+    let mut names = HashSet::new();
+    for option in options {
+        if option.name.trim().is_empty() {
+            bail!("every option needs a name");
+        }
+        if !names.insert(option.name.trim()) {
+            bail!("option names must be unique");
+        }
+        match (kind, option.odds) {
+            (WagerKind::FixedOdds, Some(odds)) if odds > 100 => {}
+            (WagerKind::FixedOdds, _) => bail!("every option of a fixed-odds wager needs odds that pay out more than the stake"),
+            (WagerKind::PariMutuel, Some(_)) => bail!("only fixed-odds wagers have odds"),
+            (WagerKind::PariMutuel, None) => {}
+        }
+    }
+
+    // a numeric wager needs a range on every option, and no outcome may be in two of them
+    let mut ranges: Vec<common::OutcomeRange> = options.iter().filter_map(|option| option.range).collect();
+    if ranges.is_empty() {
+        return Ok(());
+    }
+    if ranges.len() != options.len() {
+        bail!("either every option or no option has a range");
+    }
+    if ranges.iter().any(|range| matches!((range.min, range.max), (Some(min), Some(max)) if min >= max)) {
+        bail!("an option's range is empty");
+    }
+    ranges.sort_by_key(|range| range.min.unwrap_or(i64::MIN));
+    if ranges.windows(2).any(|pair| pair[0].max.unwrap_or(i64::MAX) > pair[1].min.unwrap_or(i64::MIN)) {
+        bail!("option ranges overlap");
+    }
+    Ok(())
+}
+
+// What the votes cast on a wager so far say about how it turned out
+#[derive(Debug, PartialEq, Eq)]
+enum VoteOutcome {
+    Undecided,
+    Decided(Thing),
+    // everyone has voted without enough of them agreeing
+    Deadlocked,
+}
+
+// A vote settles a wager once at least `quorum` basis points of its electorate have voted, and at least
+// `supermajority` basis points of the votes cast are for the same option
+fn tally_votes(electorate: usize, votes: &[Thing], quorum: u32, supermajority: u32) -> VoteOutcome {
+    let cast = votes.len() as u64;
+    let leader = votes
+        .iter()
+        .map(|option| (option, votes.iter().filter(|vote| *vote == option).count() as u64))
+        .max_by_key(|(_, count)| *count);
+    if let Some((option, count)) = leader {
+        if cast * 10_000 >= electorate as u64 * quorum as u64 && count * 10_000 >= cast * supermajority as u64 {
+            return VoteOutcome::Decided(option.clone());
+        }
+    }
+    if cast >= electorate as u64 {
+        VoteOutcome::Deadlocked
+    } else {
+        VoteOutcome::Undecided
+    }
+}
+
+// The ids of the users who may vote on a wager
+fn electorate(wager: &common::Wager) -> HashSet<String> {
+    match &wager.electorate {
+        Electorate::Nobody => HashSet::new(),
+        Electorate::Bettors => wager
+            .options
+            .iter()
+            .flat_map(|option| option.bets.iter().map(|bet| bet.user_id.clone()))
+            .collect(),
+        Electorate::Participants { user_ids } => user_ids.iter().cloned().collect(),
+    }
+}
+
+// A vote runs out `window` seconds after the earliest vote still standing
+fn vote_deadline(votes: &[DbVote], window: u64) -> Option<u64> {
+    votes.iter().map(|vote| vote.cast_at).min().map(|first| first + window)
+}
+
+// Where the money of a pari-mutuel wager goes when nobody backed any of its winners
+fn no_winner_settlement(wager: &common::Wager, policy: &NoWinnerPolicy) -> Settlement {
+    let bets: Vec<&common::Bet> = wager.options.iter().flat_map(|option| option.bets.iter()).collect();
+    let staked: u64 = bets.iter().map(|bet| bet.val).sum();
+    match policy {
+        NoWinnerPolicy::Refund => {
+            let stakes: Vec<u64> = bets.iter().map(|bet| bet.val).collect();
+            Settlement {
+                payouts: bet_payouts(bets, stakes),
+                ..Settlement::default()
+            }
+        }
+        NoWinnerPolicy::Rollover { wager_id } => Settlement {
+            rollover: Some(PotRollover {
+                wager: Thing {
+                    tb: TABLE_WAGER.into(),
+                    id: Id::String(wager_id.clone()),
+                },
+                amount: staked + wager.pot,
+            }),
+            ..Settlement::default()
+        },
+        NoWinnerPolicy::House => Settlement {
+            account_payments: vec![AccountPayment {
+                account: account_id(ACCOUNT_HOUSE),
+                amount: (staked + wager.pot) as i64,
+                kind: LedgerEntryKind::HouseSettlement,
+            }],
+            ..Settlement::default()
+        },
+    }
+}
+
+// How a pending parlay leg turned out, now that its wager has been settled
+fn settle_leg(wager: &common::Wager, paid_by_option: &HashMap<String, u64>, leg: &DbParlayLeg) -> LegStatus {
+    let resolution = match (wager.status, &wager.resolution) {
+        // a leg waits for the resolution to become final
+        (WagerStatus::Resolved, Some(resolution)) if resolution.pending_until.is_none() => resolution,
+        (WagerStatus::Cancelled, _) => return LegStatus::Void,
+        _ => return LegStatus::Pending,
+    };
+    // a numeric wager that ended exactly on its line was a push
+    if resolution.winners.is_empty() {
+        return LegStatus::Void;
+    }
+    let option_id = leg.wager_option.id.to_string();
+    let Some(winner) = resolution.winners.iter().find(|winner| winner.option_id == option_id) else {
+        return LegStatus::Lost;
+    };
+    match (wager.kind, leg.odds) {
+        (WagerKind::FixedOdds, Some(odds)) => {
+            let total_weight: u32 = resolution.winners.iter().map(common::Winner::weight).sum();
+            LegStatus::Won {
+                numerator: odds as u64 * winner.weight() as u64,
+                denominator: 100 * total_weight as u64,
+            }
+        }
+        // a pari-mutuel leg pays what single bets on the same option were paid per unit staked. If
+        // nobody bet on it there is no price, so the leg is voided
+        _ => {
+            let staked: u64 = wager
+                .options
+                .iter()
+                .filter(|option| option.id == option_id)
+                .flat_map(|option| option.bets.iter().map(|bet| bet.val))
+                .sum();
+            match paid_by_option.get(&option_id) {
+                Some(&paid) if staked > 0 => LegStatus::Won {
+                    numerator: paid,
+                    denominator: staked,
+                },
+                _ => LegStatus::Void,
+            }
+        }
+    }
+}
+
+// Settles a parlay's legs on `wager_id`. Once a leg has lost the house keeps the stake; once every leg
+// has won or been voided the parlay pays out, and if every leg was voided the stake is refunded
+fn settle_parlay(
+    mut parlay: DbParlay,
+    wager_id: &Thing,
+    wager: &common::Wager,
+    paid_by_option: &HashMap<String, u64>,
+) -> ParlaySettlement {
+    for leg in parlay.legs.iter_mut() {
+        if leg.wager == *wager_id && leg.status == LegStatus::Pending {
+            leg.status = settle_leg(wager, paid_by_option, leg);
+        }
+    }
+
+    let statuses: Vec<LegStatus> = parlay.legs.iter().map(|leg| leg.status).collect();
+    if statuses.contains(&LegStatus::Lost) {
+        parlay.status = ParlayStatus::Lost;
+        let house = parlay.stake as i64;
+        return ParlaySettlement { parlay, payout: 0, house };
+    }
+    if statuses.contains(&LegStatus::Pending) {
+        return ParlaySettlement { parlay, payout: 0, house: 0 };
+    }
+
+    let multipliers: Vec<(u64, u64)> = statuses
+        .into_iter()
+        .filter_map(|status| match status {
+            LegStatus::Won { numerator, denominator } => Some((numerator, denominator)),
+            _ => None,
+        })
+        .collect();
+    let (status, payout) = if multipliers.is_empty() {
+        (ParlayStatus::Void, parlay.stake)
+    } else {
+        (ParlayStatus::Won, payout::parlay_payout(parlay.stake, &multipliers))
+    };
+    parlay.status = status;
+    parlay.payout = Some(payout);
+    let house = parlay.stake as i64 - payout as i64;
+    ParlaySettlement { parlay, payout, house }
 }
 
 //NOTE: No functions in this impl may crash
@@ -22,71 +457,2678 @@ impl WagerManager {
     pub fn new(
         work_queue: mpsc::Receiver<WagerRequest>,
         database_requester: mpsc::Sender<DatabaseRequest>,
+        notifier: broadcast::Sender<AddressedNotification>,
+        config: ServerConfig,
     ) -> Self {
         Self {
             work_queue,
             database_requester,
+            notifier,
+            config,
+            lock_schedule: BTreeMap::new(),
+            finalise_schedule: BTreeMap::new(),
+            vote_schedule: BTreeMap::new(),
         }
     }
 
     pub async fn manage(&mut self) {
-        while let Some(request) = self.work_queue.recv().await {
-            match request {
-                WagerRequest::ResolveWager {
-                    wager_id,
-                    winning_option,
-                    responder,
-                } => {
-                    // we do not care if the receiver has already disappeared
-                    responder
-                        .send(self.resolve_wager(wager_id, winning_option).await)
-                        .ok();
+        // the schedule only lives in memory, so rebuild it from the database in case we restarted.
+        // Anything that closed while we were down is locked straight away
+        if let Err(error) = self.load_lock_schedule().await {
+            eprintln!("failed to load wager lock schedule: {error:#}");
+        }
+        if let Err(error) = self.load_finalise_schedule().await {
+            eprintln!("failed to load pending resolutions: {error:#}");
+        }
+        if let Err(error) = self.load_vote_schedule().await {
+            eprintln!("failed to load undecided votes: {error:#}");
+        }
+        if let Err(error) = self.settle_outstanding_parlays().await {
+            eprintln!("failed to settle outstanding parlays: {error:#}");
+        }
+        if let Err(error) = self.advance_resolved_brackets().await {
+            eprintln!("failed to advance brackets: {error:#}");
+        }
+
+        loop {
+            let next_due = self
+                .lock_schedule
+                .keys()
+                .chain(self.finalise_schedule.keys())
+                .chain(self.vote_schedule.keys())
+                .min()
+                .copied();
+            tokio::select! {
+                request = self.work_queue.recv() => {
+                    match request {
+                        Some(request) => self.handle_request(request).await,
+                        None => break,
+                    }
+                }
+                _ = sleep(Duration::from_secs(next_due.unwrap_or(0).saturating_sub(unix_now()))), if next_due.is_some() => {
+                    self.lock_due_wagers().await;
+                    self.finalise_due_resolutions().await;
+                    self.deadlock_overdue_votes().await;
                 }
             }
         }
     }
 
-    async fn resolve_wager(&mut self, wager_id: Thing, winning_option_id: Thing) -> anyhow::Result<()> {
-        let (wager_tx, wager_rx) = oneshot::channel();
+    async fn handle_request(&mut self, request: WagerRequest) {
+        match request {
+            WagerRequest::ResolveWager {
+                wager_id,
+                winners,
+                user,
+                responder,
+            } => {
+                let result = match self.check_status_change(&wager_id, user.as_ref()).await {
+                    Ok(()) => self.resolve_wager(wager_id.clone(), winners, None).await,
+                    Err(error) => Err(error),
+                };
+                let resolved = result.is_ok();
+                // we do not care if the receiver has already disappeared
+                responder.send(result).ok();
+                if resolved {
+                    self.settle_parlays_or_log(&wager_id).await;
+                    self.advance_bracket_or_log(&wager_id).await;
+                }
+            }
+            WagerRequest::ResolveNumericWager {
+                wager_id,
+                outcome,
+                user,
+                responder,
+            } => {
+                let result = match self.check_status_change(&wager_id, user.as_ref()).await {
+                    Ok(()) => self.resolve_numeric_wager(wager_id.clone(), outcome).await,
+                    Err(error) => Err(error),
+                };
+                let resolved = result.is_ok();
+                responder.send(result).ok();
+                if resolved {
+                    self.settle_parlays_or_log(&wager_id).await;
+                }
+            }
+            WagerRequest::DisputeResolution {
+                wager_id,
+                user,
+                reason,
+                responder,
+            } => {
+                responder
+                    .send(self.dispute_resolution(wager_id, user, reason).await)
+                    .ok();
+            }
+            WagerRequest::ConfirmResolution {
+                wager_id,
+                user,
+                responder,
+            } => {
+                let result = self.confirm_resolution(wager_id.clone(), user).await;
+                let confirmed = result.is_ok();
+                responder.send(result).ok();
+                if confirmed {
+                    self.settle_parlays_or_log(&wager_id).await;
+                    self.advance_bracket_or_log(&wager_id).await;
+                }
+            }
+            WagerRequest::OverturnResolution {
+                wager_id,
+                user,
+                responder,
+            } => {
+                responder
+                    .send(self.overturn_resolution(wager_id, user).await)
+                    .ok();
+            }
+            WagerRequest::VoteOnOutcome {
+                wager_id,
+                user,
+                option,
+                responder,
+            } => {
+                let result = self.vote_on_outcome(wager_id.clone(), user, option).await;
+                let resolved = matches!(result, Ok(Some(_)));
+                responder.send(result).ok();
+                if resolved {
+                    self.settle_parlays_or_log(&wager_id).await;
+                    self.advance_bracket_or_log(&wager_id).await;
+                }
+            }
+            WagerRequest::LockWager {
+                wager_id,
+                user,
+                responder,
+            } => {
+                let result = match self.check_status_change(&wager_id, user.as_ref()).await {
+                    Ok(()) => self.lock_wager(wager_id).await,
+                    Err(error) => Err(error),
+                };
+                responder.send(result).ok();
+            }
+            WagerRequest::ReopenWager {
+                wager_id,
+                closes_at,
+                user,
+                responder,
+            } => {
+                let result = match self.check_status_change(&wager_id, user.as_ref()).await {
+                    Ok(()) => self.reopen_wager(wager_id, closes_at).await,
+                    Err(error) => Err(error),
+                };
+                responder.send(result).ok();
+            }
+            WagerRequest::CancelWager {
+                wager_id,
+                user,
+                responder,
+            } => {
+                let result = match self.check_status_change(&wager_id, user.as_ref()).await {
+                    Ok(()) => self.cancel_wager(wager_id.clone()).await,
+                    Err(error) => Err(error),
+                };
+                let cancelled = result.is_ok();
+                responder.send(result).ok();
+                if cancelled {
+                    self.settle_parlays_or_log(&wager_id).await;
+                }
+            }
+            WagerRequest::SetOdds {
+                wager_option_id,
+                odds,
+                user,
+                responder,
+            } => {
+                responder.send(self.set_odds(wager_option_id, odds, user).await).ok();
+            }
+            WagerRequest::SetStakeLimits {
+                wager_id,
+                limits,
+                user,
+                responder,
+            } => {
+                responder.send(self.set_stake_limits(wager_id, limits, user).await).ok();
+            }
+            WagerRequest::PlaceParlay {
+                user,
+                wager_options,
+                val,
+                responder,
+            } => {
+                responder
+                    .send(self.place_parlay(user, wager_options, val).await)
+                    .ok();
+            }
+            WagerRequest::SettleChallenge {
+                challenge,
+                user,
+                winner,
+                responder,
+            } => {
+                responder
+                    .send(self.settle_challenge(challenge, user, winner).await)
+                    .ok();
+            }
+            WagerRequest::CreateBracket {
+                definition,
+                responder,
+            } => {
+                responder.send(self.create_bracket(definition).await).ok();
+            }
+            WagerRequest::CreateWager {
+                wager,
+                options,
+                responder,
+            } => {
+                responder.send(self.create_wager(wager, options).await).ok();
+            }
+            WagerRequest::EditWager {
+                wager_id,
+                name,
+                description,
+                pot,
+                responder,
+            } => {
+                responder
+                    .send(self.edit_wager(wager_id, name, description, pot).await)
+                    .ok();
+            }
+            WagerRequest::AddWagerOption {
+                wager_id,
+                option,
+                responder,
+            } => {
+                responder.send(self.add_wager_option(wager_id, option).await).ok();
+            }
+            WagerRequest::EditWagerOption {
+                wager_option_id,
+                name,
+                description,
+                responder,
+            } => {
+                responder
+                    .send(self.edit_wager_option(wager_option_id, name, description).await)
+                    .ok();
+            }
+            WagerRequest::RemoveWagerOption {
+                wager_option_id,
+                responder,
+            } => {
+                responder.send(self.remove_wager_option(wager_option_id).await).ok();
+            }
+            WagerRequest::LinkGsi { link, user, responder } => {
+                responder.send(self.link_gsi(link, user).await).ok();
+            }
+            WagerRequest::PlaceBet { bet, responder } => {
+                responder.send(self.place_bet(bet).await).ok();
+            }
+            WagerRequest::ChangeBet {
+                bet,
+                user,
+                val,
+                responder,
+            } => {
+                responder.send(self.change_bet(bet, user, val).await).ok();
+            }
+            WagerRequest::CashOutBet {
+                bet,
+                user,
+                stake,
+                responder,
+            } => {
+                responder.send(self.cash_out_bet(bet, user, stake).await).ok();
+            }
+        }
+    }
+
+    // A failure here leaves the next match waiting, to be filled in the next time the server starts
+    async fn advance_bracket_or_log(&mut self, wager_id: &Thing) {
+        if let Err(error) = self.advance_bracket(wager_id).await {
+            eprintln!("failed to advance the bracket of wager {wager_id}: {error:#}");
+        }
+    }
+
+    // A failure here leaves the legs pending, to be picked up again the next time the server starts
+    async fn settle_parlays_or_log(&mut self, wager_id: &Thing) {
+        if let Err(error) = self.settle_parlays(wager_id).await {
+            eprintln!("failed to settle the parlays on wager {wager_id}: {error:#}");
+        }
+    }
+
+    async fn load_lock_schedule(&mut self) -> anyhow::Result<()> {
+        let (wagers_tx, wagers_rx) = oneshot::channel();
         self.database_requester
-            .send(DatabaseRequest::GetWagerInfo {
-                id: wager_id,
-                responder: wager_tx,
+            .send(DatabaseRequest::GetOpenWagersWithCloseTime {
+                responder: wagers_tx,
             })
             .await?;
+        for wager in wagers_rx.await?? {
+            if let Some(closes_at) = wager.closes_at {
+                self.schedule_lock(wager.id, closes_at);
+            }
+        }
+        Ok(())
+    }
 
-        let wager_info = wager_rx.await??.ok_or(anyhow!("invalid wager"))?;
-        let mut wager_total_map = HashMap::new();
-        for option in &wager_info.options {
-            let total: u64 = option.bets.iter().map(|bet| bet.val).sum();
-            wager_total_map.insert(option.id.clone(), total);
+    fn schedule_lock(&mut self, wager_id: Thing, closes_at: u64) {
+        self.lock_schedule.entry(closes_at).or_default().push(wager_id);
+    }
+
+    async fn lock_due_wagers(&mut self) {
+        let now = unix_now();
+        let still_pending = self.lock_schedule.split_off(&(now + 1));
+        let due = std::mem::replace(&mut self.lock_schedule, still_pending);
+        for (closes_at, wager_ids) in due {
+            for wager_id in wager_ids {
+                // the wager may have been locked, resolved or cancelled by hand in the meantime, or reopened
+                // to close at another time
+                match self.get_wager_info(&wager_id).await {
+                    Ok(wager) if wager.closes_at == Some(closes_at) => {}
+                    Ok(_) => continue,
+                    Err(error) => {
+                        eprintln!("failed to automatically lock wager {wager_id}: {error:#}");
+                        continue;
+                    }
+                }
+                if let Err(error) = self.lock_wager(wager_id.clone()).await {
+                    eprintln!("failed to automatically lock wager {wager_id}: {error:#}");
+                }
+            }
         }
+    }
 
-        let abs_total: u64 =
-            wager_total_map.iter().map(|(_, value)| value).sum::<u64>() + wager_info.pot;
-        let winning_ratio = abs_total as f64
-            / *wager_total_map
-                .get(&winning_option_id.id.to_string())
-                .ok_or(anyhow::Error::msg("invalid wager totals"))? as f64;
+    async fn load_finalise_schedule(&mut self) -> anyhow::Result<()> {
+        let (wagers_tx, wagers_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetPendingResolutions {
+                responder: wagers_tx,
+            })
+            .await?;
+        for wager in wagers_rx.await?? {
+            if let Some(pending_until) = wager.resolution.and_then(|resolution| resolution.pending_until) {
+                self.schedule_finalise(wager.id, pending_until);
+            }
+        }
+        Ok(())
+    }
 
-        let winning_bets = &wager_info
-            .options
-            .iter()
-            .find(|option| option.id.clone() == winning_option_id.id.to_string())
-            .ok_or(anyhow::Error::msg("no winning bets"))?
-            .bets;
+    fn schedule_finalise(&mut self, wager_id: Thing, pending_until: u64) {
+        self.finalise_schedule.entry(pending_until).or_default().push(wager_id);
+    }
 
-        for winning_bet in winning_bets {
-            let (payout_tx, payout_rx) = oneshot::channel();
-            self.database_requester
-                .send(DatabaseRequest::ProvidePayout {
-                    bet_info: winning_bet.clone(),
-                    winning_ratio,
-                    responder: payout_tx,
-                })
-                .await?;
-            payout_rx.await??;
+    async fn finalise_due_resolutions(&mut self) {
+        let now = unix_now();
+        let still_pending = self.finalise_schedule.split_off(&(now + 1));
+        let due = std::mem::replace(&mut self.finalise_schedule, still_pending);
+        for wager_id in due.into_values().flatten() {
+            match self.finalise_resolution(&wager_id, false).await {
+                Ok(true) => {
+                    self.settle_parlays_or_log(&wager_id).await;
+                    self.advance_bracket_or_log(&wager_id).await;
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    eprintln!("failed to finalise the resolution of wager {wager_id}: {error:#}");
+                }
+            }
+        }
+    }
+
+    // Pays out a pending resolution and makes it final. Unless an admin `confirmed` it, a resolution
+    // is left pending while it is disputed or its window is still open, which can happen when it was
+    // overturned and resolved again. Returns whether the resolution was finalised
+    async fn finalise_resolution(&mut self, wager_id: &Thing, confirmed: bool) -> anyhow::Result<bool> {
+        let resolution = self
+            .get_wager_info(wager_id)
+            .await?
+            .resolution
+            .ok_or(anyhow!("wager has not been resolved"))?;
+        let Some(pending_until) = resolution.pending_until else {
+            return Ok(false);
+        };
+        if !confirmed && (resolution.disputed || pending_until > unix_now()) {
+            return Ok(false);
+        }
+
+        let (finalise_tx, finalise_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::FinaliseResolution {
+                id: wager_id.clone(),
+                responder: finalise_tx,
+            })
+            .await?;
+        finalise_rx.await??;
+        Ok(true)
+    }
+
+    async fn get_user(&mut self, user: &Thing) -> anyhow::Result<DbUser> {
+        let (user_tx, user_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetUser {
+                name: user.id.to_string(),
+                responder: user_tx,
+            })
+            .await?;
+        user_rx.await??.ok_or(anyhow!("invalid user"))
+    }
+
+    async fn is_admin(&mut self, user: &Thing) -> anyhow::Result<bool> {
+        Ok(self.get_user(user).await?.admin)
+    }
+
+    // Only admins may lock, reopen, resolve or cancel a wager by hand, since doing so goes around its
+    // closing time and its vote. Escrow wagers are only ever settled through their challenge
+    async fn check_status_change(&mut self, wager_id: &Thing, user: Option<&Thing>) -> anyhow::Result<()> {
+        if let Some(user) = user {
+            if !self.is_admin(user).await? {
+                bail!("only an admin can lock, reopen, resolve or cancel a wager");
+            }
+        }
+        self.reject_escrow(wager_id).await
+    }
+
+    async fn dispute_resolution(&mut self, wager_id: Thing, user: Thing, reason: String) -> anyhow::Result<()> {
+        if reason.trim().is_empty() {
+            bail!("a dispute needs a reason");
+        }
+        let (dispute_tx, dispute_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::AddDispute {
+                dispute: DbDispute::new(wager_id, user, reason.trim(), unix_now()),
+                responder: dispute_tx,
+            })
+            .await?;
+        dispute_rx.await?
+    }
+
+    async fn confirm_resolution(&mut self, wager_id: Thing, user: Thing) -> anyhow::Result<()> {
+        if !self.is_admin(&user).await? {
+            bail!("only an admin can confirm a resolution");
+        }
+        if !self.finalise_resolution(&wager_id, true).await? {
+            bail!("wager has no pending resolution");
+        }
+        Ok(())
+    }
+
+    async fn overturn_resolution(&mut self, wager_id: Thing, user: Thing) -> anyhow::Result<()> {
+        if !self.is_admin(&user).await? {
+            bail!("only an admin can overturn a resolution");
+        }
+        self.reject_escrow(&wager_id).await?;
+        let (overturn_tx, overturn_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::OverturnResolution {
+                id: wager_id,
+                responder: overturn_tx,
+            })
+            .await?;
+        overturn_rx.await?
+    }
+
+    // Records a user's vote on which option won a locked wager, and resolves the wager once enough of its
+    // electorate agree. If everyone votes without agreeing, or the vote runs out first, admins and the
+    // electorate are told so an admin can resolve it. Returns the resolution if this vote settled the wager
+    async fn vote_on_outcome(&mut self, wager_id: Thing, user: Thing, option: Thing) -> anyhow::Result<Option<common::Resolution>> {
+        let wager_info = self.get_wager_info(&wager_id).await?;
+        if matches!(wager_info.electorate, Electorate::Nobody) {
+            bail!("this wager can only be resolved by an admin");
+        }
+        let electorate = electorate(&wager_info);
+        if !electorate.contains(&user.id.to_string()) {
+            bail!("only the bettors or participants named on a wager can vote on it");
+        }
+
+        let (vote_tx, vote_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::CastVote {
+                vote: DbVote::new(wager_id.clone(), user, option, unix_now()),
+                responder: vote_tx,
+            })
+            .await?;
+        let votes = vote_rx.await??;
+        let options: Vec<Thing> = votes.iter().map(|vote| vote.option.clone()).collect();
+
+        match tally_votes(electorate.len(), &options, self.config.vote_quorum, self.config.vote_supermajority) {
+            VoteOutcome::Decided(winner) => {
+                let resolution = self.resolve_wager(wager_id, vec![DbWinner { option: winner, weight: None }], None).await?;
+                Ok(Some(resolution))
+            }
+            _ if wager_info.vote_deadlocked => Ok(None),
+            VoteOutcome::Deadlocked => {
+                self.declare_vote_deadlock(&wager_id, electorate).await?;
+                Ok(None)
+            }
+            VoteOutcome::Undecided => {
+                if let Some(deadline) = vote_deadline(&votes, self.config.vote_window) {
+                    self.schedule_vote_deadline(wager_id, deadline);
+                }
+                Ok(None)
+            }
         }
+    }
+
+    // Leaves the wager for an admin to resolve, and tells the admins and everyone who could vote on it
+    async fn declare_vote_deadlock(&mut self, wager_id: &Thing, electorate: HashSet<String>) -> anyhow::Result<()> {
+        let (deadlock_tx, deadlock_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::SetVoteDeadlocked {
+                id: wager_id.clone(),
+                deadlocked: true,
+                responder: deadlock_tx,
+            })
+            .await?;
+        deadlock_rx.await??;
+
+        let (admins_tx, admins_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetAdmins {
+                responder: admins_tx,
+            })
+            .await?;
+        let mut recipients = electorate;
+        recipients.extend(admins_rx.await??.into_iter().map(|admin| admin.id.id.to_string()));
+        let _ = self.notifier.send(AddressedNotification {
+            notification: Notification::VoteDeadlocked {
+                wager_id: wager_id.id.to_string(),
+            },
+            recipients: Some(recipients),
+        });
         Ok(())
     }
+
+    async fn load_vote_schedule(&mut self) -> anyhow::Result<()> {
+        let (votes_tx, votes_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetUndecidedVotes {
+                responder: votes_tx,
+            })
+            .await?;
+        let mut votes_by_wager: HashMap<String, Vec<DbVote>> = HashMap::new();
+        for vote in votes_rx.await?? {
+            votes_by_wager.entry(vote.wager.to_string()).or_default().push(vote);
+        }
+        for votes in votes_by_wager.into_values() {
+            if let Some(deadline) = vote_deadline(&votes, self.config.vote_window) {
+                self.schedule_vote_deadline(votes[0].wager.clone(), deadline);
+            }
+        }
+        Ok(())
+    }
+
+    // Every undecided vote schedules its wager, so skip wagers that are already waiting on the same deadline
+    fn schedule_vote_deadline(&mut self, wager_id: Thing, deadline: u64) {
+        let wagers = self.vote_schedule.entry(deadline).or_default();
+        if !wagers.contains(&wager_id) {
+            wagers.push(wager_id);
+        }
+    }
+
+    async fn deadlock_overdue_votes(&mut self) {
+        let now = unix_now();
+        let still_pending = self.vote_schedule.split_off(&(now + 1));
+        let due = std::mem::replace(&mut self.vote_schedule, still_pending);
+        for wager_id in due.into_values().flatten() {
+            if let Err(error) = self.deadlock_if_overdue(&wager_id).await {
+                eprintln!("failed to close the overdue vote on wager {wager_id}: {error:#}");
+            }
+        }
+    }
+
+    async fn deadlock_if_overdue(&mut self, wager_id: &Thing) -> anyhow::Result<()> {
+        // the wager may have been settled in the meantime, or had its votes thrown away and cast again
+        // after a resolution was overturned
+        let wager_info = self.get_wager_info(wager_id).await?;
+        if wager_info.status != WagerStatus::Locked || wager_info.vote_deadlocked {
+            return Ok(());
+        }
+        let (votes_tx, votes_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetVotesForWager {
+                id: wager_id.clone(),
+                responder: votes_tx,
+            })
+            .await?;
+        match vote_deadline(&votes_rx.await??, self.config.vote_window) {
+            Some(deadline) if deadline <= unix_now() => self.declare_vote_deadlock(wager_id, electorate(&wager_info)).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn reopen_wager(&mut self, wager_id: Thing, closes_at: Option<u64>) -> anyhow::Result<()> {
+        let (reopen_tx, reopen_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::ReopenWager {
+                id: wager_id.clone(),
+                closes_at,
+                responder: reopen_tx,
+            })
+            .await?;
+        reopen_rx.await??;
+        if let Some(closes_at) = closes_at {
+            self.schedule_lock(wager_id, closes_at);
+        }
+        Ok(())
+    }
+
+    // The house pays out at whatever odds are set, so only admins may set them
+    async fn set_odds(&mut self, wager_option_id: Thing, odds: u32, user: Thing) -> anyhow::Result<()> {
+        if !self.is_admin(&user).await? {
+            bail!("only an admin can set the odds of an option");
+        }
+        let (odds_tx, odds_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::SetOptionOdds {
+                id: wager_option_id,
+                odds,
+                responder: odds_tx,
+            })
+            .await?;
+        odds_rx.await?
+    }
+
+    // Limits only mean something if players cannot lift their own, so only admins may change them
+    async fn set_stake_limits(&mut self, wager_id: Thing, limits: StakeLimits, user: Thing) -> anyhow::Result<()> {
+        if !self.is_admin(&user).await? {
+            bail!("only an admin can change the stake limits of a wager");
+        }
+        let (limits_tx, limits_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::SetStakeLimits {
+                id: wager_id,
+                limits,
+                responder: limits_tx,
+            })
+            .await?;
+        limits_rx.await?
+    }
+
+    async fn lock_wager(&mut self, wager_id: Thing) -> anyhow::Result<()> {
+        self.set_wager_status(wager_id.clone(), WagerStatus::Locked).await?;
+        // nobody being connected to hear about it is fine
+        let _ = self.notifier.send(AddressedNotification::to_everyone(Notification::WagerLocked {
+            wager_id: wager_id.id.to_string(),
+        }));
+        Ok(())
+    }
+
+    // A numeric wager wins the option whose range holds the reported number. If no option holds it the
+    // wager is a push and every bet is refunded
+    async fn resolve_numeric_wager(&mut self, wager_id: Thing, outcome: i64) -> anyhow::Result<common::Resolution> {
+        let wager_info = self.get_wager_info(&wager_id).await?;
+        let mut winners = Vec::new();
+        for option in &wager_info.options {
+            let range = option.range.ok_or(anyhow!("option {} has no range, so this is not a numeric wager", option.id))?;
+            if range.contains(outcome) {
+                winners.push(DbWinner {
+                    option: Thing {
+                        tb: TABLE_WAGER_OPTION.into(),
+                        id: Id::String(option.id.clone()),
+                    },
+                    weight: None,
+                });
+            }
+        }
+        if winners.len() > 1 {
+            bail!("the ranges of several options hold {}", common::format_outcome(outcome));
+        }
+        self.resolve_wager(wager_id, winners, Some(outcome)).await
+    }
+
+    // Resolving is idempotent: repeating a resolution returns what was recorded the first time without
+    // paying anyone again, while trying to change the outcome is rejected as a conflict
+    async fn resolve_wager(
+        &mut self,
+        wager_id: Thing,
+        winners: Vec<DbWinner>,
+        outcome: Option<i64>,
+    ) -> anyhow::Result<common::Resolution> {
+        let wager_info = self.get_wager_info(&wager_id).await?;
+        if let Some(resolution) = wager_info.resolution {
+            if resolution.outcome == outcome && same_winners(&resolution.winners, &winners) {
+                return Ok(resolution);
+            }
+            return Err(Rejection::ResolutionConflict {
+                wager_id: wager_info.id,
+                resolution,
+            }
+            .into());
+        }
+        if !wager_info.status.can_transition_to(WagerStatus::Resolved) {
+            bail!("wager cannot be resolved while {:?}", wager_info.status);
+        }
+
+        if outcome.is_none() {
+            if winners.is_empty() {
+                bail!("a resolution needs at least one winner");
+            }
+            if wager_info.options.iter().any(|option| option.range.is_some()) {
+                bail!("numeric wagers are resolved with the number they ended on");
+            }
+        }
+        let mut winning_options = Vec::with_capacity(winners.len());
+        for winner in &winners {
+            let option_id = winner.option.id.to_string();
+            if winners.iter().filter(|other| other.option == winner.option).count() > 1 {
+                bail!("option {} is named as a winner more than once", option_id);
+            }
+            let weight = winner.weight.unwrap_or(1);
+            if weight == 0 {
+                bail!("winning option {} has no weight", option_id);
+            }
+            let option = wager_info
+                .options
+                .iter()
+                .find(|option| option.id == option_id)
+                .ok_or(anyhow!("winning option {} is not part of this wager", option_id))?;
+            winning_options.push((weight, option));
+        }
+        let winning_bets: Vec<&common::Bet> = winning_options
+            .iter()
+            .flat_map(|(_, option)| option.bets.iter())
+            .collect();
+        let losing_total: u64 = wager_info
+            .options
+            .iter()
+            .filter(|option| !winning_options.iter().any(|(_, winner)| winner.id == option.id))
+            .flat_map(|option| option.bets.iter().map(|bet| bet.val))
+            .sum();
+
+        let winning_total: u64 = winning_bets.iter().map(|bet| bet.val).sum();
+        let mut no_winner_policy = None;
+        let settlement = match wager_info.kind {
+            _ if winning_options.is_empty() => {
+                no_winner_policy = Some(NoWinnerPolicy::Refund);
+                no_winner_settlement(&wager_info, &NoWinnerPolicy::Refund)
+            }
+            WagerKind::PariMutuel if winning_total == 0 => {
+                let policy = self.usable_no_winner_policy(&wager_info.no_winner_policy).await?;
+                let settlement = no_winner_settlement(&wager_info, &policy);
+                no_winner_policy = Some(policy);
+                settlement
+            }
+            WagerKind::PariMutuel => {
+                let rake = payout::rake(losing_total, wager_info.rake.basis_points);
+                let winning_stakes: Vec<(u32, Vec<u64>)> = winning_options
+                    .iter()
+                    .map(|(weight, option)| (*weight, option.bets.iter().map(|bet| bet.val).collect()))
+                    .collect();
+                let payouts =
+                    payout::dead_heat_payouts(&winning_stakes, losing_total - rake, wager_info.pot)
+                        .into_iter()
+                        .flatten();
+                Settlement {
+                    payouts: bet_payouts(winning_bets, payouts),
+                    account_payments: vec![AccountPayment {
+                        account: rake_account(wager_info.rake.destination),
+                        amount: rake as i64,
+                        kind: LedgerEntryKind::Rake,
+                    }],
+                    rollover: None,
+                }
+            }
+            // the house keeps every stake and pays the winners what they were promised, so it may
+            // well end up out of pocket. There is no pool to rake, and the pot is not paid out
+            WagerKind::FixedOdds => {
+                let total_weight = winning_options.iter().map(|(weight, _)| weight).sum();
+                let mut payouts = Vec::with_capacity(winning_bets.len());
+                for (weight, option) in &winning_options {
+                    for bet in &option.bets {
+                        let potential_payout = bet
+                            .potential_payout
+                            .ok_or(anyhow!("fixed-odds bet without a payout"))?;
+                        payouts.push(payout::dead_heat_fixed_odds_payout(potential_payout, *weight, total_weight));
+                    }
+                }
+                let house_takings = (winning_total + losing_total) as i64 - payouts.iter().sum::<u64>() as i64;
+                Settlement {
+                    payouts: bet_payouts(winning_bets, payouts),
+                    account_payments: vec![AccountPayment {
+                        account: account_id(ACCOUNT_HOUSE),
+                        amount: house_takings,
+                        kind: LedgerEntryKind::HouseSettlement,
+                    }],
+                    rollover: None,
+                }
+            }
+        };
+
+        let resolved_at = unix_now();
+        let resolution = DbResolution {
+            winners,
+            no_winner_policy,
+            outcome,
+            resolved_at,
+            pending_until: (self.config.dispute_window > 0).then(|| resolved_at + self.config.dispute_window),
+            disputed: false,
+        };
+
+        let (resolve_tx, resolve_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::ResolveWager {
+                id: wager_id.clone(),
+                resolution: resolution.clone(),
+                settlement,
+                responder: resolve_tx,
+            })
+            .await?;
+        resolve_rx.await??;
+        if let Some(pending_until) = resolution.pending_until {
+            self.schedule_finalise(wager_id, pending_until);
+        }
+        Ok(resolution.into())
+    }
+
+    // Fixed-odds bets lock in the payout of their option's current odds
+    async fn place_bet(&mut self, mut bet: DbBet) -> anyhow::Result<(u64, DbBet)> {
+        let option = self.get_wager_option(&bet.wager_option).await?;
+        self.reject_escrow(&option.wager).await?;
+        self.check_stake_limits(&option.wager, &bet.user, bet.val, None).await?;
+        bet.potential_payout = option
+            .odds
+            .map(|odds| payout::fixed_odds_payout(bet.val, odds));
+        bet.placed_probability = self.option_probability(&option, bet.val, 0).await?;
+
+        let (bet_tx, bet_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::PlaceBet {
+                bet,
+                max_liability: self.config.max_liability_per_option,
+                responder: bet_tx,
+            })
+            .await?;
+        bet_rx.await?
+    }
+
+    async fn change_bet(&mut self, bet_id: Thing, user: Thing, val: u64) -> anyhow::Result<(u64, DbBet)> {
+        let (bet_tx, bet_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetBet {
+                id: bet_id.clone(),
+                responder: bet_tx,
+            })
+            .await?;
+        let bet = bet_rx.await??.ok_or(anyhow!("invalid bet"))?;
+        let option = self.get_wager_option(&bet.wager_option).await?;
+        self.check_stake_limits(&option.wager, &user, val, Some(&bet_id)).await?;
+        let placed_probability = self.option_probability(&option, val, bet.val).await?;
+
+        let (change_tx, change_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::ChangeBetStake {
+                bet: bet_id,
+                user,
+                val,
+                placed_probability,
+                responder: change_tx,
+            })
+            .await?;
+        change_rx.await?
+    }
+
+    // Sells `stake` of a bet on an open wager back at the price `payout::cash_out_value` gives for the
+    // option's chance of winning right now. Bets are placed through this manager one at a time, so the
+    // pool cannot move between pricing and paying. Returns the user's new balance and what they were paid
+    async fn cash_out_bet(&mut self, bet_id: Thing, user: Thing, stake: u64) -> anyhow::Result<(u64, u64)> {
+        let (bet_tx, bet_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetBet {
+                id: bet_id.clone(),
+                responder: bet_tx,
+            })
+            .await?;
+        let bet = bet_rx.await??.ok_or(anyhow!("invalid bet"))?;
+        if stake == 0 || stake > bet.val {
+            bail!("can only cash out part of the stake that was placed");
+        }
+        let option = self.get_wager_option(&bet.wager_option).await?;
+        self.reject_escrow(&option.wager).await?;
+        let current_probability = self.option_probability(&option, 0, 0).await?;
+        let price = payout::cash_out_value(stake, bet.placed_probability, current_probability, self.config.cash_out_haircut);
+        let remaining_payout = bet
+            .potential_payout
+            .map(|potential_payout| (potential_payout as u128 * (bet.val - stake) as u128 / bet.val as u128) as u64);
+
+        let (cash_out_tx, cash_out_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::CashOutBet {
+                bet: bet_id,
+                user,
+                stake,
+                price,
+                remaining_payout,
+                responder: cash_out_tx,
+            })
+            .await?;
+        Ok((cash_out_rx.await??, price))
+    }
+
+    // An option's implied chance of winning in basis points, as if `extra_stake` more and `removed_stake`
+    // less were on it. Fixed odds give it directly, otherwise it is the option's share of everything
+    // staked on the wager
+    async fn option_probability(&mut self, option: &DbWagerOption, extra_stake: u64, removed_stake: u64) -> anyhow::Result<Option<u32>> {
+        if let Some(odds) = option.odds {
+            return Ok(payout::odds_implied_probability(odds));
+        }
+        let wager_info = self.get_wager_info(&option.wager).await?;
+        let option_id = option.id.id.to_string();
+        let option_stake = wager_info
+            .options
+            .iter()
+            .find(|wager_option| wager_option.id == option_id)
+            .map_or(0, |wager_option| wager_option.stats.total_stake);
+        let total_stake: u64 = wager_info.options.iter().map(|wager_option| wager_option.stats.total_stake).sum();
+        Ok(payout::implied_probability(
+            (option_stake + extra_stake).saturating_sub(removed_stake),
+            (total_stake + extra_stake).saturating_sub(removed_stake),
+        ))
+    }
+
+    // Checks a stake against the wager's limits, falling back to the server wide ones for any it does
+    // not set. Every bet placement and change goes through this manager one at a time, so the user's
+    // exposure cannot change between the check and the bet. The bet being replaced, if any, does not
+    // count towards the exposure
+    async fn check_stake_limits(
+        &mut self,
+        wager_id: &Thing,
+        user: &Thing,
+        stake: u64,
+        replacing: Option<&Thing>,
+    ) -> anyhow::Result<()> {
+        let wager_info = self.get_wager_info(wager_id).await?;
+
+        let user_id = user.id.to_string();
+        let replacing_id = replacing.map(|bet| bet.id.to_string());
+        let exposure: u64 = wager_info
+            .options
+            .iter()
+            .flat_map(|option| option.bets.iter())
+            .filter(|bet| bet.user_id == user_id && Some(&bet.id) != replacing_id.as_ref())
+            .map(|bet| bet.val)
+            .sum();
+        wager_info
+            .stake_limits
+            .or(self.config.stake_limits)
+            .check(stake, exposure)
+            .map_err(|limit| Rejection::StakeLimitHit { limit }.into())
+    }
+
+    async fn place_parlay(&mut self, user: Thing, wager_options: Vec<Thing>, val: u64) -> anyhow::Result<(u64, DbParlay)> {
+        // a parlay is not a stake on any one wager, so only the server wide limits on a single stake apply
+        StakeLimits {
+            max_exposure: None,
+            ..self.config.stake_limits
+        }
+        .check(val, 0)
+        .map_err(|limit| Rejection::StakeLimitHit { limit })?;
+
+        let mut legs = Vec::with_capacity(wager_options.len());
+        for wager_option in wager_options {
+            let option = self.get_wager_option(&wager_option).await?;
+            self.reject_escrow(&option.wager).await?;
+            legs.push(DbParlayLeg {
+                wager: option.wager,
+                wager_option,
+                odds: option.odds,
+                status: LegStatus::Pending,
+            });
+        }
+        // the parlay pays at least this much if it wins, so it counts against the liability of each of
+        // its fixed-odds options
+        let fixed_odds: Vec<(u64, u64)> = legs.iter().filter_map(|leg| leg.odds).map(|odds| (odds as u64, 100)).collect();
+        let mut parlay = DbParlay::new(user, val, legs);
+        parlay.potential_payout = payout::parlay_payout(val, &fixed_odds);
+
+        let (parlay_tx, parlay_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::PlaceParlay {
+                parlay,
+                max_liability: self.config.max_liability_per_option,
+                responder: parlay_tx,
+            })
+            .await?;
+        parlay_rx.await?
+    }
+
+    // Pays out an accepted challenge by resolving its wager in favour of the winner, or cancelling it to
+    // refund both sides when there is none. Only the agreed arbiter or an admin may do this
+    async fn settle_challenge(&mut self, challenge_id: Thing, user: Thing, winner: Option<Thing>) -> anyhow::Result<DbChallenge> {
+        let (challenge_tx, challenge_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetChallenge {
+                id: challenge_id.clone(),
+                responder: challenge_tx,
+            })
+            .await?;
+        let challenge = challenge_rx.await??.ok_or(anyhow!("invalid challenge"))?;
+        if challenge.status != ChallengeStatus::Accepted {
+            bail!("challenge is not waiting to be settled");
+        }
+
+        if challenge.arbiter.as_ref() != Some(&user) && !self.is_admin(&user).await? {
+            bail!("only the arbiter or an admin can settle a challenge");
+        }
+
+        let wager_id = challenge.wager.ok_or(anyhow!("accepted challenge without a wager"))?;
+        let status = match &winner {
+            Some(winner) => {
+                if *winner != challenge.challenger && *winner != challenge.opponent {
+                    bail!("the winner of a challenge must be one of its players");
+                }
+                let wager_info = self.get_wager_info(&wager_id).await?;
+                let winner_id = winner.id.to_string();
+                let option = wager_info
+                    .options
+                    .iter()
+                    .find(|option| option.bets.iter().any(|bet| bet.user_id == winner_id))
+                    .ok_or(anyhow!("challenge has no stake from {}", winner_id))?;
+                let winning_option = Thing {
+                    tb: TABLE_WAGER_OPTION.into(),
+                    id: Id::String(option.id.clone()),
+                };
+                self.resolve_wager(wager_id, vec![DbWinner { option: winning_option, weight: None }], None).await?;
+                ChallengeStatus::Settled
+            }
+            None => {
+                // a previous attempt may have got as far as cancelling the wager
+                if self.get_wager_info(&wager_id).await?.status != WagerStatus::Cancelled {
+                    self.cancel_wager(wager_id).await?;
+                }
+                ChallengeStatus::Void
+            }
+        };
+
+        let (finish_tx, finish_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::FinishChallenge {
+                id: challenge_id,
+                status,
+                winner,
+                responder: finish_tx,
+            })
+            .await?;
+        finish_rx.await?
+    }
+
+    async fn create_bracket(&mut self, definition: common::BracketDefinition) -> anyhow::Result<common::Bracket> {
+        let layout = bracket::layout(&definition, self.config.default_rake)?;
+        let (bracket_tx, bracket_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::AddBracket {
+                layout: layout.clone(),
+                responder: bracket_tx,
+            })
+            .await?;
+        bracket_rx.await??;
+        Ok(common::Bracket {
+            id: layout.bracket.id.id.to_string(),
+            name: layout.bracket.name,
+            format: layout.bracket.format,
+            matches: layout.matches.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    // The policy a wager nobody backed a winner of can actually be settled with. A rollover into a wager
+    // that has finished since is refunded instead
+    async fn usable_no_winner_policy(&mut self, policy: &NoWinnerPolicy) -> anyhow::Result<NoWinnerPolicy> {
+        if let NoWinnerPolicy::Rollover { wager_id } = policy {
+            let (target_tx, target_rx) = oneshot::channel();
+            self.database_requester
+                .send(DatabaseRequest::GetWagerInfo {
+                    id: Thing {
+                        tb: TABLE_WAGER.into(),
+                        id: Id::String(wager_id.clone()),
+                    },
+                    responder: target_tx,
+                })
+                .await?;
+            let target = target_rx.await??;
+            if !target.is_some_and(|target| matches!(target.status, WagerStatus::Open | WagerStatus::Locked)) {
+                return Ok(NoWinnerPolicy::Refund);
+            }
+        }
+        Ok(policy.clone())
+    }
+
+    // Creates a wager and all of its options at once, and schedules it to lock if it has a closing time
+    async fn create_wager(
+        &mut self,
+        potential: common::PotentialWager,
+        options: Vec<common::PotentialWagerOption>,
+    ) -> anyhow::Result<DbWager> {
+        check_potential_wager(&potential, &options, unix_now())?;
+        if let NoWinnerPolicy::Rollover { wager_id } = &potential.no_winner_policy {
+            let target = self
+                .get_wager_info(&Thing {
+                    tb: TABLE_WAGER.into(),
+                    id: Id::String(wager_id.clone()),
+                })
+                .await?;
+            if matches!(target.status, WagerStatus::Resolved | WagerStatus::Cancelled) {
+                bail!("cannot roll over into a wager that has already finished");
+            }
+        }
+
+        let mut wager = DbWager::new(potential.name.trim(), potential.description, potential.pot);
+        wager.closes_at = potential.closes_at;
+        wager.rake = potential.rake.unwrap_or(self.config.default_rake);
+        wager.kind = potential.kind;
+        wager.no_winner_policy = potential.no_winner_policy;
+        wager.stake_limits = potential.stake_limits;
+        wager.category = common::normalise_label(potential.category);
+        wager.game = common::normalise_label(potential.game);
+        wager.tags = common::normalise_tags(potential.tags);
+        wager.electorate = match potential.electorate {
+            Electorate::Participants { user_ids } => {
+                let mut participants: Vec<String> = Vec::with_capacity(user_ids.len());
+                for user_id in user_ids {
+                    let user_id = user_id.trim().to_string();
+                    if !participants.contains(&user_id) {
+                        self.get_user(&Thing {
+                            tb: TABLE_USER.into(),
+                            id: Id::String(user_id.clone()),
+                        })
+                        .await?;
+                        participants.push(user_id);
+                    }
+                }
+                Electorate::Participants { user_ids: participants }
+            }
+            electorate => electorate,
+        };
+        let options: Vec<DbWagerOption> = options
+            .into_iter()
+            .map(|option| {
+                let mut db_option = DbWagerOption::new(option.name.trim(), option.description, wager.id.clone());
+                db_option.odds = option.odds;
+                db_option.range = option.range;
+                db_option
+            })
+            .collect();
+        wager.options = options.iter().map(|option| option.id.clone()).collect();
+
+        let (create_tx, create_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::CreateWager {
+                wager: wager.clone(),
+                options,
+                responder: create_tx,
+            })
+            .await?;
+        create_rx.await??;
+        if let Some(closes_at) = wager.closes_at {
+            self.schedule_lock(wager.id.clone(), closes_at);
+        }
+        Ok(wager)
+    }
+
+    // The wager, as long as it is still open. Bracket matches get their options from the bracket, so
+    // only their names and descriptions may be changed by hand
+    async fn get_editable_wager(&mut self, wager_id: &Thing, changes_options: bool) -> anyhow::Result<common::Wager> {
+        let wager = self.get_wager_info(wager_id).await?;
+        if wager.status != WagerStatus::Open {
+            bail!("only open wagers can be edited");
+        }
+        if changes_options {
+            let (match_tx, match_rx) = oneshot::channel();
+            self.database_requester
+                .send(DatabaseRequest::GetBracketMatchForWager {
+                    wager: wager_id.clone(),
+                    responder: match_tx,
+                })
+                .await?;
+            if match_rx.await??.is_some() {
+                bail!("the options of a bracket match cannot be changed");
+            }
+        }
+        Ok(wager)
+    }
+
+    // Escrow wagers hold the stakes of an accepted challenge, so they only change when the challenge is
+    // settled by its arbiter or an admin
+    async fn reject_escrow(&mut self, wager_id: &Thing) -> anyhow::Result<()> {
+        let (challenge_tx, challenge_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetChallengeForWager {
+                wager: wager_id.clone(),
+                responder: challenge_tx,
+            })
+            .await?;
+        if challenge_rx.await??.is_some() {
+            bail!("this wager holds the stakes of a challenge and can only be settled through it");
+        }
+        Ok(())
+    }
+
+    async fn edit_wager(
+        &mut self,
+        wager_id: Thing,
+        name: Option<String>,
+        description: Option<String>,
+        pot: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let name = name.map(|name| name.trim().to_string());
+        if name.as_ref().is_some_and(|name| name.is_empty()) {
+            bail!("a wager needs a name");
+        }
+        self.get_editable_wager(&wager_id, false).await?;
+        let (edit_tx, edit_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::EditWager {
+                id: wager_id,
+                name,
+                description,
+                pot,
+                responder: edit_tx,
+            })
+            .await?;
+        edit_rx.await?
+    }
+
+    async fn add_wager_option(&mut self, wager_id: Thing, option: common::PotentialWagerOption) -> anyhow::Result<Thing> {
+        let wager = self.get_editable_wager(&wager_id, true).await?;
+        let mut options: Vec<common::PotentialWagerOption> = wager.options.iter().map(Into::into).collect();
+        options.push(option.clone());
+        check_wager_options(wager.kind, &options)?;
+
+        let mut db_option = DbWagerOption::new(option.name.trim(), option.description, wager_id);
+        db_option.odds = option.odds;
+        db_option.range = option.range;
+        let option_id = db_option.id.clone();
+        let (add_tx, add_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::AddWagerOption {
+                option: db_option,
+                responder: add_tx,
+            })
+            .await?;
+        add_rx.await??;
+        Ok(option_id)
+    }
+
+    async fn edit_wager_option(
+        &mut self,
+        wager_option_id: Thing,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> anyhow::Result<()> {
+        let name = name.map(|name| name.trim().to_string());
+        let option = self.get_wager_option(&wager_option_id).await?;
+        let wager = self.get_editable_wager(&option.wager, false).await?;
+        if let Some(name) = &name {
+            let mut options: Vec<common::PotentialWagerOption> = Vec::new();
+            for existing in &wager.options {
+                let mut potential = common::PotentialWagerOption::from(existing);
+                if existing.id == wager_option_id.id.to_string() {
+                    potential.name = name.clone();
+                }
+                options.push(potential);
+            }
+            check_wager_options(wager.kind, &options)?;
+        }
+
+        let (edit_tx, edit_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::EditWagerOption {
+                id: wager_option_id,
+                name,
+                description,
+                responder: edit_tx,
+            })
+            .await?;
+        edit_rx.await?
+    }
+
+    // Whoever holds a link's token can resolve its wager, which otherwise takes an admin or the wager's
+    // vote, so only admins may hand one out. Escrow wagers are settled through their challenge instead
+    async fn link_gsi(&mut self, link: DbGsiLink, user: Thing) -> anyhow::Result<()> {
+        if !self.is_admin(&user).await? {
+            bail!("only an admin can link a wager to a game");
+        }
+        self.reject_escrow(&link.wager).await?;
+        let (link_tx, link_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::AddGsiLink {
+                link,
+                responder: link_tx,
+            })
+            .await?;
+        link_rx.await?
+    }
+
+    // Removes an option from an open wager, refunding everything staked on it. Options that are a leg of
+    // an unsettled parlay are kept, since the parlay could no longer be settled
+    async fn remove_wager_option(&mut self, wager_option_id: Thing) -> anyhow::Result<()> {
+        let option = self.get_wager_option(&wager_option_id).await?;
+        let wager = self.get_editable_wager(&option.wager, true).await?;
+        let options: Vec<common::PotentialWagerOption> = wager
+            .options
+            .iter()
+            .filter(|existing| existing.id != wager_option_id.id.to_string())
+            .map(Into::into)
+            .collect();
+        check_wager_options(wager.kind, &options)?;
+        let parlays = self.get_active_parlays().await?;
+        if parlays.iter().flat_map(|parlay| &parlay.legs).any(|leg| leg.wager_option == wager_option_id) {
+            bail!("the option is a leg of an unsettled parlay");
+        }
+
+        let (remove_tx, remove_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::RemoveWagerOption {
+                id: wager_option_id,
+                responder: remove_tx,
+            })
+            .await?;
+        remove_rx.await?
+    }
+
+    // Fills the winner, and in double elimination the loser, of a resolved bracket match into the
+    // matches they go on to. Does nothing for wagers that are not part of a bracket
+    async fn advance_bracket(&mut self, wager_id: &Thing) -> anyhow::Result<()> {
+        let (match_tx, match_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetBracketMatchForWager {
+                wager: wager_id.clone(),
+                responder: match_tx,
+            })
+            .await?;
+        match match_rx.await?? {
+            Some(bracket_match) => self.advance_bracket_match(bracket_match).await,
+            None => Ok(()),
+        }
+    }
+
+    // Catches up on bracket matches that were resolved while we were down
+    async fn advance_resolved_brackets(&mut self) -> anyhow::Result<()> {
+        let (matches_tx, matches_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetResolvedBracketMatches {
+                responder: matches_tx,
+            })
+            .await?;
+        for bracket_match in matches_rx.await?? {
+            self.advance_bracket_match(bracket_match).await?;
+        }
+        Ok(())
+    }
+
+    async fn advance_bracket_match(&mut self, bracket_match: DbBracketMatch) -> anyhow::Result<()> {
+        let resolution = self
+            .get_wager_info(&bracket_match.wager)
+            .await?
+            .resolution
+            .ok_or(anyhow!("{} has not been resolved", bracket_match.name))?;
+        // the bracket moves on once the result is final
+        if resolution.pending_until.is_some() {
+            return Ok(());
+        }
+        let [winner] = resolution.winners.as_slice() else {
+            bail!("{} needs a single winner to advance the bracket", bracket_match.name);
+        };
+        let winning_side = bracket_match
+            .options
+            .iter()
+            .position(|option| option.id.to_string() == winner.option_id)
+            .ok_or(anyhow!("{} was won by an option it does not have", bracket_match.name))?;
+
+        let advancing = [
+            (bracket_match.winner_to, winning_side),
+            (bracket_match.loser_to, 1 - winning_side),
+        ];
+        for (destination, side) in advancing {
+            let (Some(slot), Some(Some(team))) = (destination, bracket_match.teams.get(side)) else {
+                continue;
+            };
+            let (fill_tx, fill_rx) = oneshot::channel();
+            self.database_requester
+                .send(DatabaseRequest::FillBracketSlot {
+                    slot,
+                    team: team.clone(),
+                    responder: fill_tx,
+                })
+                .await?;
+            fill_rx.await??;
+        }
+        Ok(())
+    }
+
+    async fn get_active_parlays(&mut self) -> anyhow::Result<Vec<DbParlay>> {
+        let (parlays_tx, parlays_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetActiveParlays {
+                responder: parlays_tx,
+            })
+            .await?;
+        parlays_rx.await?
+    }
+
+    // Catches up on parlay legs whose wagers were settled while we were down
+    async fn settle_outstanding_parlays(&mut self) -> anyhow::Result<()> {
+        let mut wager_ids: Vec<Thing> = Vec::new();
+        for parlay in self.get_active_parlays().await? {
+            for leg in parlay.legs {
+                if leg.status == LegStatus::Pending && !wager_ids.contains(&leg.wager) {
+                    wager_ids.push(leg.wager);
+                }
+            }
+        }
+        for wager_id in wager_ids {
+            self.settle_parlays(&wager_id).await?;
+        }
+        Ok(())
+    }
+
+    // Settles every pending parlay leg on a wager that has been resolved or cancelled, paying out the
+    // parlays that are finished. Legs only ever leave Pending, so running this again does nothing
+    async fn settle_parlays(&mut self, wager_id: &Thing) -> anyhow::Result<()> {
+        let parlays: Vec<DbParlay> = self
+            .get_active_parlays()
+            .await?
+            .into_iter()
+            .filter(|parlay| {
+                parlay
+                    .legs
+                    .iter()
+                    .any(|leg| leg.wager == *wager_id && leg.status == LegStatus::Pending)
+            })
+            .collect();
+        if parlays.is_empty() {
+            return Ok(());
+        }
+
+        let wager_info = self.get_wager_info(wager_id).await?;
+        let mut paid_by_option: HashMap<String, u64> = HashMap::new();
+        match wager_info.status {
+            WagerStatus::Resolved => {
+                let (payouts_tx, payouts_rx) = oneshot::channel();
+                self.database_requester
+                    .send(DatabaseRequest::GetPayoutsForWager {
+                        id: wager_id.clone(),
+                        responder: payouts_tx,
+                    })
+                    .await?;
+                for payout in payouts_rx.await?? {
+                    *paid_by_option.entry(payout.option.id.to_string()).or_default() += payout.amount as u64;
+                }
+            }
+            WagerStatus::Cancelled => {}
+            WagerStatus::Open | WagerStatus::Locked | WagerStatus::Waiting => return Ok(()),
+        }
+
+        let settlements = parlays
+            .into_iter()
+            .map(|parlay| settle_parlay(parlay, wager_id, &wager_info, &paid_by_option))
+            .collect();
+        let (settle_tx, settle_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::SettleParlays {
+                wager: wager_id.clone(),
+                settlements,
+                settled_at: unix_now(),
+                responder: settle_tx,
+            })
+            .await?;
+        settle_rx.await?
+    }
+
+    async fn get_wager_info(&mut self, wager_id: &Thing) -> anyhow::Result<common::Wager> {
+        let (wager_tx, wager_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetWagerInfo {
+                id: wager_id.clone(),
+                responder: wager_tx,
+            })
+            .await?;
+        wager_rx.await??.ok_or(anyhow!("invalid wager"))
+    }
+
+    async fn get_wager_option(&mut self, option_id: &Thing) -> anyhow::Result<DbWagerOption> {
+        let (option_tx, option_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::GetWagerOption {
+                id: option_id.clone(),
+                responder: option_tx,
+            })
+            .await?;
+        option_rx.await??.ok_or(anyhow!("invalid wager option"))
+    }
+
+    async fn cancel_wager(&mut self, wager_id: Thing) -> anyhow::Result<()> {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::CancelWager {
+                id: wager_id,
+                responder: cancel_tx,
+            })
+            .await?;
+        cancel_rx.await?
+    }
+
+    async fn set_wager_status(&mut self, wager_id: Thing, status: WagerStatus) -> anyhow::Result<()> {
+        let (status_tx, status_rx) = oneshot::channel();
+        self.database_requester
+            .send(DatabaseRequest::SetWagerStatus {
+                id: wager_id,
+                status,
+                responder: status_tx,
+            })
+            .await?;
+        status_rx.await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::*;
+    use crate::database_manager::DatabaseManager;
+    use surrealdb::engine::local::Db;
+    use tokio::time::timeout;
+    use common::{Rake, RakeDestination, StakeLimit, StakeLimits};
+
+    struct ManagerSetup {
+        pub wager_requester: mpsc::Sender<WagerRequest>,
+        pub database_requester: mpsc::Sender<DatabaseRequest>,
+        pub notifications: broadcast::Receiver<AddressedNotification>,
+    }
+
+    fn start_managers(database_connection: DatabaseConnection<Db>) -> ManagerSetup {
+        start_managers_with_config(database_connection, ServerConfig::default())
+    }
+
+    fn start_managers_with_config(database_connection: DatabaseConnection<Db>, config: ServerConfig) -> ManagerSetup {
+        let (db_tx, db_rx) = mpsc::channel(32);
+        let mut db_manager = DatabaseManager::new(database_connection, db_rx);
+        tokio::spawn(async move {
+            db_manager.manage().await;
+        });
+
+        let (notification_tx, notifications) = broadcast::channel(32);
+        let (wager_tx, wager_rx) = mpsc::channel(32);
+        let mut wager_manager = WagerManager::new(wager_rx, db_tx.clone(), notification_tx, config);
+        tokio::spawn(async move {
+            wager_manager.manage().await;
+        });
+
+        ManagerSetup {
+            wager_requester: wager_tx,
+            database_requester: db_tx,
+            notifications,
+        }
+    }
+
+    // A manager that is called directly rather than through its queue, so its schedules can be looked at
+    fn start_direct_manager(database_connection: DatabaseConnection<Db>, config: ServerConfig) -> WagerManager {
+        let (db_tx, db_rx) = mpsc::channel(32);
+        let mut db_manager = DatabaseManager::new(database_connection, db_rx);
+        tokio::spawn(async move {
+            db_manager.manage().await;
+        });
+        let (_, work_queue) = mpsc::channel(1);
+        let (notifier, _) = broadcast::channel(1);
+        WagerManager::new(work_queue, db_tx, notifier, config)
+    }
+
+    fn sole_winner(option: &Thing) -> Vec<DbWinner> {
+        vec![DbWinner { option: option.clone(), weight: None }]
+    }
+
+    async fn fetch_wager_info(setup: &ManagerSetup, wager_id: &Thing) -> common::Wager {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup
+            .database_requester
+            .send(DatabaseRequest::GetWagerInfo {
+                id: wager_id.clone(),
+                responder: resp_tx,
+            })
+            .await
+            .unwrap();
+        resp_rx.await.unwrap().unwrap().expect("wager should exist")
+    }
+
+    async fn fetch_balance(setup: &ManagerSetup, name: &str) -> u64 {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup
+            .database_requester
+            .send(DatabaseRequest::GetUser {
+                name: name.into(),
+                responder: resp_tx,
+            })
+            .await
+            .unwrap();
+        resp_rx.await.unwrap().unwrap().expect("user should exist").balance
+    }
+
+    async fn add_wager_closing_at(database_connection: &mut DatabaseConnection<Db>, closes_at: u64) -> Thing {
+        let mut wager = DbWager::new("scheduled", "scheduled", 0);
+        wager.closes_at = Some(closes_at);
+        database_connection.add_wager(&wager).await.unwrap().unwrap().id
+    }
+
+    #[tokio::test]
+    async fn test_locks_wagers_that_closed_while_down() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let wager = add_wager_closing_at(&mut database_connection, unix_now() - 60).await;
+
+        let mut setup = start_managers(database_connection);
+
+        let notification = timeout(Duration::from_secs(5), setup.notifications.recv()).await.expect("wager should lock").unwrap();
+        assert_eq!(notification, AddressedNotification::to_everyone(Notification::WagerLocked { wager_id: wager.id.to_string() }));
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Locked);
+    }
+
+    #[tokio::test]
+    async fn test_locks_wager_at_close_time() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let wager = add_wager_closing_at(&mut database_connection, unix_now() + 1).await;
+
+        let mut setup = start_managers(database_connection);
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Open);
+
+        let notification = timeout(Duration::from_secs(5), setup.notifications.recv()).await.expect("wager should lock").unwrap();
+        assert_eq!(notification, AddressedNotification::to_everyone(Notification::WagerLocked { wager_id: wager.id.to_string() }));
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Locked);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_wager_reschedules_lock() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let wager = add_wager_closing_at(&mut database_connection, unix_now() - 60).await;
+        let mut manager = start_direct_manager(database_connection, ServerConfig::default());
+        manager.load_lock_schedule().await.unwrap();
+        manager.lock_due_wagers().await;
+        assert_eq!(manager.get_wager_info(&wager).await.unwrap().status, WagerStatus::Locked);
+
+        manager.reopen_wager(wager.clone(), Some(unix_now() - 1)).await.expect_err("a wager cannot close in the past");
+        let closes_at = unix_now() + 3600;
+        manager.reopen_wager(wager.clone(), Some(closes_at)).await.expect("locked wagers can be reopened");
+        let wager_info = manager.get_wager_info(&wager).await.unwrap();
+        assert_eq!((wager_info.status, wager_info.closes_at), (WagerStatus::Open, Some(closes_at)));
+        assert_eq!(manager.lock_schedule.get(&closes_at), Some(&vec![wager.clone()]));
+        manager.reopen_wager(wager.clone(), None).await.expect_err("open wagers cannot be reopened");
+
+        // the old closing time is still in the schedule, but no longer locks the wager
+        manager.schedule_lock(wager.clone(), unix_now() - 60);
+        manager.lock_due_wagers().await;
+        assert_eq!(manager.get_wager_info(&wager).await.unwrap().status, WagerStatus::Open);
+    }
+
+    #[tokio::test]
+    async fn test_lock_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let wager = database_connection.add_wager(&DbWager::new("manual", "manual", 0)).await.unwrap().unwrap().id;
+        let player = database_connection.add_user(&DbUser::new("player", 1000)).await.unwrap().unwrap().id;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+
+        let mut setup = start_managers(database_connection);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LockWager { wager_id: wager.clone(), user: Some(player), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("only an admin can lock a wager by hand");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LockWager { wager_id: wager.clone(), user: Some(admin), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("open wagers can be locked");
+
+        let notification = setup.notifications.recv().await.unwrap();
+        assert_eq!(notification, AddressedNotification::to_everyone(Notification::WagerLocked { wager_id: wager.id.to_string() }));
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Locked);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LockWager { wager_id: wager.clone(), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("locked wagers cannot be locked again");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner1 = database_connection.add_user(&DbUser::new("winner1", 1000)).await.unwrap().unwrap().id;
+        let winner2 = database_connection.add_user(&DbUser::new("winner2", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let wager = database_connection.add_wager(&DbWager::new("wager", "wager", 200)).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(winner1.clone(), winning_option.clone(), 200), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(winner2.clone(), winning_option.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(loser.clone(), losing_option.clone(), 300), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        let resolution = resp_rx.await.unwrap().expect("should be able to resolve wager");
+        assert_eq!(resolution.winners, vec![common::Winner { option_id: winning_option.id.to_string(), weight: None }]);
+
+        // 800 in total is split 2:1, and the leftover unit goes to the larger remainder
+        assert_eq!(fetch_balance(&setup, "winner1").await, 800 + 533);
+        assert_eq!(fetch_balance(&setup, "winner2").await, 900 + 267);
+        assert_eq!(fetch_balance(&setup, "loser").await, 700);
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Resolved);
+        assert_eq!(fetch_wager_info(&setup, &wager).await.resolution, Some(resolution.clone()));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager_takes_rake_from_losing_pool() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner = database_connection.add_user(&DbUser::new("winner", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let mut wager = DbWager::new("wager", "wager", 200);
+        wager.rake = Rake { basis_points: 500, destination: RakeDestination::Jackpot };
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(winner, winning_option.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(loser, losing_option, 300), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        assert_eq!(fetch_wager_info(&setup, &wager).await.rake, Rake { basis_points: 500, destination: RakeDestination::Jackpot });
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("should be able to resolve wager");
+
+        // 5% of the 300 losing pool is 15, the pot is never raked
+        assert_eq!(fetch_balance(&setup, "winner").await, 900 + 100 + 285 + 200);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager_is_idempotent() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner = database_connection.add_user(&DbUser::new("winner", 1000)).await.unwrap().unwrap().id;
+        let wager = database_connection.add_wager(&DbWager::new("wager", "wager", 200)).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(winner, winning_option.clone(), 200), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        let resolution = resp_rx.await.unwrap().expect("should be able to resolve wager");
+        assert_eq!(fetch_balance(&setup, "winner").await, 1200);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        let repeated_resolution = resp_rx.await.unwrap().expect("repeating a resolution should succeed");
+        assert_eq!(repeated_resolution, resolution);
+        assert_eq!(fetch_balance(&setup, "winner").await, 1200);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&losing_option), user: None, responder: resp_tx }).await.unwrap();
+        let error = resp_rx.await.unwrap().expect_err("should not be able to change the outcome");
+        assert_eq!(
+            error.downcast_ref::<Rejection>(),
+            Some(&Rejection::ResolutionConflict { wager_id: wager.id.to_string(), resolution })
+        );
+        assert_eq!(fetch_balance(&setup, "winner").await, 1200);
+    }
+
+    async fn place_bet(setup: &ManagerSetup, user: &Thing, wager_option: &Thing, val: u64) -> anyhow::Result<(u64, DbBet)> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::PlaceBet { bet: DbBet::new(user.clone(), wager_option.clone(), val), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_set_odds() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let player = database_connection.add_user(&DbUser::new("player", 1000)).await.unwrap().unwrap().id;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+        let mut wager = DbWager::new("wager", "wager", 0);
+        wager.kind = WagerKind::FixedOdds;
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let option = database_connection.add_wager_option_db(&DbWagerOption::new("option", "option", wager)).await.unwrap().unwrap().id;
+        let setup = start_managers(database_connection);
+        let set_odds = |user: &Thing, odds: u32| {
+            let (wager_requester, wager_option_id, user) = (setup.wager_requester.clone(), option.clone(), user.clone());
+            async move {
+                let (resp_tx, resp_rx) = oneshot::channel();
+                wager_requester.send(WagerRequest::SetOdds { wager_option_id, odds, user, responder: resp_tx }).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+
+        set_odds(&player, 10_000).await.expect_err("players should not be able to pick the odds they bet at");
+        set_odds(&admin, 250).await.expect("an admin should be able to set the odds");
+        let (_, bet) = place_bet(&setup, &player, &option, 100).await.unwrap();
+        assert_eq!(bet.potential_payout, Some(250));
+    }
+
+    #[tokio::test]
+    async fn test_fixed_odds_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner = database_connection.add_user(&DbUser::new("winner", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let parlayer = database_connection.add_user(&DbUser::new("parlayer", 1000)).await.unwrap().unwrap().id;
+        let pool = database_connection.add_wager(&DbWager::new("pool", "pool", 0)).await.unwrap().unwrap().id;
+        let pool_option = database_connection.add_wager_option_db(&DbWagerOption::new("option", "option", pool)).await.unwrap().unwrap().id;
+        let mut wager = DbWager::new("wager", "wager", 200);
+        wager.kind = WagerKind::FixedOdds;
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.set_option_odds(&winning_option, 300).await.unwrap();
+        database_connection.set_option_odds(&losing_option, 150).await.unwrap();
+
+        let config = ServerConfig { max_liability_per_option: Some(500), ..ServerConfig::default() };
+        let setup = start_managers_with_config(database_connection, config);
+        let (balance, bet) = place_bet(&setup, &winner, &winning_option, 200).await.expect("should be able to place bet");
+        assert_eq!((balance, bet.potential_payout), (800, Some(600)));
+        place_bet(&setup, &winner, &winning_option, 200).await.expect_err("should not be able to exceed the maximum liability");
+        place_bet(&setup, &loser, &losing_option, 100).await.expect("should be able to place bet");
+        // a parlay through the winning option owes at least its odds less its stake, on top of the 600 - 300
+        // it already owes
+        place_parlay(&setup, &parlayer, &[&winning_option, &pool_option], 130).await.expect_err("should not be able to exceed the maximum liability with a parlay");
+        place_parlay(&setup, &parlayer, &[&winning_option, &pool_option], 100).await.expect("should be able to place parlay");
+        // and it keeps counting against the option once placed
+        place_bet(&setup, &winner, &winning_option, 10).await.expect_err("should not be able to exceed the maximum liability next to a parlay");
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("should be able to resolve wager");
+
+        // the winner is paid the odds they bet at, the pot is untouched, and the house covers the difference
+        assert_eq!(fetch_balance(&setup, "winner").await, 800 + 600);
+        assert_eq!(fetch_balance(&setup, "loser").await, 900);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.database_requester.send(DatabaseRequest::GetWagerInfo { id: wager.clone(), responder: resp_tx }).await.unwrap();
+        assert_eq!(resp_rx.await.unwrap().unwrap().unwrap().status, WagerStatus::Resolved);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_wager_dead_heat() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let first = database_connection.add_user(&DbUser::new("first", 1000)).await.unwrap().unwrap().id;
+        let second = database_connection.add_user(&DbUser::new("second", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let wager = database_connection.add_wager(&DbWager::new("wager", "wager", 0)).await.unwrap().unwrap().id;
+        let first_option = database_connection.add_wager_option_db(&DbWagerOption::new("first", "first", wager.clone())).await.unwrap().unwrap().id;
+        let second_option = database_connection.add_wager_option_db(&DbWagerOption::new("second", "second", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        let other_wager = database_connection.add_wager(&DbWager::new("other", "other", 0)).await.unwrap().unwrap().id;
+        let other_option = database_connection.add_wager_option_db(&DbWagerOption::new("other", "other", other_wager)).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(first, first_option.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(second, second_option.clone(), 300), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(loser, losing_option, 400), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        let resolve = |winners: Vec<DbWinner>| {
+            let wager_requester = setup.wager_requester.clone();
+            let wager = wager.clone();
+            async move {
+                let (resp_tx, resp_rx) = oneshot::channel();
+                wager_requester.send(WagerRequest::ResolveWager { wager_id: wager, winners, user: None, responder: resp_tx }).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+        resolve(vec![]).await.expect_err("should need a winner");
+        resolve([sole_winner(&first_option), sole_winner(&first_option)].concat()).await.expect_err("should not name a winner twice");
+        resolve([sole_winner(&first_option), sole_winner(&other_option)].concat()).await.expect_err("should not name another wager's option");
+        resolve(vec![DbWinner { option: first_option.clone(), weight: Some(0) }]).await.expect_err("should not have a zero weight");
+
+        // the first option gets three quarters of the 400 losing pool, regardless of how much was staked on it
+        let winners = vec![
+            DbWinner { option: first_option.clone(), weight: Some(3) },
+            DbWinner { option: second_option.clone(), weight: Some(1) },
+        ];
+        resolve(winners.clone()).await.expect("should be able to resolve wager");
+        assert_eq!(fetch_balance(&setup, "first").await, 900 + 100 + 300);
+        assert_eq!(fetch_balance(&setup, "second").await, 700 + 300 + 100);
+        assert_eq!(fetch_balance(&setup, "loser").await, 600);
+
+        let reordered = winners.into_iter().rev().collect();
+        resolve(reordered).await.expect("repeating a resolution in another order should succeed");
+        resolve(sole_winner(&first_option)).await.expect_err("should not be able to change the winners");
+        assert_eq!(fetch_balance(&setup, "first").await, 1300);
+    }
+
+    // Resolves a wager on which only the losing option was backed, returning the resolution
+    async fn resolve_with_no_winner(next_status: WagerStatus, policy: impl FnOnce(&Thing) -> NoWinnerPolicy) -> (ManagerSetup, Thing, common::Resolution) {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let mut next_wager = DbWager::new("next", "next", 100);
+        next_wager.status = next_status;
+        let next_wager = database_connection.add_wager(&next_wager).await.unwrap().unwrap().id;
+        let mut wager = DbWager::new("wager", "wager", 200);
+        wager.no_winner_policy = policy(&next_wager);
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(loser, losing_option, 300), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager, winners: sole_winner(&winning_option), user: None, responder: resp_tx }).await.unwrap();
+        let resolution = resp_rx.await.unwrap().expect("should be able to resolve wager");
+        (setup, next_wager, resolution)
+    }
+
+    #[tokio::test]
+    async fn test_no_winner_refund() {
+        let (setup, next_wager, resolution) = resolve_with_no_winner(WagerStatus::Open, |_| NoWinnerPolicy::Refund).await;
+        assert_eq!(resolution.no_winner_policy, Some(NoWinnerPolicy::Refund));
+        assert_eq!(fetch_balance(&setup, "loser").await, 1000);
+        assert_eq!(fetch_wager_info(&setup, &next_wager).await.pot, 100);
+    }
+
+    #[tokio::test]
+    async fn test_no_winner_rollover() {
+        let (setup, next_wager, resolution) = resolve_with_no_winner(WagerStatus::Open, |next_wager| NoWinnerPolicy::Rollover { wager_id: next_wager.id.to_string() }).await;
+        assert!(matches!(resolution.no_winner_policy, Some(NoWinnerPolicy::Rollover { .. })));
+        assert_eq!(fetch_balance(&setup, "loser").await, 700);
+        // the 300 staked and the pot of 200 are carried over
+        assert_eq!(fetch_wager_info(&setup, &next_wager).await.pot, 100 + 300 + 200);
+    }
+
+    #[tokio::test]
+    async fn test_no_winner_rollover_into_finished_wager() {
+        let (setup, next_wager, resolution) = resolve_with_no_winner(WagerStatus::Cancelled, |next_wager| NoWinnerPolicy::Rollover { wager_id: next_wager.id.to_string() }).await;
+        // the target finished after the rollover was set up, so the stakes are refunded instead
+        assert_eq!(resolution.no_winner_policy, Some(NoWinnerPolicy::Refund));
+        assert_eq!(fetch_balance(&setup, "loser").await, 1000);
+        assert_eq!(fetch_wager_info(&setup, &next_wager).await.pot, 100);
+    }
+
+    #[tokio::test]
+    async fn test_no_winner_house() {
+        let (setup, next_wager, resolution) = resolve_with_no_winner(WagerStatus::Open, |_| NoWinnerPolicy::House).await;
+        assert_eq!(resolution.no_winner_policy, Some(NoWinnerPolicy::House));
+        assert_eq!(fetch_balance(&setup, "loser").await, 700);
+        assert_eq!(fetch_wager_info(&setup, &next_wager).await.pot, 100);
+    }
+
+    #[tokio::test]
+    async fn test_stake_limits() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let user = database_connection.add_user(&DbUser::new("user", 1000)).await.unwrap().unwrap().id;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+        let mut wager = DbWager::new("wager", "wager", 0);
+        wager.stake_limits = StakeLimits { max_stake: Some(300), ..StakeLimits::default() };
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let option1 = database_connection.add_wager_option_db(&DbWagerOption::new("option1", "option1", wager.clone())).await.unwrap().unwrap().id;
+        let option2 = database_connection.add_wager_option_db(&DbWagerOption::new("option2", "option2", wager.clone())).await.unwrap().unwrap().id;
+
+        let stake_limits = StakeLimits { min_stake: Some(50), max_stake: Some(1000), max_exposure: Some(500) };
+        let setup = start_managers_with_config(database_connection, ServerConfig { stake_limits, ..ServerConfig::default() });
+        let hit = |result: anyhow::Result<(u64, DbBet)>| result.expect_err("should hit a stake limit").downcast::<Rejection>().ok();
+
+        assert_eq!(hit(place_bet(&setup, &user, &option1, 49).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MinStake(50) }));
+        // the wager's own maximum replaces the server wide one
+        assert_eq!(hit(place_bet(&setup, &user, &option1, 301).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MaxStake(300) }));
+        let (_, bet) = place_bet(&setup, &user, &option1, 300).await.expect("should be able to place bet");
+        // exposure counts bets on every option of the wager
+        assert_eq!(hit(place_bet(&setup, &user, &option2, 201).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MaxExposure(500) }));
+        place_bet(&setup, &user, &option2, 200).await.expect("should be able to place bet");
+
+        let change_bet = |val: u64| {
+            let wager_requester = setup.wager_requester.clone();
+            let (bet, user) = (bet.id.clone(), user.clone());
+            async move {
+                let (resp_tx, resp_rx) = oneshot::channel();
+                wager_requester.send(WagerRequest::ChangeBet { bet, user, val, responder: resp_tx }).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+        assert_eq!(hit(change_bet(20).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MinStake(50) }));
+        // the bet being changed does not count towards the exposure
+        let (balance, changed) = change_bet(100).await.expect("should be able to change bet");
+        assert_eq!(balance, 700);
+        // the bet is priced as if it had been placed with its new stake, 100 of 300
+        assert_eq!(changed.placed_probability, Some(3_333));
+        assert_eq!(hit(change_bet(301).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MaxStake(300) }));
+
+        let set_limits = |user: &Thing| {
+            let (wager_requester, wager_id, user) = (setup.wager_requester.clone(), wager.clone(), user.clone());
+            async move {
+                let limits = StakeLimits { max_exposure: Some(1000), ..StakeLimits::default() };
+                let (resp_tx, resp_rx) = oneshot::channel();
+                wager_requester.send(WagerRequest::SetStakeLimits { wager_id, limits, user, responder: resp_tx }).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+        set_limits(&user).await.expect_err("players should not be able to lift their own limits");
+        assert_eq!(hit(change_bet(301).await), Some(Rejection::StakeLimitHit { limit: StakeLimit::MaxStake(300) }));
+        set_limits(&admin).await.expect("an admin should be able to change the limits");
+        change_bet(301).await.expect("the new limits should apply");
+    }
+
+    async fn place_parlay(setup: &ManagerSetup, user: &Thing, wager_options: &[&Thing], val: u64) -> anyhow::Result<(u64, DbParlay)> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let wager_options = wager_options.iter().map(|&option| option.clone()).collect();
+        setup.wager_requester.send(WagerRequest::PlaceParlay { user: user.clone(), wager_options, val, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    async fn resolve(setup: &ManagerSetup, wager: &Thing, winner: &Thing) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(winner), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("should be able to resolve wager");
+    }
+
+    async fn fetch_parlay(setup: &ManagerSetup, parlay: &DbParlay) -> DbParlay {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.database_requester.send(DatabaseRequest::GetActiveParlays { responder: resp_tx }).await.unwrap();
+        let active = resp_rx.await.unwrap().unwrap();
+        active.into_iter().find(|active| active.id == parlay.id).expect("parlay should still be active")
+    }
+
+    #[tokio::test]
+    async fn test_parlay() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let user = database_connection.add_user(&DbUser::new("user", 1000)).await.unwrap().unwrap().id;
+        let other = database_connection.add_user(&DbUser::new("other", 1000)).await.unwrap().unwrap().id;
+        let mut fixed = DbWager::new("fixed", "fixed", 0);
+        fixed.kind = WagerKind::FixedOdds;
+        let fixed = database_connection.add_wager(&fixed).await.unwrap().unwrap().id;
+        let fixed_win = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", fixed.clone())).await.unwrap().unwrap().id;
+        database_connection.set_option_odds(&fixed_win, 250).await.unwrap();
+        let pool = database_connection.add_wager(&DbWager::new("pool", "pool", 0)).await.unwrap().unwrap().id;
+        let pool_win = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", pool.clone())).await.unwrap().unwrap().id;
+        let pool_lose = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", pool.clone())).await.unwrap().unwrap().id;
+        let cancelled = database_connection.add_wager(&DbWager::new("cancelled", "cancelled", 0)).await.unwrap().unwrap().id;
+        let cancelled_option = database_connection.add_wager_option_db(&DbWagerOption::new("option", "option", cancelled.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(other.clone(), pool_win.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(other.clone(), pool_lose.clone(), 100), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        place_parlay(&setup, &user, &[&fixed_win], 100).await.expect_err("a parlay needs more than one leg");
+        let (balance, parlay) = place_parlay(&setup, &user, &[&fixed_win, &pool_win, &cancelled_option], 100).await.expect("should be able to place parlay");
+        assert_eq!(balance, 900);
+        assert_eq!(parlay.legs[0].odds, Some(250));
+        let (_, losing_parlay) = place_parlay(&setup, &user, &[&fixed_win, &pool_lose], 100).await.expect("should be able to place parlay");
+
+        resolve(&setup, &fixed, &fixed_win).await;
+        let fetched = fetch_parlay(&setup, &parlay).await;
+        assert_eq!(fetched.legs[0].status, LegStatus::Won { numerator: 250, denominator: 100 });
+        assert_eq!(fetched.legs[1].status, LegStatus::Pending);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CancelWager { wager_id: cancelled, user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("should be able to cancel wager");
+        assert_eq!(fetch_parlay(&setup, &parlay).await.legs[2].status, LegStatus::Void);
+        assert_eq!(fetch_balance(&setup, "user").await, 800);
+
+        // the pool leg pays 200 for every 100 staked on it, and the cancelled leg drops out
+        resolve(&setup, &pool, &pool_win).await;
+        assert_eq!(fetch_balance(&setup, "user").await, 800 + 100 * 5);
+        assert_eq!(fetch_balance(&setup, "other").await, 800 + 200);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.database_requester.send(DatabaseRequest::GetActiveParlays { responder: resp_tx }).await.unwrap();
+        assert!(resp_rx.await.unwrap().unwrap().iter().all(|active| active.id != parlay.id && active.id != losing_parlay.id));
+    }
+
+    async fn add_numeric_wager(database_connection: &mut DatabaseConnection<Db>, ranges: Vec<(String, common::OutcomeRange)>) -> (Thing, Vec<Thing>) {
+        let wager = database_connection.add_wager(&DbWager::new("numeric", "numeric", 0)).await.unwrap().unwrap().id;
+        let mut options = Vec::new();
+        for (name, range) in ranges {
+            let mut option = DbWagerOption::new(name.clone(), name, wager.clone());
+            option.range = Some(range);
+            options.push(database_connection.add_wager_option_db(&option).await.unwrap().unwrap().id);
+        }
+        (wager, options)
+    }
+
+    async fn resolve_numeric(setup: &ManagerSetup, wager: &Thing, outcome: i64) -> anyhow::Result<common::Resolution> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveNumericWager { wager_id: wager.clone(), outcome, user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resolve_numeric_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let under = database_connection.add_user(&DbUser::new("under", 1000)).await.unwrap().unwrap().id;
+        let over = database_connection.add_user(&DbUser::new("over", 1000)).await.unwrap().unwrap().id;
+        let ranges = common::OutcomeRange::split_at(&[2000, 1000]);
+        let names: Vec<&str> = ranges.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["Under 10", "10 to 20", "20 or more"]);
+        let (wager, options) = add_numeric_wager(&mut database_connection, common::OutcomeRange::over_under(4550)).await;
+        database_connection.place_bet(&DbBet::new(under, options[0].clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(over, options[1].clone(), 300), None).await.unwrap();
+
+        let setup = start_managers(database_connection);
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&options[0]), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("numeric wagers should be resolved with a number");
+
+        let resolution = resolve_numeric(&setup, &wager, 4600).await.expect("should be able to resolve wager");
+        assert_eq!(resolution.outcome, Some(4600));
+        assert_eq!(resolution.winners, vec![common::Winner { option_id: options[1].id.to_string(), weight: None }]);
+        assert_eq!(fetch_balance(&setup, "over").await, 1100);
+        assert_eq!(fetch_balance(&setup, "under").await, 900);
+
+        assert_eq!(resolve_numeric(&setup, &wager, 4600).await.expect("repeating a resolution should succeed"), resolution);
+        // the same option wins, but the recorded number would change
+        let error = resolve_numeric(&setup, &wager, 4700).await.expect_err("should not be able to change the outcome");
+        assert!(matches!(error.downcast_ref::<Rejection>(), Some(Rejection::ResolutionConflict { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_numeric_wager_on_the_line_is_a_push() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let user = database_connection.add_user(&DbUser::new("user", 1000)).await.unwrap().unwrap().id;
+        let (wager, options) = add_numeric_wager(&mut database_connection, common::OutcomeRange::over_under(4500)).await;
+        database_connection.place_bet(&DbBet::new(user.clone(), options[0].clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(user, options[1].clone(), 300), None).await.unwrap();
+        let (overlapping, _) = add_numeric_wager(&mut database_connection, [common::OutcomeRange::split_at(&[10]), common::OutcomeRange::split_at(&[20])].concat()).await;
+
+        let setup = start_managers(database_connection);
+        resolve_numeric(&setup, &overlapping, 15).await.expect_err("only one option should hold the outcome");
+        let resolution = resolve_numeric(&setup, &wager, 4500).await.expect("should be able to resolve wager");
+        assert_eq!((resolution.winners, resolution.no_winner_policy), (vec![], Some(NoWinnerPolicy::Refund)));
+        assert_eq!(fetch_balance(&setup, "user").await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_settle_challenge() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let challenger = database_connection.add_user(&DbUser::new("challenger", 1000)).await.unwrap().unwrap().id;
+        let opponent = database_connection.add_user(&DbUser::new("opponent", 1000)).await.unwrap().unwrap().id;
+        let arbiter = database_connection.add_user(&DbUser::new("arbiter", 0)).await.unwrap().unwrap().id;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+        let mut accepted = Vec::new();
+        for arbiter in [Some(arbiter.clone()), None] {
+            let challenge = DbChallenge::new(challenger.clone(), opponent.clone(), 200, "terms", arbiter);
+            let challenge = database_connection.propose_challenge(&challenge).await.unwrap();
+            let (_, challenge) = database_connection.accept_challenge(&challenge.id, &opponent).await.unwrap();
+            accepted.push(challenge);
+        }
+        let (arbitrated, unarbitrated) = (accepted[0].id.clone(), accepted[1].id.clone());
+        let escrow = accepted[0].wager.clone().unwrap();
+
+        let setup = start_managers(database_connection);
+        let settle = |challenge: &Thing, user: &Thing, winner: Option<&Thing>| {
+            let wager_requester = setup.wager_requester.clone();
+            let (challenge, user, winner) = (challenge.clone(), user.clone(), winner.cloned());
+            async move {
+                let (resp_tx, resp_rx) = oneshot::channel();
+                wager_requester.send(WagerRequest::SettleChallenge { challenge, user, winner, responder: resp_tx }).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+        settle(&arbitrated, &challenger, Some(&challenger)).await.expect_err("players should not settle their own challenge");
+        settle(&unarbitrated, &arbiter, Some(&challenger)).await.expect_err("only the agreed arbiter should settle");
+        settle(&arbitrated, &arbiter, Some(&arbiter)).await.expect_err("the winner should be one of the players");
+
+        // the escrow wager can only be settled through its challenge
+        let escrow_option = Thing { tb: TABLE_WAGER_OPTION.into(), id: Id::String(fetch_wager_info(&setup, &escrow).await.options[0].id.clone()) };
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: escrow.clone(), winners: sole_winner(&escrow_option), user: Some(admin.clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("should not be able to resolve an escrow wager directly");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CancelWager { wager_id: escrow.clone(), user: Some(admin.clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("should not be able to cancel an escrow wager directly");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ReopenWager { wager_id: escrow.clone(), closes_at: None, user: Some(admin.clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("should not be able to reopen an escrow wager");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::OverturnResolution { wager_id: escrow.clone(), user: admin.clone(), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("should not be able to overturn an escrow wager");
+        place_bet(&setup, &challenger, &escrow_option, 100).await.expect_err("should not be able to bet on an escrow wager");
+        link_gsi(&setup, &escrow, &admin).await.expect_err("should not be able to link an escrow wager to a game");
+        assert_eq!(fetch_wager_info(&setup, &escrow).await.status, WagerStatus::Locked);
+
+        let settled = settle(&arbitrated, &arbiter, Some(&opponent)).await.expect("the arbiter should be able to settle");
+        assert_eq!((settled.status, settled.winner), (ChallengeStatus::Settled, Some(opponent.clone())));
+        assert_eq!(fetch_balance(&setup, "opponent").await, 600 + 400);
+        settle(&arbitrated, &arbiter, Some(&challenger)).await.expect_err("should not be able to settle twice");
+
+        let voided = settle(&unarbitrated, &admin, None).await.expect("an admin should be able to settle");
+        assert_eq!(voided.status, ChallengeStatus::Void);
+        assert_eq!(fetch_balance(&setup, "challenger").await, 800);
+        assert_eq!(fetch_balance(&setup, "opponent").await, 1200);
+    }
+
+    #[tokio::test]
+    async fn test_bracket_advances_winners_and_losers() {
+        let database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let setup = start_managers(database_connection);
+        let definition = common::BracketDefinition {
+            name: "cup".into(),
+            format: common::BracketFormat::DoubleElimination,
+            teams: ["a", "b", "c", "d"].map(String::from).to_vec(),
+        };
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CreateBracket { definition, responder: resp_tx }).await.unwrap();
+        let bracket = resp_rx.await.unwrap().expect("should be able to create bracket");
+        let wager_ids: Vec<Thing> = bracket.matches.iter().map(|bracket_match| Thing { tb: TABLE_WAGER.into(), id: Id::String(bracket_match.wager_id.clone()) }).collect();
+
+        let first = fetch_wager_info(&setup, &wager_ids[0]).await;
+        assert_eq!(first.name, "cup: Winners Round 1 Match 1");
+        assert_eq!(first.options.iter().map(|option| option.name.as_str()).collect::<Vec<_>>(), vec!["a", "d"]);
+        let winners_final = fetch_wager_info(&setup, &wager_ids[2]).await;
+        assert_eq!(winners_final.status, WagerStatus::Waiting);
+        assert_eq!(winners_final.options[0].name, "Winner of Winners Round 1 Match 1");
+        let placeholder_option = Thing { tb: TABLE_WAGER_OPTION.into(), id: Id::String(winners_final.options[0].id.clone()) };
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager_ids[2].clone(), winners: sole_winner(&placeholder_option), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("should not be able to resolve a match before its teams are known");
+
+        // d beats a, and b beats c
+        for (wager_id, side) in [(&wager_ids[0], 1), (&wager_ids[1], 0)] {
+            let option = fetch_wager_info(&setup, wager_id).await.options[side].id.clone();
+            let option = Thing { tb: TABLE_WAGER_OPTION.into(), id: Id::String(option) };
+            let (resp_tx, resp_rx) = oneshot::channel();
+            setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager_id.clone(), winners: sole_winner(&option), user: None, responder: resp_tx }).await.unwrap();
+            resp_rx.await.unwrap().expect("should be able to resolve match");
+        }
+
+        let winners_final = fetch_wager_info(&setup, &wager_ids[2]).await;
+        assert_eq!(winners_final.status, WagerStatus::Open);
+        assert_eq!(winners_final.options.iter().map(|option| option.name.as_str()).collect::<Vec<_>>(), vec!["d", "b"]);
+        let losers_round = fetch_wager_info(&setup, &wager_ids[3]).await;
+        assert_eq!(losers_round.status, WagerStatus::Open);
+        assert_eq!(losers_round.options.iter().map(|option| option.name.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+        assert_eq!(fetch_wager_info(&setup, &wager_ids[5]).await.status, WagerStatus::Waiting);
+    }
+
+    fn potential_option(name: &str, odds: Option<u32>, range: Option<common::OutcomeRange>) -> common::PotentialWagerOption {
+        common::PotentialWagerOption { name: name.into(), description: name.into(), odds, range }
+    }
+
+    fn potential_wager(kind: WagerKind, closes_at: Option<u64>) -> common::PotentialWager {
+        common::PotentialWager {
+            name: "created".into(),
+            description: "created".into(),
+            pot: 100,
+            closes_at,
+            rake: None,
+            kind,
+            no_winner_policy: NoWinnerPolicy::Refund,
+            stake_limits: StakeLimits::default(),
+            category: None,
+            game: None,
+            tags: vec![],
+            electorate: Electorate::Nobody,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_wager() {
+        let database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let config = ServerConfig {
+            default_rake: Rake { basis_points: 500, destination: RakeDestination::House },
+            ..ServerConfig::default()
+        };
+        let setup = start_managers(database_connection);
+        // driven directly rather than through its queue, so its lock schedule can be looked at
+        let (_, work_queue) = mpsc::channel(1);
+        let (notifier, _) = broadcast::channel(1);
+        let mut manager = WagerManager::new(work_queue, setup.database_requester.clone(), notifier, config);
+
+        let closes_at = unix_now() + 3600;
+        let options = vec![potential_option("home", Some(150), None), potential_option("away", Some(250), None)];
+        let mut potential = potential_wager(WagerKind::FixedOdds, Some(closes_at));
+        potential.category = Some(" Match Winner ".into());
+        potential.game = Some("  ".into());
+        let created = manager
+            .create_wager(potential, options)
+            .await
+            .expect("should be able to create wager");
+
+        let wager = fetch_wager_info(&setup, &created.id).await;
+        assert_eq!(wager.status, WagerStatus::Open);
+        assert_eq!(wager.kind, WagerKind::FixedOdds);
+        assert_eq!(wager.rake.basis_points, 500);
+        assert_eq!((wager.category.as_deref(), wager.game), (Some("match winner"), None));
+        assert_eq!(wager.options.iter().map(|option| (option.name.as_str(), option.odds)).collect::<Vec<_>>(), vec![("home", Some(150)), ("away", Some(250))]);
+
+        // the new wager is locked at its closing time like any other
+        assert_eq!(manager.lock_schedule.get(&closes_at), Some(&vec![created.id.clone()]));
+    }
+
+    #[tokio::test]
+    async fn test_create_wager_rejects_invalid_wagers() {
+        let now = unix_now();
+        let pari_mutuel = potential_wager(WagerKind::PariMutuel, None);
+        let valid = vec![potential_option("a", None, None), potential_option("b", None, None)];
+        assert!(check_potential_wager(&pari_mutuel, &valid, now).is_ok());
+        assert!(check_potential_wager(&pari_mutuel, &valid[..1], now).is_err());
+        assert!(check_potential_wager(&potential_wager(WagerKind::PariMutuel, Some(now)), &valid, now).is_err());
+        assert!(check_potential_wager(&pari_mutuel, &[potential_option("a", None, None), potential_option("a ", None, None)], now).is_err());
+        assert!(check_potential_wager(&pari_mutuel, &[potential_option("a", Some(200), None), potential_option("b", None, None)], now).is_err());
+        assert!(check_potential_wager(&potential_wager(WagerKind::FixedOdds, None), &valid, now).is_err());
+
+        let ranged = |ranges: [(Option<i64>, Option<i64>); 2]| {
+            ranges
+                .iter()
+                .enumerate()
+                .map(|(i, (min, max))| potential_option(&i.to_string(), None, Some(common::OutcomeRange { min: *min, max: *max })))
+                .collect::<Vec<_>>()
+        };
+        assert!(check_potential_wager(&pari_mutuel, &ranged([(None, Some(10)), (Some(10), None)]), now).is_ok());
+        assert!(check_potential_wager(&pari_mutuel, &ranged([(None, Some(10)), (Some(5), None)]), now).is_err());
+        assert!(check_potential_wager(&pari_mutuel, &ranged([(Some(10), Some(10)), (Some(20), None)]), now).is_err());
+        assert!(check_potential_wager(&pari_mutuel, &[potential_option("a", None, Some(common::OutcomeRange { min: None, max: Some(10) })), potential_option("b", None, None)], now).is_err());
+
+        // rolling over into a wager that does not exist or has already finished is refused, and nothing is
+        // created
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let mut finished = DbWager::new("finished", "finished", 0);
+        finished.status = WagerStatus::Resolved;
+        let finished = database_connection.add_wager(&finished).await.unwrap().unwrap().id;
+        let setup = start_managers(database_connection);
+        for target in ["missing".to_string(), finished.id.to_string()] {
+            let mut rollover = pari_mutuel.clone();
+            rollover.no_winner_policy = NoWinnerPolicy::Rollover { wager_id: target };
+            let (resp_tx, resp_rx) = oneshot::channel();
+            setup.wager_requester.send(WagerRequest::CreateWager { wager: rollover, options: valid.clone(), responder: resp_tx }).await.unwrap();
+            assert!(resp_rx.await.unwrap().is_err());
+        }
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.database_requester.send(DatabaseRequest::GetWagerInfoMatching { filter: common::WagerFilter::default(), responder: resp_tx }).await.unwrap();
+        assert_eq!(resp_rx.await.unwrap().unwrap().len(), 1);
+    }
+
+    async fn create_wager(setup: &ManagerSetup, wager: common::PotentialWager, options: Vec<common::PotentialWagerOption>) -> DbWager {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CreateWager { wager, options, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("should be able to create wager")
+    }
+
+    async fn edit_wager_option(setup: &ManagerSetup, wager_option_id: &Thing, name: &str) -> anyhow::Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup
+            .wager_requester
+            .send(WagerRequest::EditWagerOption { wager_option_id: wager_option_id.clone(), name: Some(name.into()), description: None, responder: resp_tx })
+            .await
+            .unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    async fn link_gsi(setup: &ManagerSetup, wager: &Thing, user: &Thing) -> anyhow::Result<()> {
+        let teams = fetch_wager_info(setup, wager)
+            .await
+            .options
+            .into_iter()
+            .zip(["ct", "t"])
+            .map(|(option, team)| common::GsiTeam { team: team.into(), wager_option_id: option.id })
+            .collect();
+        let link = DbGsiLink::new(wager.clone(), common::GsiLink { game: common::GsiGame::CounterStrike2, map_name: None, match_id: None, teams });
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LinkGsi { link, user: user.clone(), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_link_gsi() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let (wager, _, _) = add_voted_wager(&mut database_connection, Electorate::Nobody, &["red", "blue"]).await;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+        let setup = start_managers(database_connection);
+
+        let red = Thing { tb: TABLE_USER.into(), id: Id::String("red".into()) };
+        link_gsi(&setup, &wager, &red).await.expect_err("only an admin should be able to link a wager to a game");
+        link_gsi(&setup, &wager, &admin).await.expect("an admin should be able to link a wager to a game");
+    }
+
+    async fn remove_wager_option(setup: &ManagerSetup, wager_option_id: &Thing) -> anyhow::Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::RemoveWagerOption { wager_option_id: wager_option_id.clone(), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_edit_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let bettor = database_connection.add_user(&DbUser::new("bettor", 100)).await.unwrap().unwrap().id;
+        let setup = start_managers(database_connection);
+        let options = vec![potential_option("a", None, None), potential_option("b", None, None)];
+        let wager = create_wager(&setup, potential_wager(WagerKind::PariMutuel, None), options).await;
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup
+            .wager_requester
+            .send(WagerRequest::EditWager { wager_id: wager.id.clone(), name: Some("renamed".into()), description: None, pot: Some(500), responder: resp_tx })
+            .await
+            .unwrap();
+        resp_rx.await.unwrap().expect("should be able to edit an open wager");
+        let info = fetch_wager_info(&setup, &wager.id).await;
+        assert_eq!((info.name.as_str(), info.description.as_str(), info.pot), ("renamed", "created", 500));
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::AddWagerOption { wager_id: wager.id.clone(), option: potential_option("a", None, None), responder: resp_tx }).await.unwrap();
+        assert!(resp_rx.await.unwrap().is_err(), "option names should stay unique");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::AddWagerOption { wager_id: wager.id.clone(), option: potential_option("c", None, None), responder: resp_tx }).await.unwrap();
+        let added = resp_rx.await.unwrap().expect("should be able to add an option");
+
+        assert!(edit_wager_option(&setup, &added, "b").await.is_err());
+        edit_wager_option(&setup, &added, "see").await.expect("should be able to rename an option");
+        assert_eq!(fetch_wager_info(&setup, &wager.id).await.options[2].name, "see");
+
+        // removing an option refunds its bets, but a wager keeps at least two options
+        place_bet(&setup, &bettor, &added, 40).await.unwrap();
+        assert_eq!(fetch_balance(&setup, "bettor").await, 60);
+        remove_wager_option(&setup, &added).await.expect("should be able to remove an option");
+        assert_eq!(fetch_balance(&setup, "bettor").await, 100);
+        assert_eq!(fetch_wager_info(&setup, &wager.id).await.options.len(), 2);
+        assert!(remove_wager_option(&setup, &wager.options[0]).await.is_err());
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LockWager { wager_id: wager.id.clone(), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().unwrap();
+        assert!(edit_wager_option(&setup, &wager.options[0], "locked").await.is_err(), "locked wagers cannot be edited");
+    }
+
+    async fn cash_out(setup: &ManagerSetup, user: &Thing, bet: &Thing, stake: u64) -> anyhow::Result<(u64, u64)> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CashOutBet { bet: bet.clone(), user: user.clone(), stake, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cash_out_bet() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let seller = database_connection.add_user(&DbUser::new("seller", 1000)).await.unwrap().unwrap().id;
+        let other = database_connection.add_user(&DbUser::new("other", 1000)).await.unwrap().unwrap().id;
+        let wager = database_connection.add_wager(&DbWager::new("wager", "wager", 0)).await.unwrap().unwrap().id;
+        let backed = database_connection.add_wager_option_db(&DbWagerOption::new("backed", "backed", wager.clone())).await.unwrap().unwrap().id;
+        let opposed = database_connection.add_wager_option_db(&DbWagerOption::new("opposed", "opposed", wager.clone())).await.unwrap().unwrap().id;
+        let config = ServerConfig { cash_out_haircut: 1_000, ..ServerConfig::default() };
+        let setup = start_managers_with_config(database_connection, config);
+
+        place_bet(&setup, &other, &opposed, 100).await.unwrap();
+        let (_, bet) = place_bet(&setup, &seller, &backed, 100).await.unwrap();
+        assert_eq!(bet.placed_probability, Some(5_000));
+        // the option drifts from a 50% to a 25% chance
+        place_bet(&setup, &other, &opposed, 200).await.unwrap();
+
+        let (balance, paid) = cash_out(&setup, &seller, &bet.id, 40).await.expect("should be able to cash out part of a bet");
+        assert_eq!((balance, paid), (918, 18));
+        let wager_info = fetch_wager_info(&setup, &wager).await;
+        assert_eq!(wager_info.options[0].bets[0].val, 60);
+
+        cash_out(&setup, &seller, &bet.id, 61).await.expect_err("should not be able to sell more than is left");
+        cash_out(&setup, &other, &bet.id, 10).await.expect_err("should not be able to cash out someone else's bet");
+        // what is left is now 60 of 360 staked
+        let (balance, paid) = cash_out(&setup, &seller, &bet.id, 60).await.expect("should be able to cash out the rest");
+        assert_eq!((balance, paid), (935, 17));
+        assert!(fetch_wager_info(&setup, &wager).await.options[0].bets.is_empty());
+
+        let (_, bet) = place_bet(&setup, &seller, &backed, 100).await.unwrap();
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::LockWager { wager_id: wager.clone(), user: None, responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().unwrap();
+        cash_out(&setup, &seller, &bet.id, 50).await.expect_err("should not be able to cash out once the wager has locked");
+        assert_eq!(fetch_balance(&setup, "seller").await, 835);
+    }
+
+    // A pari-mutuel wager with a winning and a losing bet of 100 each, resolved by a manager whose
+    // resolutions can be disputed for `dispute_window` seconds
+    async fn resolve_with_dispute_window(dispute_window: u64) -> (ManagerSetup, Thing, Thing, Thing) {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let winner = database_connection.add_user(&DbUser::new("winner", 1000)).await.unwrap().unwrap().id;
+        let loser = database_connection.add_user(&DbUser::new("loser", 1000)).await.unwrap().unwrap().id;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        database_connection.add_user(&admin).await.unwrap().unwrap();
+        let wager = database_connection.add_wager(&DbWager::new("wager", "wager", 0)).await.unwrap().unwrap().id;
+        let winning_option = database_connection.add_wager_option_db(&DbWagerOption::new("win", "win", wager.clone())).await.unwrap().unwrap().id;
+        let losing_option = database_connection.add_wager_option_db(&DbWagerOption::new("lose", "lose", wager.clone())).await.unwrap().unwrap().id;
+        database_connection.place_bet(&DbBet::new(winner, winning_option.clone(), 100), None).await.unwrap();
+        database_connection.place_bet(&DbBet::new(loser.clone(), losing_option.clone(), 100), None).await.unwrap();
+
+        let setup = start_managers_with_config(database_connection, ServerConfig { dispute_window, ..ServerConfig::default() });
+        resolve(&setup, &wager, &winning_option).await;
+        (setup, wager, losing_option, loser)
+    }
+
+    #[tokio::test]
+    async fn test_resolution_is_final_once_dispute_window_closes() {
+        let (setup, wager, _, _) = resolve_with_dispute_window(1).await;
+        let resolution = fetch_wager_info(&setup, &wager).await.resolution.unwrap();
+        assert!(resolution.pending_until.is_some());
+        assert_eq!(fetch_balance(&setup, "winner").await, 900, "nothing is paid out while the resolution is pending");
+
+        timeout(Duration::from_secs(5), async {
+            while fetch_wager_info(&setup, &wager).await.resolution.unwrap().pending_until.is_some() {
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("resolution should become final");
+        assert_eq!(fetch_balance(&setup, "winner").await, 1100);
+        assert_eq!(fetch_balance(&setup, "loser").await, 900);
+    }
+
+    #[tokio::test]
+    async fn test_disputed_resolution() {
+        let (setup, wager, losing_option, loser) = resolve_with_dispute_window(3600).await;
+        let admin = Thing { tb: TABLE_USER.into(), id: Id::String("admin".into()) };
+        let request = |make: fn(Thing, Thing, Responder<()>) -> WagerRequest, user: &Thing| {
+            let (resp_tx, resp_rx) = oneshot::channel();
+            let request = make(wager.clone(), user.clone(), resp_tx);
+            let wager_requester = setup.wager_requester.clone();
+            async move {
+                wager_requester.send(request).await.unwrap();
+                resp_rx.await.unwrap()
+            }
+        };
+        let dispute = |wager_id, user, responder| WagerRequest::DisputeResolution { wager_id, user, reason: "wrong winner".into(), responder };
+        let confirm = |wager_id, user, responder| WagerRequest::ConfirmResolution { wager_id, user, responder };
+        let overturn = |wager_id, user, responder| WagerRequest::OverturnResolution { wager_id, user, responder };
+
+        request(dispute, &loser).await.expect("should be able to dispute a pending resolution");
+        assert!(fetch_wager_info(&setup, &wager).await.resolution.unwrap().disputed);
+        request(confirm, &loser).await.expect_err("only an admin can confirm a resolution");
+        request(overturn, &loser).await.expect_err("only an admin can overturn a resolution");
+
+        request(overturn, &admin).await.expect("an admin should be able to overturn a resolution");
+        let wager_info = fetch_wager_info(&setup, &wager).await;
+        assert_eq!((wager_info.status, wager_info.resolution), (WagerStatus::Locked, None));
+        assert_eq!(fetch_balance(&setup, "winner").await, 900);
+
+        resolve(&setup, &wager, &losing_option).await;
+        request(confirm, &admin).await.expect("an admin should be able to confirm a resolution");
+        assert_eq!(fetch_wager_info(&setup, &wager).await.resolution.unwrap().pending_until, None);
+        assert_eq!(fetch_balance(&setup, "winner").await, 900);
+        assert_eq!(fetch_balance(&setup, "loser").await, 1100);
+        request(dispute, &loser).await.expect_err("a final resolution cannot be disputed");
+    }
+
+    #[test]
+    fn test_tally_votes() {
+        let (a, b, c) = (Thing::from(("wager_option", "a")), Thing::from(("wager_option", "b")), Thing::from(("wager_option", "c")));
+        let tally = |votes: &[&Thing]| tally_votes(3, &votes.iter().map(|&vote| vote.clone()).collect::<Vec<_>>(), 5_000, 6_667);
+        assert_eq!(tally(&[&a]), VoteOutcome::Undecided, "one vote of three is short of the quorum");
+        assert_eq!(tally(&[&a, &a]), VoteOutcome::Decided(a.clone()));
+        assert_eq!(tally(&[&a, &b]), VoteOutcome::Undecided);
+        assert_eq!(tally(&[&a, &a, &b]), VoteOutcome::Deadlocked, "two thirds is just short of the supermajority");
+        assert_eq!(tally(&[&a, &b, &c]), VoteOutcome::Deadlocked);
+        assert_eq!(tally(&[&a, &a, &a]), VoteOutcome::Decided(a.clone()));
+    }
+
+    // A locked pari-mutuel wager with one option for each of `voters`, who have bet 100 on it
+    async fn add_voted_wager(
+        database_connection: &mut DatabaseConnection<Db>,
+        electorate: Electorate,
+        voters: &[&str],
+    ) -> (Thing, Vec<Thing>, Vec<Thing>) {
+        let mut wager = DbWager::new("side match", "side match", 0);
+        wager.electorate = electorate;
+        let wager = database_connection.add_wager(&wager).await.unwrap().unwrap().id;
+        let mut users = Vec::new();
+        let mut options = Vec::new();
+        for voter in voters {
+            let user = database_connection.add_user(&DbUser::new(*voter, 1000)).await.unwrap().unwrap().id;
+            let option = database_connection.add_wager_option_db(&DbWagerOption::new(*voter, *voter, wager.clone())).await.unwrap().unwrap().id;
+            database_connection.place_bet(&DbBet::new(user.clone(), option.clone(), 100), None).await.unwrap();
+            users.push(user);
+            options.push(option);
+        }
+        database_connection.set_wager_status(&wager, WagerStatus::Locked).await.unwrap();
+        (wager, users, options)
+    }
+
+    async fn vote(setup: &ManagerSetup, wager: &Thing, user: &Thing, option: &Thing) -> anyhow::Result<Option<common::Resolution>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::VoteOnOutcome { wager_id: wager.clone(), user: user.clone(), option: option.clone(), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_vote_resolves_wager() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let (wager, users, options) = add_voted_wager(&mut database_connection, Electorate::Bettors, &["first", "second", "third"]).await;
+        let outsider = database_connection.add_user(&DbUser::new("outsider", 1000)).await.unwrap().unwrap().id;
+        let setup = start_managers(database_connection);
+
+        vote(&setup, &wager, &outsider, &options[0]).await.expect_err("only bettors can vote");
+        assert_eq!(vote(&setup, &wager, &users[0], &options[1]).await.unwrap(), None);
+        // changing your mind replaces your vote rather than adding another
+        assert_eq!(vote(&setup, &wager, &users[0], &options[0]).await.unwrap(), None);
+        let resolution = vote(&setup, &wager, &users[1], &options[0])
+            .await
+            .unwrap()
+            .expect("two votes of three that agree should settle the wager");
+        assert_eq!(resolution.winners, vec![common::Winner { option_id: options[0].id.to_string(), weight: None }]);
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Resolved);
+        assert_eq!(fetch_balance(&setup, "first").await, 1200);
+        vote(&setup, &wager, &users[2], &options[2]).await.expect_err("a resolved wager cannot be voted on");
+    }
+
+    #[tokio::test]
+    async fn test_deadlocked_vote_falls_back_to_admin() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let electorate = Electorate::Participants { user_ids: vec!["red".into(), "blue".into()] };
+        let (wager, users, options) = add_voted_wager(&mut database_connection, electorate, &["red", "blue", "spectator"]).await;
+        let mut admin = DbUser::new("admin", 0);
+        admin.admin = true;
+        let admin = database_connection.add_user(&admin).await.unwrap().unwrap().id;
+        let mut setup = start_managers(database_connection);
+
+        // the vote cannot be skipped by resolving or throwing out the wager by hand
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&options[0]), user: Some(users[0].clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("only an admin should be able to resolve a wager by hand");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::CancelWager { wager_id: wager.clone(), user: Some(users[1].clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("only an admin should be able to cancel a wager");
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ReopenWager { wager_id: wager.clone(), closes_at: None, user: Some(users[1].clone()), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect_err("only an admin should be able to reopen a wager");
+        assert_eq!(fetch_wager_info(&setup, &wager).await.status, WagerStatus::Locked);
+
+        vote(&setup, &wager, &users[2], &options[2]).await.expect_err("only the named participants can vote");
+        assert_eq!(vote(&setup, &wager, &users[0], &options[0]).await.unwrap(), None);
+        assert_eq!(vote(&setup, &wager, &users[1], &options[1]).await.unwrap(), None);
+
+        let notification = timeout(Duration::from_secs(5), setup.notifications.recv()).await.expect("admins should be told").unwrap();
+        assert_eq!(notification.notification, Notification::VoteDeadlocked { wager_id: wager.id.to_string() });
+        // spectators have no say, so they are not bothered with it
+        let recipients = HashSet::from(["red".to_string(), "blue".to_string(), "admin".to_string()]);
+        assert_eq!(notification.recipients, Some(recipients));
+        assert!(!notification.is_for("spectator"));
+        let wager_info = fetch_wager_info(&setup, &wager).await;
+        assert_eq!((wager_info.status, wager_info.vote_deadlocked), (WagerStatus::Locked, true));
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        setup.wager_requester.send(WagerRequest::ResolveWager { wager_id: wager.clone(), winners: sole_winner(&options[1]), user: Some(admin), responder: resp_tx }).await.unwrap();
+        resp_rx.await.unwrap().expect("an admin should be able to resolve a deadlocked wager");
+        assert_eq!(fetch_balance(&setup, "blue").await, 1200);
+    }
+
+    #[tokio::test]
+    async fn test_vote_runs_out() {
+        let mut database_connection = DatabaseConnection::<Db>::new().await.unwrap();
+        let (wager, users, options) = add_voted_wager(&mut database_connection, Electorate::Bettors, &["first", "second", "third"]).await;
+        let config = ServerConfig { vote_window: 60, ..ServerConfig::default() };
+        let mut manager = start_direct_manager(database_connection, config);
+
+        let before = unix_now();
+        assert_eq!(manager.vote_on_outcome(wager.clone(), users[0].clone(), options[0].clone()).await.unwrap(), None);
+        assert_eq!(manager.vote_on_outcome(wager.clone(), users[1].clone(), options[1].clone()).await.unwrap(), None);
+        // the deadline counts from the first vote, so the second one does not move it
+        let (deadline, wagers) = manager.vote_schedule.first_key_value().expect("the vote should run out");
+        assert!((before + 60..=unix_now() + 60).contains(deadline));
+        assert_eq!(wagers, &vec![wager.clone()]);
+        assert_eq!(manager.vote_schedule.len(), 1);
+
+        // the schedule is rebuilt after a restart
+        manager.vote_schedule.clear();
+        manager.load_vote_schedule().await.unwrap();
+        assert_eq!(manager.vote_schedule.values().flatten().collect::<Vec<_>>(), vec![&wager]);
+
+        // an entry that comes due early is ignored while the vote still has time left
+        manager.vote_schedule = BTreeMap::from([(0, vec![wager.clone()])]);
+        manager.deadlock_overdue_votes().await;
+        assert!(!manager.get_wager_info(&wager).await.unwrap().vote_deadlocked);
+
+        manager.config.vote_window = 0;
+        manager.vote_schedule = BTreeMap::from([(0, vec![wager.clone()])]);
+        manager.deadlock_overdue_votes().await;
+        let wager_info = manager.get_wager_info(&wager).await.unwrap();
+        assert_eq!((wager_info.status, wager_info.vote_deadlocked), (WagerStatus::Locked, true));
+        assert!(manager.vote_schedule.is_empty());
+        manager.load_vote_schedule().await.unwrap();
+        assert!(manager.vote_schedule.is_empty(), "a deadlocked wager is waiting on an admin, not its voters");
+    }
 }